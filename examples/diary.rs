@@ -6,49 +6,35 @@ use hellschreiber::*;
 use std::io::{BufRead, BufReader};
 
 #[allow(unused)]
+#[derive(Schema, FromEntity, IntoTx)]
+#[hellschreiber(namespace = "diary.entry")]
 struct DiaryEntry {
-    eid: EntityId,
+    #[hellschreiber(id)]
+    eid: Option<EntityId>,
     date: chrono::DateTime<chrono::Utc>,
     text: String
 }
 
-impl<'a> From<Entity<'a>> for DiaryEntry {
-    fn from(o: Entity<'a>) -> DiaryEntry {
-        DiaryEntry {
-            eid: o.eid,
-            date: o.get("diary.entry/date").unwrap().as_datetime().unwrap(),
-            text: o.get("diary.entry/text").unwrap().as_string().unwrap(),
-        }
-    }
-}
-
 fn main() {
     let mut db = hellschreiber::Db::open("diary.sqlite").unwrap();
 
-    if !db.has_attribute("diary.entry/text") {
-        store_schema(&mut db);
-    }
+    db.migrate(&[Migration::new("diary.entry/schema", DiaryEntry::schema())]).unwrap();
 
     let text_attribute = db.attribute("diary.entry/text").unwrap();
 
     for datom in db.datoms(Index::Aevt.a(text_attribute)).unwrap().iter() {
-        let entry: DiaryEntry = db.entity(datom.entity).unwrap().into();
+        let entry = DiaryEntry::from_entity(&db.entity(datom.entity).unwrap()).unwrap();
         println!("{:?}: {}", entry.date, entry.text);
     }
 
     for line in BufReader::new(std::io::stdin()).lines() {
-        let line = line.unwrap();
-
-        let entry = db.tempid();
-        db.transact(&[(Assert, entry, "diary.entry/date", Value::DateTime(chrono::Utc::now())),
-                      (Assert, entry, "diary.entry/text", line.into())])
-            .unwrap();
+        let entry = DiaryEntry {
+            eid: None,
+            date: chrono::Utc::now(),
+            text: line.unwrap(),
+        };
+
+        let tempid = db.tempid();
+        db.transact(entry.into_tx(tempid)).unwrap();
     }
 }
-
-fn store_schema(db: &mut hellschreiber::Db) {
-    let schema_tx = &[(Assert, db.tempid(), "db/ident", "diary.entry/text"),
-                      (Assert, db.tempid(), "db/ident", "diary.entry/date")];
-    db.transact(schema_tx)
-        .unwrap();
-}
@@ -15,8 +15,8 @@ fn main() {
     // Get temporary ID for our diary entry
     let entry_tempid = db.tempid();
     // A list of facts to be asserted.
-    let tx = &[(Assert, entry_tempid, "diary.entry/date", Value::DateTime(chrono::Utc::now())),
-               (Assert, entry_tempid, "diary.entry/text", "Hello World!".into())];
+    let tx = &[(Assert, entry_tempid.clone(), "diary.entry/date", Value::DateTime(chrono::Utc::now())),
+               (Assert, entry_tempid.clone(), "diary.entry/text", "Hello World!".into())];
     let tx_data = db.transact(tx).expect("Failed to transact diary entry");
 
     // `tx_data` maps from our tempid `entry` to the real EntityId:
@@ -0,0 +1,335 @@
+//! Derive macros for the `Schema`, `FromEntity` and `IntoTx` traits of
+//! `hellschreiber`.
+//!
+//! ```ignore
+//! #[derive(Schema, FromEntity, IntoTx)]
+//! #[hellschreiber(namespace = "diary.entry")]
+//! struct DiaryEntry {
+//!     // `None` for entities which aren't transacted yet
+//!     #[hellschreiber(id)]
+//!     eid: Option<EntityId>,
+//!     date: chrono::DateTime<chrono::Utc>,
+//!     #[hellschreiber(doc = "The text of the entry", fulltext)]
+//!     text: String,
+//!     // Cardinality many
+//!     tags: Vec<String>,
+//!     // Optional attribute with a custom name
+//!     #[hellschreiber(attribute = "diary/mood")]
+//!     mood: Option<String>,
+//! }
+//! ```
+//!
+//! Attribute idents default to `<namespace>/<field name>`. The namespace
+//! defaults to the struct name, `DiaryEntry` becomes `diary.entry`.
+
+extern crate proc_macro;
+extern crate proc_macro2;
+#[macro_use] extern crate quote;
+extern crate syn;
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use syn::{Data, DeriveInput, Fields, Lit, Meta, NestedMeta};
+
+#[proc_macro_derive(Schema, attributes(hellschreiber))]
+pub fn derive_schema(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+    let model = match Model::parse(&input) {
+        Ok(model) => model,
+        Err(e) => return e.to_compile_error().into()
+    };
+
+    let attributes = model.attributes().map(|field| {
+        let name = &field.attribute;
+        let many = if field.kind == Kind::Many {
+            quote! { ops.push(::hellschreiber::Operation::TempidAssertion(tid.clone(), "db.cardinality/many".into(), true.into())); }
+        } else {
+            quote! {}
+        };
+        let doc = match field.doc {
+            Some(ref doc) => quote! { ops.push(::hellschreiber::Operation::TempidAssertion(tid.clone(), "db/doc".into(), #doc.into())); },
+            None => quote! {}
+        };
+        let unique = if field.unique {
+            quote! { ops.push(::hellschreiber::Operation::TempidAssertion(tid.clone(), "db/unique".into(), true.into())); }
+        } else {
+            quote! {}
+        };
+        let fulltext = if field.fulltext {
+            quote! { ops.push(::hellschreiber::Operation::TempidAssertion(tid.clone(), "db/fulltext".into(), true.into())); }
+        } else {
+            quote! {}
+        };
+
+        quote! {
+            {
+                let tid = ::hellschreiber::tempid();
+                ops.push(::hellschreiber::Operation::TempidAssertion(tid.clone(), "db/ident".into(), #name.into()));
+                #many
+                #doc
+                #unique
+                #fulltext
+            }
+        }
+    });
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let expanded = quote! {
+        impl #impl_generics ::hellschreiber::Schema for #ident #ty_generics #where_clause {
+            fn schema() -> ::std::vec::Vec<::hellschreiber::Operation> {
+                let mut ops = ::std::vec::Vec::new();
+                #(#attributes)*
+                ops
+            }
+        }
+    };
+
+    expanded.into()
+}
+
+#[proc_macro_derive(FromEntity, attributes(hellschreiber))]
+pub fn derive_from_entity(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+    let model = match Model::parse(&input) {
+        Ok(model) => model,
+        Err(e) => return e.to_compile_error().into()
+    };
+
+    let fields = model.fields.iter().map(|field| {
+        let ident = &field.ident;
+        let name = &field.attribute;
+
+        let from_value = quote! {
+            ::hellschreiber::FromValue::from_value(value)
+                .ok_or_else(|| ::hellschreiber::FromEntityError::InvalidValue(#name.into(), value.clone()))
+        };
+
+        let value = match field.kind {
+            Kind::Id => quote! { entity.eid.into() },
+            Kind::One => quote! {
+                {
+                    let value = entity.get(#name)
+                        .ok_or_else(|| ::hellschreiber::FromEntityError::MissingAttribute(#name.into()))?;
+                    #from_value?
+                }
+            },
+            Kind::Optional => quote! {
+                match entity.get(#name) {
+                    Some(value) => Some(#from_value?),
+                    None => None
+                }
+            },
+            Kind::Many => quote! {
+                entity.get_many(#name)
+                    .iter()
+                    .map(|value| #from_value)
+                    .collect::<::std::result::Result<_, _>>()?
+            },
+        };
+
+        quote! { #ident: #value }
+    });
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let expanded = quote! {
+        impl #impl_generics ::hellschreiber::FromEntity for #ident #ty_generics #where_clause {
+            fn from_entity(entity: &::hellschreiber::Entity) -> ::std::result::Result<Self, ::hellschreiber::FromEntityError> {
+                Ok(#ident {
+                    #(#fields),*
+                })
+            }
+        }
+    };
+
+    expanded.into()
+}
+
+#[proc_macro_derive(IntoTx, attributes(hellschreiber))]
+pub fn derive_into_tx(input: TokenStream) -> TokenStream {
+    let input = syn::parse_macro_input!(input as DeriveInput);
+    let model = match Model::parse(&input) {
+        Ok(model) => model,
+        Err(e) => return e.to_compile_error().into()
+    };
+
+    let assertions = model.attributes().map(|field| {
+        let ident = &field.ident;
+        let name = &field.attribute;
+        let assert = |value: TokenStream2| quote! {
+            ops.push(::hellschreiber::Operation::TempidAssertion(entity.clone(), #name.into(), #value.clone().into()));
+        };
+
+        match field.kind {
+            Kind::Id => unreachable!(),
+            Kind::One => assert(quote! { self.#ident }),
+            Kind::Optional => {
+                let assert = assert(quote! { value });
+                quote! {
+                    if let Some(ref value) = self.#ident {
+                        #assert
+                    }
+                }
+            },
+            Kind::Many => {
+                let assert = assert(quote! { value });
+                quote! {
+                    for value in &self.#ident {
+                        #assert
+                    }
+                }
+            },
+        }
+    });
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let expanded = quote! {
+        impl #impl_generics ::hellschreiber::IntoTx for #ident #ty_generics #where_clause {
+            fn into_tx(&self, entity: ::hellschreiber::TempId) -> ::std::vec::Vec<::hellschreiber::Operation> {
+                let mut ops = ::std::vec::Vec::new();
+                #(#assertions)*
+                ops
+            }
+        }
+    };
+
+    expanded.into()
+}
+
+#[derive(Debug, PartialEq, Eq)]
+enum Kind {
+    /// The `EntityId` (or `Option<EntityId>`) of the entity, marked with
+    /// `#[hellschreiber(id)]`
+    Id,
+    One,
+    /// `Option<T>`
+    Optional,
+    /// `Vec<T>`, stored as `db.cardinality/many`
+    Many,
+}
+
+struct Field {
+    ident: syn::Ident,
+    attribute: String,
+    kind: Kind,
+    doc: Option<String>,
+    unique: bool,
+    fulltext: bool,
+}
+
+struct Model {
+    fields: Vec<Field>,
+}
+
+impl Model {
+    fn parse(input: &DeriveInput) -> syn::Result<Model> {
+        let mut namespace = default_namespace(&input.ident.to_string());
+        for meta in hellschreiber_metas(&input.attrs)? {
+            match meta {
+                Meta::NameValue(ref nv) if nv.path.is_ident("namespace") => namespace = lit_str(&nv.lit)?,
+                other => return Err(syn::Error::new_spanned(other, "unknown hellschreiber attribute"))
+            }
+        }
+
+        let fields = match input.data {
+            Data::Struct(ref data) => match data.fields {
+                Fields::Named(ref fields) => &fields.named,
+                _ => return Err(syn::Error::new_spanned(input, "only structs with named fields are supported"))
+            },
+            _ => return Err(syn::Error::new_spanned(input, "only structs are supported"))
+        };
+
+        let fields = fields.iter()
+            .map(|field| Field::parse(field, &namespace))
+            .collect::<syn::Result<Vec<Field>>>()?;
+
+        Ok(Model { fields })
+    }
+
+    /// All fields stored as attributes, i.e. without the id field.
+    fn attributes(&self) -> impl Iterator<Item=&Field> {
+        self.fields.iter().filter(|f| f.kind != Kind::Id)
+    }
+}
+
+impl Field {
+    fn parse(field: &syn::Field, namespace: &str) -> syn::Result<Field> {
+        let ident = field.ident.clone().unwrap();
+
+        let kind = match type_name(&field.ty).as_ref().map(|s| &s[..]) {
+            Some("Option") => Kind::Optional,
+            Some("Vec")    => Kind::Many,
+            _              => Kind::One,
+        };
+
+        let mut parsed = Field {
+            attribute: format!("{}/{}", namespace, ident),
+            ident,
+            kind,
+            doc: None,
+            unique: false,
+            fulltext: false,
+        };
+
+        for meta in hellschreiber_metas(&field.attrs)? {
+            match meta {
+                Meta::Path(ref path) if path.is_ident("id")       => parsed.kind = Kind::Id,
+                Meta::Path(ref path) if path.is_ident("unique")   => parsed.unique = true,
+                Meta::Path(ref path) if path.is_ident("fulltext") => parsed.fulltext = true,
+                Meta::NameValue(ref nv) if nv.path.is_ident("attribute") => parsed.attribute = lit_str(&nv.lit)?,
+                Meta::NameValue(ref nv) if nv.path.is_ident("doc")       => parsed.doc = Some(lit_str(&nv.lit)?),
+                other => return Err(syn::Error::new_spanned(other, "unknown hellschreiber attribute"))
+            }
+        }
+
+        Ok(parsed)
+    }
+}
+
+fn hellschreiber_metas(attrs: &[syn::Attribute]) -> syn::Result<Vec<Meta>> {
+    let mut metas = vec![];
+    for attr in attrs.iter().filter(|a| a.path.is_ident("hellschreiber")) {
+        match attr.parse_meta()? {
+            Meta::List(list) => {
+                for nested in list.nested {
+                    match nested {
+                        NestedMeta::Meta(meta) => metas.push(meta),
+                        NestedMeta::Lit(lit) => return Err(syn::Error::new_spanned(lit, "expected attribute option"))
+                    }
+                }
+            },
+            other => return Err(syn::Error::new_spanned(other, "expected #[hellschreiber(...)]"))
+        }
+    }
+    Ok(metas)
+}
+
+fn lit_str(lit: &Lit) -> syn::Result<String> {
+    match lit {
+        Lit::Str(s) => Ok(s.value()),
+        other => Err(syn::Error::new_spanned(other, "expected string literal"))
+    }
+}
+
+/// Name of the outermost type of a field, e.g. `Option` for
+/// `Option<String>`.
+fn type_name(ty: &syn::Type) -> Option<String> {
+    match ty {
+        syn::Type::Path(path) => path.path.segments.last().map(|s| s.ident.to_string()),
+        _ => None
+    }
+}
+
+/// `DiaryEntry` -> `diary.entry`
+fn default_namespace(struct_name: &str) -> String {
+    let mut namespace = String::new();
+    for (i, c) in struct_name.chars().enumerate() {
+        if c.is_uppercase() && i > 0 {
+            namespace.push('.');
+        }
+        namespace.extend(c.to_lowercase());
+    }
+    namespace
+}
@@ -0,0 +1,95 @@
+extern crate chrono;
+extern crate hellschreiber;
+
+use hellschreiber::*;
+
+#[derive(Debug, PartialEq, Schema, FromEntity, IntoTx)]
+#[hellschreiber(namespace = "diary.entry")]
+struct DiaryEntry {
+    #[hellschreiber(id)]
+    eid: Option<EntityId>,
+    date: chrono::DateTime<chrono::Utc>,
+    #[hellschreiber(doc = "The text of the entry", fulltext)]
+    text: String,
+    tags: Vec<String>,
+    #[hellschreiber(attribute = "diary/mood")]
+    mood: Option<String>,
+}
+
+#[derive(Debug, PartialEq, Schema, FromEntity, IntoTx)]
+struct PersonInfo {
+    #[hellschreiber(unique)]
+    email: String,
+    age: Option<i64>,
+}
+
+fn db() -> Db {
+    let mut db = Db::new().unwrap();
+    db.transact(DiaryEntry::schema()).unwrap();
+    db.transact(PersonInfo::schema()).unwrap();
+    db
+}
+
+#[test]
+fn test_schema() {
+    let db = db();
+
+    assert!(db.has_attribute("diary.entry/date"));
+    assert!(db.has_attribute("diary.entry/text"));
+    assert!(db.has_attribute("diary.entry/tags"));
+    assert!(db.has_attribute("diary/mood"));
+    assert!(!db.has_attribute("diary.entry/eid"));
+
+    let text = db.attribute_info("diary.entry/text").unwrap();
+    assert_eq!(text.doc, Some("The text of the entry".into()));
+    assert!(text.fulltext);
+    assert!(db.attribute_info("diary.entry/tags").unwrap().cardinality_many);
+    assert!(!db.attribute_info("diary.entry/date").unwrap().cardinality_many);
+
+    // The namespace defaults to the struct name
+    assert!(db.attribute_info("person.info/email").unwrap().unique);
+    assert!(db.has_attribute("person.info/age"));
+}
+
+#[test]
+fn test_roundtrip() {
+    let mut db = db();
+
+    let entry = DiaryEntry {
+        eid: None,
+        date: chrono::Utc::now(),
+        text: "Hello World!".into(),
+        tags: vec!["hello".into(), "world".into()],
+        mood: None,
+    };
+
+    let tid = db.tempid();
+    let eid = db.transact(entry.into_tx(tid.clone())).unwrap().tempid_mappings[&tid];
+
+    let stored = DiaryEntry::from_entity(&db.entity(eid).unwrap()).unwrap();
+    assert_eq!(stored, DiaryEntry { eid: Some(eid), ..entry });
+}
+
+#[test]
+fn test_missing_attribute() {
+    let mut db = db();
+
+    let tid = db.tempid();
+    let eid = db.transact(&[(Assert, tid.clone(), "person.info/age", Value::Int(42))]).unwrap()
+        .tempid_mappings[&tid];
+
+    let error = PersonInfo::from_entity(&db.entity(eid).unwrap()).unwrap_err();
+    assert_eq!(error, FromEntityError::MissingAttribute("person.info/email".into()));
+}
+
+#[test]
+fn test_invalid_value() {
+    let mut db = db();
+
+    let tid = db.tempid();
+    let eid = db.transact(&[(Assert, tid.clone(), "person.info/email", Value::Int(42))]).unwrap()
+        .tempid_mappings[&tid];
+
+    let error = PersonInfo::from_entity(&db.entity(eid).unwrap()).unwrap_err();
+    assert_eq!(error, FromEntityError::InvalidValue("person.info/email".into(), Value::Int(42)));
+}
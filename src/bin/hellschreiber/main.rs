@@ -0,0 +1,248 @@
+extern crate failure;
+extern crate hellschreiber;
+#[macro_use] extern crate serde_json;
+#[cfg(feature = "repl")]
+extern crate rustyline;
+
+use hellschreiber::*;
+use hellschreiber::edn::{self, Edn};
+
+use std::collections::BTreeMap;
+use std::env;
+use std::fs::File;
+use std::io::{self, Read, Write};
+use std::path::Path;
+use std::process;
+
+#[cfg(feature = "repl")]
+mod repl;
+
+type Result<T> = std::result::Result<T, failure::Error>;
+
+const USAGE: &str = "Usage: hellschreiber <db> <command> [args]
+
+Commands:
+  schema                 List all attributes
+  entity <eid>           Print an entity as EDN
+  datoms <index> [e a v t]
+                         Print the datoms of an index (eavt, aevt, avet or vaet),
+                         optionally filtered. Use _ to skip a component,
+                         values are EDN.
+  tx-log                 Print all transactions with their datoms
+  transact <file>        Transact EDN (*.edn) or JSON entity maps
+  export [file]          Write the transaction log as JSON lines
+  import [file]          Replay an exported transaction log into an empty db
+  excise <eid> [attr...] Permanently remove an entity, or only the given
+                         attributes of it, including its history
+  repl                   Start an interactive shell
+  serve [addr]           Serve the database over HTTP (default 127.0.0.1:8080)
+
+<db> is a SQLite file or a directory of segment files. Only import
+creates it if it doesn't exist.
+Files default to stdin/stdout if omitted or -.";
+
+fn main() {
+    let args = env::args().skip(1).collect::<Vec<_>>();
+
+    if args.len() < 2 {
+        eprintln!("{}", USAGE);
+        process::exit(2);
+    }
+
+    if let Err(e) = run(&args[0], &args[1], &args[2..]) {
+        eprintln!("Error: {}", e);
+        process::exit(1);
+    }
+}
+
+fn run(path: &str, command: &str, args: &[String]) -> Result<()> {
+    // Opening creates missing databases, which is only wanted for import
+    if command != "import" && !Path::new(path).exists() {
+        return Err(failure::err_msg(format!("No database at {}", path)));
+    }
+
+    let mut db = if Path::new(path).is_dir() {
+        Db::with_storage(SegmentStorage::open(path)?)?
+    } else {
+        Db::open(path)?
+    };
+
+    match command {
+        "schema"   => schema(&db),
+        "entity"   => entity(&db, args),
+        "datoms"   => datoms(&db, args),
+        "tx-log"   => tx_log(&db),
+        "transact" => transact(&mut db, args),
+        "export"   => db.export(output(args.first())?).map_err(Into::into),
+        "import"   => db.import(input(args.first())?).map_err(Into::into),
+        "excise"   => excise(&mut db, args),
+        #[cfg(feature = "repl")]
+        "repl"     => repl::run(&mut db),
+        #[cfg(feature = "server")]
+        "serve"    => serve(db, args),
+        _ => Err(failure::err_msg(format!("Unknown command {}\n\n{}", command, USAGE)))
+    }
+}
+
+pub(crate) fn schema(db: &Db) -> Result<()> {
+    let ident = db.attribute("db/ident").expect("db/ident is always present");
+
+    for datom in db.datoms(Index::Aevt.a(ident))? {
+        let name = match datom.value.as_str() {
+            Some(name) => name.to_string(),
+            None => continue
+        };
+        let info = db.attribute_info(&name)?;
+
+        let mut flags = vec![if info.cardinality_many { "many" } else { "one" }];
+        if info.unique { flags.push("unique"); }
+        if info.fulltext { flags.push("fulltext"); }
+        if info.tuple_attrs.is_some() { flags.push("tuple"); }
+
+        print!("{:<8} {:<32} {}", i64::from(datom.entity), name, flags.join(","));
+        if let Some(doc) = info.doc {
+            print!("  {}", doc);
+        }
+        println!();
+    }
+
+    Ok(())
+}
+
+pub(crate) fn entity(db: &Db, args: &[String]) -> Result<()> {
+    let eid = match args.first() {
+        Some(eid) => parse_eid(eid)?,
+        None => return Err(failure::err_msg("Missing entity id"))
+    };
+
+    println!("{}", Edn::from_entity(&db.entity(eid)?));
+    Ok(())
+}
+
+pub(crate) fn datoms(db: &Db, args: &[String]) -> Result<()> {
+    let index = match args.first().map(|s| &s[..]) {
+        Some("eavt") => Index::Eavt,
+        Some("aevt") => Index::Aevt,
+        Some("avet") => Index::Avet,
+        Some("vaet") => Index::Vaet,
+        other => return Err(failure::err_msg(format!("Invalid index {:?}. Expected eavt, aevt, avet or vaet", other)))
+    };
+
+    let component = |i: usize| args.get(i).filter(|arg| *arg != "_");
+    let mut filtered = FilteredIndex::new(index);
+
+    if let Some(e) = component(1) {
+        filtered = filtered.e(parse_eid(e)?);
+    }
+    if let Some(a) = component(2) {
+        let name = a.trim_start_matches(':');
+        let attribute = db.attribute(name)
+            .ok_or_else(|| failure::err_msg(format!("Unknown attribute {}", name)))?;
+        filtered = filtered.a(attribute);
+    }
+    if let Some(v) = component(3) {
+        let forms = edn::parse(v)?;
+        let form = forms.first()
+            .ok_or_else(|| failure::err_msg("Missing value"))?;
+        filtered = filtered.v(edn::to_value(form)?);
+    }
+    if let Some(t) = component(4) {
+        filtered = filtered.t(parse_eid(t)?);
+    }
+
+    for datom in db.datoms(filtered)? {
+        println!("{}", Edn::from_datom(db, &datom));
+    }
+
+    Ok(())
+}
+
+pub(crate) fn tx_log(db: &Db) -> Result<()> {
+    for (_, datoms) in db.tx_log()? {
+        for datom in &datoms {
+            println!("{}", Edn::from_datom(db, datom));
+        }
+        println!();
+    }
+
+    Ok(())
+}
+
+fn transact(db: &mut Db, args: &[String]) -> Result<()> {
+    let mut data = String::new();
+    input(args.first())?.read_to_string(&mut data)?;
+
+    let is_edn = match args.first() {
+        Some(file) => file.ends_with(".edn"),
+        None => false,
+    };
+    transact_data(db, &data, is_edn)
+}
+
+/// Transacts EDN transaction data or JSON entity maps and prints the tx
+/// id and the entity ids of named tempids.
+pub(crate) fn transact_data(db: &mut Db, data: &str, is_edn: bool) -> Result<()> {
+    let (operations, tempids) = if is_edn {
+        let tx = edn::parse_tx(data)?;
+        (tx.operations, tx.tempids)
+    } else {
+        let maps = EntityMaps::from_json(serde_json::from_str(data)?)?;
+        (maps.operations, maps.tempids)
+    };
+
+    let txd = db.transact(operations)?;
+
+    let tempids = tempids.into_iter()
+        .filter_map(|(name, tempid)| txd.tempid_mappings.get(&tempid).map(|&eid| (name, i64::from(eid))))
+        .collect::<BTreeMap<_, _>>();
+    println!("{}", json!({"tx": i64::from(txd.tx_id), "tempids": tempids}));
+
+    Ok(())
+}
+
+fn excise(db: &mut Db, args: &[String]) -> Result<()> {
+    let eid = match args.first() {
+        Some(eid) => parse_eid(eid)?,
+        None => return Err(failure::err_msg("Missing entity id"))
+    };
+
+    let excisions = if args.len() > 1 {
+        args[1..].iter().map(|attribute| Excision::Attribute(eid, attribute.clone())).collect()
+    } else {
+        vec![Excision::Entity(eid)]
+    };
+
+    let txd = db.excise(&excisions)?;
+    println!("{}", json!({"tx": i64::from(txd.tx_id)}));
+
+    Ok(())
+}
+
+#[cfg(feature = "server")]
+fn serve(db: Db, args: &[String]) -> Result<()> {
+    let addr = args.first().map_or("127.0.0.1:8080", |addr| &addr[..]);
+    let server = Server::new(db, addr)?;
+    eprintln!("Listening on http://{}", server.local_addr().map_or(addr.to_string(), |a| a.to_string()));
+    server.run()?;
+    Ok(())
+}
+
+pub(crate) fn parse_eid(s: &str) -> Result<EntityId> {
+    s.parse::<i64>()
+        .map(EntityId::from)
+        .map_err(|_| failure::err_msg(format!("Invalid entity id {}", s)))
+}
+
+fn input(file: Option<&String>) -> Result<Box<dyn Read>> {
+    match file.map(|f| &f[..]) {
+        None | Some("-") => Ok(Box::new(io::stdin())),
+        Some(file) => Ok(Box::new(File::open(file)?))
+    }
+}
+
+fn output(file: Option<&String>) -> Result<Box<dyn Write>> {
+    match file.map(|f| &f[..]) {
+        None | Some("-") => Ok(Box::new(io::stdout())),
+        Some(file) => Ok(Box::new(File::create(file)?))
+    }
+}
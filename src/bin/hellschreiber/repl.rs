@@ -0,0 +1,180 @@
+use super::{datoms, entity, parse_eid, schema, transact_data, tx_log, Result};
+
+use hellschreiber::{Db, Index};
+use hellschreiber::edn;
+
+use rustyline::completion::Completer;
+use rustyline::error::ReadlineError;
+use rustyline::highlight::Highlighter;
+use rustyline::hint::Hinter;
+use rustyline::validate::Validator;
+use rustyline::{Context, Editor, Helper};
+
+use std::env;
+use std::path::PathBuf;
+
+const HELP: &str = "Commands:
+  schema                 List all attributes
+  entity <eid>           Print an entity
+  datoms <index> [e a v t]
+                         Print the datoms of an index (eavt, aevt, avet or vaet),
+                         optionally filtered. Use _ to skip a component
+  tx-log                 Print all transactions with their datoms
+  transact <edn>         Transact EDN transaction data, e.g.
+                         [[:db/add \"tempid\" :person/name \"Karl\"]]
+  as-of [<tx>|now]       Read the database as of a transaction
+  help                   Show this help
+  quit                   Leave the shell
+
+Arguments are EDN, strings have to be quoted.";
+
+const COMMANDS: &[&str] = &["schema", "entity", "datoms", "tx-log", "transact", "as-of", "help", "quit"];
+
+/// Completes command names and attribute idents
+struct ReplHelper {
+    idents: Vec<String>,
+}
+
+impl ReplHelper {
+    fn refresh(&mut self, db: &Db) {
+        let ident = db.attribute("db/ident").expect("db/ident is always present");
+        self.idents = db.datoms(Index::Aevt.a(ident))
+            .map(|datoms| datoms.into_iter().filter_map(|d| d.value.as_string()).collect())
+            .unwrap_or_default();
+    }
+}
+
+impl Completer for ReplHelper {
+    type Candidate = String;
+
+    fn complete(&self, line: &str, pos: usize, _ctx: &Context) -> rustyline::Result<(usize, Vec<String>)> {
+        let start = line[..pos].rfind(char::is_whitespace).map_or(0, |i| i + 1);
+        let word = &line[start..pos];
+
+        let candidates = if start == 0 {
+            COMMANDS.iter()
+                .filter(|c| c.starts_with(word))
+                .map(|c| c.to_string())
+                .collect()
+        } else {
+            let colon = if word.starts_with(':') { ":" } else { "" };
+            self.idents.iter()
+                .filter(|ident| ident.starts_with(word.trim_start_matches(':')))
+                .map(|ident| format!("{}{}", colon, ident))
+                .collect()
+        };
+
+        Ok((start, candidates))
+    }
+}
+
+impl Hinter for ReplHelper {
+    type Hint = String;
+}
+
+impl Highlighter for ReplHelper {}
+impl Validator for ReplHelper {}
+impl Helper for ReplHelper {}
+
+fn history_file() -> Option<PathBuf> {
+    env::var_os("HOME").map(|home| PathBuf::from(home).join(".hellschreiber_history"))
+}
+
+pub fn run(db: &mut Db) -> Result<()> {
+    let mut helper = ReplHelper { idents: vec![] };
+    helper.refresh(db);
+
+    let mut editor = Editor::<ReplHelper>::new();
+    editor.set_helper(Some(helper));
+
+    let history = history_file();
+    if let Some(ref history) = history {
+        // There's no history on the first start
+        let _ = editor.load_history(history);
+    }
+
+    loop {
+        let prompt = match db.as_of() {
+            Some(tx) => format!("hellschreiber@{}> ", i64::from(tx)),
+            None => "hellschreiber> ".to_string(),
+        };
+
+        let line = match editor.readline(&prompt) {
+            Ok(line) => line,
+            Err(ReadlineError::Interrupted) => continue,
+            Err(ReadlineError::Eof) => break,
+            Err(e) => return Err(e.into()),
+        };
+
+        let line = line.trim();
+        if line.is_empty() {
+            continue;
+        }
+        editor.add_history_entry(line);
+
+        let (command, rest) = match line.find(char::is_whitespace) {
+            Some(i) => (&line[..i], line[i..].trim()),
+            None => (line, ""),
+        };
+
+        if command == "quit" || command == "exit" {
+            break;
+        }
+
+        if let Err(e) = eval(db, command, rest) {
+            eprintln!("Error: {}", e);
+        }
+
+        if let Some(helper) = editor.helper_mut() {
+            helper.refresh(db);
+        }
+    }
+
+    if let Some(ref history) = history {
+        editor.save_history(history)?;
+    }
+
+    Ok(())
+}
+
+fn eval(db: &mut Db, command: &str, rest: &str) -> Result<()> {
+    // `transact` takes its argument as a whole, all other commands a
+    // list of EDN forms
+    if command == "transact" {
+        return transact_data(db, rest, true);
+    }
+
+    let args = edn::parse(rest)?.iter()
+        .map(|form| form.to_string())
+        .collect::<Vec<_>>();
+
+    match command {
+        "schema" => schema(db),
+        "entity" => match args.first() {
+            Some(eid) => {
+                println!("{:#?}", db.entity(parse_eid(eid)?)?);
+                Ok(())
+            },
+            None => entity(db, &args),
+        },
+        "datoms" => datoms(db, &args),
+        "tx-log" => tx_log(db),
+        "as-of" => {
+            match args.first().map(|s| &s[..]) {
+                None => (),
+                Some("now") => db.set_as_of(None),
+                Some(tx) => db.set_as_of(Some(parse_eid(tx)?)),
+            }
+            match db.as_of() {
+                Some(tx) => println!("As of transaction {}", i64::from(tx)),
+                None => println!("As of now"),
+            }
+            Ok(())
+        },
+        "help" => {
+            println!("{}", HELP);
+            Ok(())
+        },
+        _ => Err(failure::err_msg(format!("Unknown command {}. Try help", command)))
+    }
+}
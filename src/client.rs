@@ -0,0 +1,208 @@
+extern crate ureq;
+
+use super::{resolve_enum_values, Attribute, AttributeInfo, AttributeName, Database, Datom, Datoms, Entity, EntityId,
+            FilteredIndex, Index, Operation, Status, TransactionData, Value};
+use error::Error;
+use db::attribute_info_from_datoms;
+
+use serde_json::{self, Value as Json};
+use std::cell::RefCell;
+use std::collections::BTreeMap;
+
+/// `Database` backed by a `Server` over HTTP.
+///
+/// Attribute idents are cached and reloaded after each transaction and
+/// whenever an unknown ident is requested.
+pub struct Client {
+    url: String,
+    agent: ureq::Agent,
+    idents: RefCell<BTreeMap<AttributeName, Attribute>>,
+}
+
+impl Client {
+    /// Creates a client for the server at `url`, e.g. `http://localhost:8080`.
+    pub fn new<S: Into<String>>(url: S) -> Self {
+        Client {
+            url: url.into().trim_end_matches('/').to_string(),
+            agent: ureq::AgentBuilder::new().build(),
+            idents: RefCell::new(BTreeMap::new()),
+        }
+    }
+
+    fn request(&self, method: &str, path: &str, body: Option<Json>) -> Result<Json, Error> {
+        let request = self.agent.request(method, &format!("{}{}", self.url, path));
+        let response = match body {
+            Some(body) => request.send_json(body),
+            None => request.call(),
+        };
+
+        match response {
+            Ok(response) => response.into_json().map_err(|e| Error::Remote(e.to_string())),
+            Err(ureq::Error::Status(status, response)) => {
+                let message = response.into_json::<Json>().ok()
+                    .and_then(|json| json["error"].as_str().map(str::to_string))
+                    .unwrap_or_else(|| format!("HTTP status {}", status));
+                Err(Error::Remote(message))
+            },
+            Err(e) => Err(Error::Remote(e.to_string())),
+        }
+    }
+
+    fn reload_idents(&self) -> Result<(), Error> {
+        let datoms = self.request("POST", "/datoms", Some(json!({"index": "aevt", "a": "db/ident"})))?;
+
+        let idents = datoms.as_array().map(|d| &d[..]).unwrap_or(&[]).iter()
+            .filter_map(|datom| {
+                let e: EntityId = serde_json::from_value(datom["e"].clone()).ok()?;
+                let v: Value = serde_json::from_value(datom["v"].clone()).ok()?;
+                Some((v.as_string()?, Attribute(e)))
+            })
+            .collect();
+
+        *self.idents.borrow_mut() = idents;
+        Ok(())
+    }
+
+    fn datom_from_json(&self, json: &Json) -> Result<Datom, Error> {
+        let invalid = || Error::Remote(format!("Invalid datom {}", json));
+        let a = json["a"].as_str().ok_or_else(invalid)?;
+
+        Ok(Datom {
+            entity:    serde_json::from_value(json["e"].clone())?,
+            attribute: self.attribute(a).ok_or_else(invalid)?,
+            value:     serde_json::from_value(json["v"].clone())?,
+            tx:        serde_json::from_value(json["tx"].clone())?,
+            status:    Status::Asserted,
+        })
+    }
+}
+
+impl Database for Client {
+    fn transact_operations(&mut self, operations: Vec<Operation>) -> Result<TransactionData, Error> {
+        let txd = self.request("POST", "/operations", Some(serde_json::to_value(operations)?))?;
+        self.reload_idents()?;
+        Ok(serde_json::from_value(txd)?)
+    }
+
+    fn datoms(&self, index: FilteredIndex) -> Result<Datoms<'_>, Error> {
+        let mut request = json!({
+            "index": match index.index {
+                Index::Eavt => "eavt",
+                Index::Aevt => "aevt",
+                Index::Avet => "avet",
+                Index::Vaet => "vaet",
+            }
+        });
+
+        if let Some(e) = index.e {
+            request["e"] = json!(e);
+        }
+        if let Some(a) = index.a {
+            request["a"] = json!(self.attribute_name(a)
+                .ok_or_else(|| Error::Remote(format!("Unknown attribute {:?}", a)))?);
+        }
+        if let Some(v) = index.v {
+            request["v"] = json!(v);
+        }
+        if let Some(t) = index.t {
+            request["t"] = json!(t);
+        }
+
+        let datoms = self.request("POST", "/datoms", Some(request))?;
+        datoms.as_array().map(|d| &d[..]).unwrap_or(&[]).iter()
+            .map(|datom| self.datom_from_json(datom))
+            .collect()
+    }
+
+    fn entity(&self, entity: EntityId) -> Result<Entity<'_>, Error> {
+        let mut values: BTreeMap<Attribute, Vec<Value>> = BTreeMap::new();
+        for datom in Database::datoms(self, Index::Eavt.e(entity))? {
+            values.entry(datom.attribute).or_default().push(datom.value);
+        }
+        resolve_enum_values(self, &mut values)?;
+
+        Ok(Entity { db: self, eid: entity, values })
+    }
+
+    fn attribute(&self, attribute_name: &str) -> Option<Attribute> {
+        if let Some(attribute) = self.idents.borrow().get(attribute_name) {
+            return Some(*attribute);
+        }
+
+        self.reload_idents().ok()?;
+        self.idents.borrow().get(attribute_name).cloned()
+    }
+
+    fn attribute_name(&self, attribute: Attribute) -> Option<AttributeName> {
+        let find = || self.idents.borrow().iter()
+            .find(|(_, a)| **a == attribute)
+            .map(|(name, _)| name.clone());
+
+        find().or_else(|| {
+            self.reload_idents().ok()?;
+            find()
+        })
+    }
+
+    fn attribute_info_for(&self, attribute: Attribute) -> Result<AttributeInfo, Error> {
+        let datoms = Database::datoms(self, Index::Eavt.e(attribute.0))?;
+        Ok(attribute_info_from_datoms(attribute, &datoms))
+    }
+}
+
+#[cfg(all(test, feature = "server"))]
+mod tests {
+    use super::*;
+    use {Assert, Db, Server};
+    use std::sync::mpsc;
+    use std::thread;
+
+    fn start() -> Client {
+        let (sender, receiver) = mpsc::channel();
+
+        thread::spawn(move || {
+            let server = Server::new(Db::new().unwrap(), "127.0.0.1:0").unwrap();
+            sender.send(server.local_addr().unwrap()).unwrap();
+            server.run().unwrap();
+        });
+
+        Client::new(format!("http://{}", receiver.recv().unwrap()))
+    }
+
+    /// Application code which doesn't care where the database lives
+    fn add_person<D: Database>(db: &mut D, name: &str) -> EntityId {
+        if db.attribute("person/name").is_none() {
+            db.transact(&[(Assert, ::tempid(), "db/ident", "person/name")]).unwrap();
+        }
+
+        let person = ::tempid();
+        db.transact(&[(Assert, person.clone(), "person/name", name)]).unwrap()
+            .tempid_mappings[&person]
+    }
+
+    #[test]
+    fn same_api_as_db() {
+        let mut db = Db::new().unwrap();
+        let mut client = start();
+
+        let local = add_person(&mut db, "Karl");
+        let remote = add_person(&mut client, "Karl");
+        assert_eq!(local, remote);
+
+        let local = Database::entity(&db, local).unwrap();
+        let remote = client.entity(remote).unwrap();
+        assert_eq!(local.values, remote.values);
+        assert_eq!(remote["person/name"], Value::from("Karl"));
+
+        let ident = client.attribute("db/ident").unwrap();
+        assert_eq!(client.attribute_name(ident), Some("db/ident".to_string()));
+        assert!(client.attribute_info_for(ident).unwrap().unique);
+    }
+
+    #[test]
+    fn remote_errors() {
+        let mut client = start();
+        let error = client.transact(&[(Assert, ::tempid(), "unknown/attribute", "x")]).unwrap_err();
+        assert!(error.to_string().contains("unknown/attribute"));
+    }
+}
@@ -0,0 +1,35 @@
+use super::{Attr, Attribute, AttributeInfo, AttributeName, Datoms, Entity, EntityId, FilteredIndex, IntoOperations, Operation, TransactionData};
+use error::Error;
+
+/// Reading and writing of a database, independent of where it's stored.
+/// Implemented by the embedded SQLite `Db` and by the HTTP `Client`, so
+/// code written against `Database` works with both.
+///
+/// `Entity` holds a `&dyn Database`, so all methods but `transact` work
+/// on trait objects.
+pub trait Database {
+    fn transact_operations(&mut self, operations: Vec<Operation>) -> Result<TransactionData, Error>;
+
+    fn datoms(&self, index: FilteredIndex) -> Result<Datoms<'_>, Error>;
+
+    fn entity(&self, entity: EntityId) -> Result<Entity<'_>, Error>;
+
+    fn attribute(&self, attribute_name: &str) -> Option<Attribute>;
+
+    fn attribute_name(&self, attribute: Attribute) -> Option<AttributeName>;
+
+    fn attribute_info_for(&self, attribute: Attribute) -> Result<AttributeInfo, Error>;
+
+    fn transact<T: IntoOperations>(&mut self, tx: T) -> Result<TransactionData, Error>
+        where Self: Sized
+    {
+        self.transact_operations(tx.into_operations()?)
+    }
+
+    /// See `Db::attr`
+    fn attr<T>(&self, attribute_name: &str) -> Result<Attr<T>, Error>
+        where Self: Sized
+    {
+        Attr::resolve(self, attribute_name)
+    }
+}
@@ -0,0 +1,1056 @@
+use super::*;
+use storage::{Storage, SqliteStorage, INDEXED_ATTRIBUTES};
+use schema_cache::SchemaCache;
+use predicates::Predicates;
+
+use std::cell::{RefCell, RefMut};
+use std::cmp;
+use std::io::{self, BufRead};
+use std::path::Path;
+use std::collections::{HashSet, HashMap};
+use std::collections::hash_map::Entry;
+
+#[derive(Debug)]
+pub struct Db {
+    storage: Box<dyn Storage>,
+    /// Only datoms up to this transaction are visible, see `Db::as_of`
+    basis: Option<TxId>,
+    /// Loaded on first use and dropped when a transaction changes the schema
+    schema: RefCell<Option<SchemaCache>>,
+    /// Entity ids handed out by `allocate_eid`
+    allocated: HashMap<Partition, EntityId>,
+    /// Number of tempids allocated by `tempid` and `tempid_in`
+    tempids: u64,
+    pub(crate) predicates: Predicates,
+}
+
+impl Db {
+    /// Opens an in-memory SQLite database
+    pub fn new() -> Result<Self, Error> {
+        Self::with_storage(SqliteStorage::in_memory()?)
+    }
+
+    /// Opens the SQLite database file at `path`, creating it if it doesn't
+    /// exist
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Self::with_storage(SqliteStorage::open(path)?)
+    }
+
+    pub fn with_storage<S: Storage + 'static>(storage: S) -> Result<Self, Error> {
+        let mut db = Db { storage: Box::new(storage), basis: None, schema: RefCell::new(None), allocated: HashMap::new(), tempids: 0, predicates: Predicates::default() };
+        db.initialize()?;
+        Ok(db)
+    }
+
+    fn initialize(&mut self) -> Result<(), Error> {
+        // Databases created by older versions might lack some of the
+        // internal attributes
+        let missing_seed_datoms = seed_datoms().into_iter()
+            .filter(|d| self.cached_attribute_name(Attribute(d.entity)).is_none())
+            .collect::<Vec<_>>();
+        if !missing_seed_datoms.is_empty() {
+            self.store_datoms(&missing_seed_datoms)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Db {
+    #[cfg(test)]
+    pub(crate) fn all_datoms<'a>(&'a self) -> Datoms<'a> {
+        let mut datoms = vec![];
+        self.storage.scan_log(&mut |datom| {
+            datoms.push(datom);
+            Ok(())
+        }).unwrap();
+
+        // TODO: Move to helper
+        datoms.sort_by_key(|d| (d.tx, d.attribute, d.status));
+
+        datoms
+    }
+
+    pub(crate) fn highest_eid(&self, partition: Partition) -> Result<EntityId, Error> {
+        let highest = self.storage.highest_eid(partition)?;
+        Ok(match self.allocated.get(&partition) {
+            Some(allocated) => cmp::max(highest, *allocated),
+            None => highest,
+        })
+    }
+
+    /// Allocates a new entity id in `partition`, e.g. to transact an
+    /// entity in a `Partition::Custom`. The id is reserved until the `Db`
+    /// is dropped, after that only if a datom of the entity was transacted.
+    /// Fails with `TransactionError::PartitionFull` if the partition has no
+    /// ids left.
+    pub fn allocate_eid(&mut self, partition: Partition) -> Result<EntityId, Error> {
+        let eid = EntityId(self.highest_eid(partition)?.0 + 1);
+        if !partition.contains(eid) {
+            return Err(TransactionError::PartitionFull(partition).into());
+        }
+        self.allocated.insert(partition, eid);
+        Ok(eid)
+    }
+
+    pub fn datoms<I: Into<FilteredIndex>>(&self, index: I) -> Result<Datoms, Error> {
+        self.storage.datoms(&index.into(), self.basis)
+    }
+
+    pub(crate) fn store_datoms(&mut self, datoms: &[Datom]) -> Result<(), Error> {
+        if SchemaCache::is_affected_by(datoms) {
+            *self.schema.get_mut() = None;
+        }
+        self.storage.store_datoms(datoms)
+    }
+
+    pub(crate) fn excise_datoms(&mut self, targets: &[(EntityId, Option<Attribute>)], record: &[Datom]) -> Result<(), Error> {
+        *self.schema.get_mut() = None;
+        self.storage.excise(targets, record)
+    }
+
+    pub(crate) fn begin_bulk(&mut self) -> Result<(), Error> {
+        self.storage.begin_bulk()
+    }
+
+    pub(crate) fn end_bulk(&mut self) -> Result<(), Error> {
+        self.storage.end_bulk()
+    }
+
+    pub(crate) fn abort_bulk(&mut self) -> Result<(), Error> {
+        *self.schema.get_mut() = None;
+        self.storage.abort_bulk()
+    }
+
+    /// The schema cache, loaded on first use. Doesn't check whether
+    /// another process changed the schema, see `refresh_schema`.
+    fn schema(&self) -> Result<RefMut<'_, SchemaCache>, Error> {
+        let mut schema = self.schema.borrow_mut();
+        if schema.is_none() {
+            *schema = Some(SchemaCache::load(&*self.storage)?);
+        }
+
+        Ok(RefMut::map(schema, |schema| schema.as_mut().unwrap()))
+    }
+
+    /// Drops the schema cache if another process wrote to the storage
+    /// since it was loaded. Public reads and `transact` call this once,
+    /// the lookups within them use the cache as is.
+    pub(crate) fn refresh_schema(&self) -> Result<(), Error> {
+        let stale = match *self.schema.borrow() {
+            Some(ref cache) => cache.is_stale(&*self.storage)?,
+            None => false,
+        };
+        if stale {
+            *self.schema.borrow_mut() = None;
+        }
+
+        Ok(())
+    }
+
+    pub fn entity(&self, entity: EntityId) -> Result<Entity, Error> {
+        self.refresh_schema()?;
+        let mut values = self.entity_values(entity)?;
+        resolve_enum_values(self, &mut values)?;
+
+        Ok(Entity { db: self, eid: entity, values })
+    }
+
+    /// The current values of `entity`, with enum values as refs
+    pub(crate) fn entity_values(&self, entity: EntityId) -> Result<BTreeMap<Attribute, Vec<Value>>, Error> {
+        let datoms = self.datoms(Index::Eavt.e(entity))?;
+        let mut attrs: BTreeMap<Attribute, BTreeSet<&Datom>> = BTreeMap::new();
+
+        for datoms in &datoms {
+            let entry = attrs.entry(datoms.attribute)
+                .or_insert_with(BTreeSet::new);
+
+            match datoms.status {
+                Status::Asserted => {
+                    entry.insert(&datoms);
+                },
+                Status::Retracted(_) if entry.contains(&datoms) => {
+                    entry.remove(&datoms);
+                },
+                Status::Retracted(_) => {
+                    panic!("Got retraction for non-existing value. Retraction: {:?}", datoms)
+                }
+            }
+        }
+
+        // Assert all datoms are of the same entity
+        assert!(attrs.values().flat_map(|x| x).all(|datoms| datoms.entity == entity));
+
+        let values = attrs.into_iter()
+            .map(|(a, ds)| {
+                let datoms: Vec<_> = ds.into_iter()
+                    .map(|datoms| datoms.value.clone())
+                    .collect();
+                (a, datoms)
+            }).collect::<BTreeMap<Attribute, Vec<Value>>>();
+
+        Ok(values)
+    }
+}
+
+impl Db {
+    /// Sets the basis of all reads to the database as it was after
+    /// transaction `tx`. `None` goes back to the current state.
+    /// Transacting is not possible while a basis is set.
+    pub fn set_as_of(&mut self, tx: Option<TxId>) {
+        self.basis = tx;
+    }
+
+    pub fn as_of(&self) -> Option<TxId> {
+        self.basis
+    }
+
+    /// A new tempid which is distinct from all other tempids allocated by
+    /// this `Db` and from tempids created by `tempid()`, `TempId::Id` or
+    /// `TempId::named`
+    pub fn tempid(&mut self) -> TempId {
+        self.allocate_tempid(None)
+    }
+
+    /// A tempid which is allocated in `partition` when it's transacted.
+    /// Custom partitions must be declared first, see `Db::create_partition`.
+    pub fn tempid_in(&mut self, partition: Partition) -> TempId {
+        self.allocate_tempid(Some(partition))
+    }
+
+    fn allocate_tempid(&mut self, partition: Option<Partition>) -> TempId {
+        self.tempids += 1;
+        TempId::Allocated(self.tempids, partition)
+    }
+
+    pub fn transact<T: IntoOperations>(&mut self, tx: T) -> Result<TransactionData, Error> {
+        let (datoms, data) = self.transaction_datoms(tx)?;
+        self.store_datoms(&datoms)?;
+        Ok(data)
+    }
+
+    /// The datoms `transact` stores for `tx`, after checking them
+    pub(crate) fn transaction_datoms<T: IntoOperations>(&mut self, tx: T) -> Result<(Datoms<'static>, TransactionData), Error> {
+        if let Some(basis) = self.basis {
+            return Err(TransactionError::AsOfBasis(basis.0).into());
+        }
+        self.refresh_schema()?;
+
+        let tx_eid = EntityId(self.highest_eid(Partition::Tx)?.0 + 1);
+
+        let now = chrono::Utc::now();
+
+        let mut datoms = vec![Datom {
+            entity:    tx_eid,
+            attribute: attr::tx_instant,
+            value:     Value::DateTime(now),
+            tx:        tx_eid,
+            status:    Status::Asserted
+        }];
+
+        let tx = tx.into_operations()?.into_iter()
+            .map(|operation| self.resolve_attribute_id(operation).and_then(|operation| self.resolve_lookup_refs(operation)))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        datoms.reserve(tx.len());
+
+        let attribute_ids = {
+            let deduped_attribute_names = tx.iter()
+            .map(Operation::attribute_name)
+                .collect::<HashSet<_>>();
+
+            deduped_attribute_names.into_iter()
+                .map(|attribute_name| {
+                    if let Some(attribute) = self.cached_attribute(&attribute_name) {
+                        Ok((attribute_name.into(), attribute))
+                    } else {
+                        Err(TransactionError::UnknownAttribute(attribute_name.to_string()))
+                    }
+                })
+                .collect::<Result<HashMap<AttributeName, Attribute>, _>>()?
+        };
+        
+        let eids = {
+            let mut eids = BTreeMap::new();
+            eids.insert(TempId::Tx, tx_eid);
+
+            // Tempids in the order they're allocated, with their partition
+            let mut partitions = vec![];
+            let mut seen = BTreeSet::new();
+            seen.insert(TempId::Tx);
+
+            for operation in &tx {
+                let (tempid, attribute_name) = match operation {
+                    Operation::TempidAssertion(tempid, attribute_name, _)    => (tempid, attribute_name),
+                    Operation::TempidRefAssertion(tempid, attribute_name, _) => (tempid, attribute_name),
+                    _ => continue
+                };
+
+                if seen.insert(tempid.clone()) {
+                    // If we're asserting an internal attribute (db/id,
+                    // db/ident, db/doc, db.cardinality/many) we use the Db
+                    // partition
+                    let default = if attribute_ids[attribute_name].is_internal() { Partition::Db } else { Partition::User };
+                    partitions.push((tempid.clone(), tempid.partition().unwrap_or(default)));
+                }
+            }
+
+            // Tempids which are only used as the value of a ref
+            for operation in &tx {
+                let tempid = match operation {
+                    Operation::RefAssertion(_, _, tempid) | Operation::TempidRefAssertion(_, _, tempid) => tempid,
+                    _ => continue
+                };
+
+                if seen.insert(tempid.clone()) {
+                    partitions.push((tempid.clone(), tempid.partition().unwrap_or(Partition::User)));
+                }
+            }
+
+            let mut highest = HashMap::new();
+            for (tempid, partition) in partitions {
+                let eid = match highest.entry(partition) {
+                    Entry::Occupied(entry) => entry.into_mut(),
+                    Entry::Vacant(entry) => {
+                        if let Partition::Custom(n) = partition {
+                            if self.cached_lookup("db/partition", i64::from(n))?.is_none() {
+                                return Err(TransactionError::UnknownPartition(n).into());
+                            }
+                        }
+                        entry.insert(self.highest_eid(partition)?)
+                    }
+                };
+                eid.0 += 1;
+                if !partition.contains(*eid) {
+                    return Err(TransactionError::PartitionFull(partition).into());
+                }
+                eids.insert(tempid, *eid);
+            }
+
+            eids
+        };
+
+        // Idents asserted in this transaction, so `db/tuple_attrs` can
+        // refer to attributes defined alongside the composite attribute
+        let pending_idents = tx.iter()
+            .filter_map(|operation| match operation {
+                Operation::Assertion(eid, a, Value::Str(ident)) if a == "db/ident"       => Some((ident.clone(), *eid)),
+                Operation::TempidAssertion(tid, a, Value::Str(ident)) if a == "db/ident" => Some((ident.clone(), eids[tid])),
+                _ => None
+            })
+            .collect::<HashMap<String, EntityId>>();
+
+        let composite_attributes = self.composite_attributes()?;
+        let new_entities = eids.values().cloned().collect::<HashSet<_>>();
+
+        // The values of existing entities assertions replace and
+        // compare-and-swaps expect, read at once
+        let current_values = {
+            let keys = tx.iter()
+                .filter_map(|operation| match operation {
+                    Operation::Assertion(e, a, _) | Operation::RefAssertion(e, a, _) | Operation::Cas(e, a, _, _)
+                        if !new_entities.contains(e) => Some((*e, attribute_ids[a])),
+                    _ => None
+                })
+                .collect::<BTreeSet<_>>();
+            self.storage.current_values(&keys.into_iter().collect::<Vec<_>>())?
+        };
+        let current_value = |e: EntityId, attribute: Attribute| {
+            current_values.get(&(e, attribute)).and_then(|values| values.first())
+        };
+        // Entity specs asserted with `db/ensure`, which aren't stored
+        let mut ensure = vec![];
+
+        for operation in tx {
+            let (e, a, mut v, status) = match operation {
+                Operation::Assertion(eid, a, v)          => (eid,        a, v,                    Status::Asserted),
+                Operation::Retraction(eid, a, v)         => (eid,        a, v,                    Status::Retracted(tx_eid)),
+                Operation::TempidAssertion(tid, a, v)    => (eids[&tid], a, v,                    Status::Asserted),
+                Operation::RefAssertion(eid, a, v)       => (eid,        a, Value::Ref(eids[&v]), Status::Asserted),
+                Operation::TempidRefAssertion(tid, a, v) => (eids[&tid], a, Value::Ref(eids[&v]), Status::Asserted),
+                Operation::Cas(eid, a, expected, v) => {
+                    let expected = match expected {
+                        Some(Value::Str(ident)) => Some(self.resolve_enum_value(attribute_ids[&a], ident, &pending_idents)?),
+                        expected => expected,
+                    };
+                    let current = current_value(eid, attribute_ids[&a]).cloned();
+                    if current != expected {
+                        return Err(TransactionError::CasFailed(a, eid.0, format!("{:?}", current)).into())
+                    }
+                    (eid, a, v, Status::Asserted)
+                },
+                Operation::LookupAssertion(..) | Operation::LookupRetraction(..) => unreachable!("Lookup refs are resolved above"),
+                Operation::ByAttribute(..) => unreachable!("Attribute ids are resolved above"),
+            };
+
+            let attribute = attribute_ids[&a];
+
+            if attribute == attr::ensure {
+                if status.is_assertion() {
+                    ensure.push((e, v));
+                }
+                continue;
+            }
+
+            // Composite tuples are derived from their components
+            if composite_attributes.contains_key(&attribute) {
+                return Err(TransactionError::CompositeTupleAttribute(a).into())
+            }
+
+            if attribute == attr::tuple_attrs {
+                v = self.resolve_tuple_attrs(v, &pending_idents)?;
+            }
+
+            if let Value::Str(ident) = v {
+                v = self.resolve_enum_value(attribute, ident, &pending_idents)?;
+            }
+
+            if attribute == attr::entity_attrs {
+                v = self.resolve_attribute_ref(v, &pending_idents)
+                    .map_err(|v| TransactionError::UnknownAttribute(format!("{:?}", v)))?;
+            }
+
+            if attribute == attr::partition && status.is_assertion() {
+                match v {
+                    Value::Int(n) if 0 <= n && n <= i64::from(u32::MAX) => (),
+                    _ => return Err(TransactionError::InvalidPartition(format!("{:?}", v)).into())
+                }
+            }
+
+            // If the operation is an assertion we have to handle the following things:
+            //
+            // - If the datom isn't db.cardinality/many we have to generate a retraction for the previous value
+            //
+            // - If the attribute of this datom is `db/ident` we're renaming
+            //   an attribute and keep the old ident as `db/alias`
+            //
+            // Entities allocated for tempids in this transaction don't
+            // have previous values
+            if status == Status::Asserted && !new_entities.contains(&e) {
+                if let Some(previous_value) = current_value(e, attribute) {
+                    if attribute == attr::ident && v != *previous_value {
+                        if seed_datoms().iter().any(|d| d.entity == e) {
+                            let old_attribute_name = previous_value.as_string().unwrap();
+                            let new_attribute_name = v.as_string().unwrap();
+                            return Err(TransactionError::ChangingIdentAttribute(old_attribute_name, new_attribute_name).into())
+                        }
+
+                        datoms.extend(self.alias_datoms(e, previous_value, &v, tx_eid)?);
+                    }
+
+                    // Handle db.cardinality/many
+                    let attribute_info = self.attribute_info_for(attribute)?;
+
+                    if !attribute_info.cardinality_many {
+                        let retraction = Datom {
+                            entity: e,
+                            attribute: attribute,
+                            value: previous_value.clone(),
+                            tx: tx_eid,
+                            status: Status::Retracted(tx_eid)
+                        };
+
+                        datoms.push(retraction);
+                    }
+                }
+            }
+
+            let datom = Datom {
+                entity: e,
+                attribute: attribute,
+                value: v.clone(),
+
+                tx: tx_eid,
+                status
+            };
+
+            datoms.push(datom);
+        }
+
+        let composite_datoms = self.composite_datoms(&composite_attributes, &datoms, tx_eid)?;
+        datoms.extend(composite_datoms);
+
+        self.check_unique(&datoms)?;
+        self.check_schema_alterations(&datoms)?;
+        self.check_predicates(&datoms, &ensure)?;
+
+        Ok((datoms, TransactionData {
+            tx_id: tx_eid,
+            tempid_mappings: eids,
+        }))
+    }
+
+    /// Replaces the attribute name of an `Operation::ByAttribute` with the
+    /// current ident of its attribute
+    fn resolve_attribute_id(&self, operation: Operation) -> Result<Operation, Error> {
+        match operation {
+            Operation::ByAttribute(attribute, operation) => {
+                let ident = self.cached_attribute_name(attribute)
+                    .ok_or_else(|| TransactionError::UnknownAttribute(operation.attribute_name().to_string()))?;
+                Ok(self.resolve_attribute_id(*operation)?.with_attribute_name(ident))
+            },
+            operation => Ok(operation),
+        }
+    }
+
+    /// Replaces the lookup ref of `operation` with the entity it resolves to
+    fn resolve_lookup_refs(&self, operation: Operation) -> Result<Operation, Error> {
+        let entity = |lookup_ref: &LookupRef| -> Result<EntityId, Error> {
+            if !self.has_attribute(&lookup_ref.attribute) {
+                return Err(TransactionError::UnknownAttribute(lookup_ref.attribute.clone()).into())
+            }
+            self.cached_lookup(&lookup_ref.attribute, lookup_ref.value.clone())?
+                .ok_or_else(|| TransactionError::LookupRefNotFound(lookup_ref.attribute.clone(), format!("{:?}", lookup_ref.value)).into())
+        };
+
+        Ok(match operation {
+            Operation::LookupAssertion(lookup_ref, a, v)  => Operation::Assertion(entity(&lookup_ref)?, a, v),
+            Operation::LookupRetraction(lookup_ref, a, v) => Operation::Retraction(entity(&lookup_ref)?, a, v),
+            operation => operation,
+        })
+    }
+
+    /// Converts the value of a `db/tuple_attrs` assertion to a
+    /// `Value::Tuple` of `Value::Ref`s. Components can be given either as
+    /// refs or as attribute idents.
+    fn resolve_tuple_attrs(&self, value: Value, pending_idents: &HashMap<String, EntityId>) -> Result<Value, Error> {
+        let components = match value {
+            Value::Tuple(components) => components,
+            _ => return Err(TransactionError::InvalidTupleAttrs(format!("{:?}", value)).into())
+        };
+
+        let components = components.into_iter()
+            .map(|component| self.resolve_attribute_ref(component, pending_idents).map_err(|component| match component {
+                Value::Str(ident) => TransactionError::UnknownAttribute(ident),
+                other => TransactionError::InvalidTupleAttrs(format!("{:?}", other))
+            }))
+            .collect::<Result<Vec<Value>, _>>()?;
+
+        Ok(Value::Tuple(components))
+    }
+
+    /// Converts an ident asserted for an enum attribute to a `Value::Ref`
+    /// of its entity. Returns `ident` as is for other attributes.
+    fn resolve_enum_value(&self, attribute: Attribute, ident: String, pending_idents: &HashMap<String, EntityId>) -> Result<Value, Error> {
+        if !self.attribute_info_for(attribute)?.enumerated {
+            return Ok(Value::Str(ident));
+        }
+
+        match self.cached_attribute(&ident).map(|a| a.0).or_else(|| pending_idents.get(&ident).cloned()) {
+            Some(eid) => Ok(Value::Ref(eid)),
+            None => Err(TransactionError::UnknownEnumValue(self.cached_attribute_name(attribute).unwrap_or_default(), ident).into())
+        }
+    }
+
+    /// Converts an attribute given as ident to a `Value::Ref`, returning
+    /// values which aren't an attribute as error
+    fn resolve_attribute_ref(&self, value: Value, pending_idents: &HashMap<String, EntityId>) -> Result<Value, Value> {
+        match value {
+            Value::Ref(eid) => Ok(Value::Ref(eid)),
+            Value::Str(ident) => {
+                match self.cached_attribute(&ident).map(|a| a.0).or_else(|| pending_idents.get(&ident).cloned()) {
+                    Some(eid) => Ok(Value::Ref(eid)),
+                    None => Err(Value::Str(ident))
+                }
+            },
+            other => Err(other)
+        }
+    }
+
+    /// Returns all composite tuple attributes mapped to their component
+    /// attributes.
+    pub(crate) fn composite_attributes(&self) -> Result<BTreeMap<Attribute, Vec<Attribute>>, Error> {
+        Ok(self.datoms(Index::Aevt.a(attr::tuple_attrs))?
+           .into_iter()
+           .map(|d| (Attribute(d.entity), tuple_components(&d.value)))
+           .collect())
+    }
+
+    /// Generates the datoms needed to keep composite tuple attributes in
+    /// sync with the component values changed by `datoms`. A composite
+    /// tuple is only asserted if all its components have a value.
+    fn composite_datoms(&self,
+                        composite_attributes: &BTreeMap<Attribute, Vec<Attribute>>,
+                        datoms: &[Datom],
+                        tx_eid: TxId) -> Result<Vec<Datom>, Error> {
+        let mut composite_datoms = vec![];
+
+        for (composite, components) in composite_attributes {
+            let entities = datoms.iter()
+                .filter(|d| components.contains(&d.attribute))
+                .map(|d| d.entity)
+                .collect::<BTreeSet<EntityId>>();
+
+            for e in entities {
+                let mut tuple = Vec::with_capacity(components.len());
+
+                for component in components {
+                    let mut values = self.datoms(Index::Eavt.e(e).a(*component))?
+                        .into_iter()
+                        .map(|d| d.value)
+                        .collect::<BTreeSet<Value>>();
+
+                    // Same order as `store_datoms`: Retractions never
+                    // affect values asserted in the same transaction
+                    let changes = datoms.iter()
+                        .filter(|d| d.entity == e && d.attribute == *component);
+                    for d in changes.clone().filter(|d| d.status.is_retraction()) {
+                        values.remove(&d.value);
+                    }
+                    for d in changes.filter(|d| d.status.is_assertion()) {
+                        values.insert(d.value.clone());
+                    }
+
+                    if values.len() == 1 {
+                        tuple.extend(values);
+                    } else {
+                        break;
+                    }
+                }
+
+                let new_value = if tuple.len() == components.len() {
+                    Some(Value::Tuple(tuple))
+                } else {
+                    None
+                };
+
+                let old_value = self.datoms(Index::Eavt.e(e).a(*composite))?
+                    .into_iter()
+                    .next()
+                    .map(|d| d.value);
+
+                if new_value == old_value {
+                    continue;
+                }
+
+                if let Some(value) = old_value {
+                    composite_datoms.push(Datom {
+                        entity: e,
+                        attribute: *composite,
+                        value,
+                        tx: tx_eid,
+                        status: Status::Retracted(tx_eid)
+                    });
+                }
+
+                if let Some(value) = new_value {
+                    composite_datoms.push(Datom {
+                        entity: e,
+                        attribute: *composite,
+                        value,
+                        tx: tx_eid,
+                        status: Status::Asserted
+                    });
+                }
+            }
+        }
+
+        Ok(composite_datoms)
+    }
+
+    /// Datoms renaming `attribute` from `old` to `new`: `old` becomes an
+    /// alias and `new` stops being one if the attribute is renamed back.
+    fn alias_datoms(&self, attribute: EntityId, old: &Value, new: &Value, tx_eid: TxId) -> Result<Vec<Datom>, Error> {
+        let aliases = self.datoms(Index::Eavt.e(attribute).a(attr::alias))?;
+        let mut datoms = vec![];
+
+        if !aliases.iter().any(|d| d.value == *old) {
+            datoms.push(Datom {
+                entity: attribute,
+                attribute: attr::alias,
+                value: old.clone(),
+                tx: tx_eid,
+                status: Status::Asserted
+            });
+        }
+
+        for alias in aliases.into_iter().filter(|d| d.value == *new) {
+            datoms.push(Datom { tx: tx_eid, status: Status::Retracted(tx_eid), ..alias });
+        }
+
+        Ok(datoms)
+    }
+
+    /// Makes sure switching an attribute in `datoms` to cardinality one or
+    /// making it unique is valid for the values already in the database.
+    fn check_schema_alterations(&self, datoms: &[Datom]) -> Result<(), Error> {
+        let asserted = |attribute: Attribute, entity: EntityId, value: bool| datoms.iter().any(|d| {
+            d.status.is_assertion() && d.attribute == attribute && d.entity == entity && d.value == Value::Bool(value)
+        });
+
+        let altered = datoms.iter()
+            .filter(|d| d.attribute == attr::cardinality_many || d.attribute == attr::unique)
+            .map(|d| d.entity)
+            .collect::<BTreeSet<_>>();
+
+        for entity in altered {
+            let attribute = Attribute(entity);
+            let info = self.attribute_info_for(attribute)?;
+            let attribute_name = || self.cached_attribute_name(attribute)
+                .unwrap_or_else(|| format!("{:?}", attribute));
+
+            let to_one = info.cardinality_many
+                && !asserted(attr::cardinality_many, entity, true)
+                && datoms.iter().any(|d| d.entity == entity && d.attribute == attr::cardinality_many);
+            if to_one {
+                let values = self.datoms(Index::Aevt.a(attribute))?;
+                if let Some(pair) = values.windows(2).find(|pair| pair[0].entity == pair[1].entity) {
+                    return Err(TransactionError::CardinalityConflict(attribute_name(), pair[0].entity.0).into())
+                }
+            }
+
+            if !info.unique && asserted(attr::unique, entity, true) {
+                let mut entities = BTreeMap::new();
+                for datom in self.datoms(Index::Aevt.a(attribute))? {
+                    if entities.insert(datom.value.clone(), datom.entity).is_some() {
+                        return Err(TransactionError::UniqueConflict(attribute_name(), format!("{:?}", datom.value)).into())
+                    }
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Makes sure no value of a unique attribute asserted in `datoms` is
+    /// held by another entity, either already in the database or in the
+    /// same transaction.
+    fn check_unique(&self, datoms: &[Datom]) -> Result<(), Error> {
+        let mut asserted: BTreeMap<(Attribute, &Value), EntityId> = BTreeMap::new();
+
+        for datom in datoms.iter().filter(|d| d.status.is_assertion()) {
+            if !self.attribute_info_for(datom.attribute)?.unique {
+                continue;
+            }
+
+            let conflict = || {
+                let attribute_name = self.cached_attribute_name(datom.attribute)
+                    .unwrap_or_else(|| format!("{:?}", datom.attribute));
+                TransactionError::UniqueConflict(attribute_name, format!("{:?}", datom.value))
+            };
+
+            match asserted.insert((datom.attribute, &datom.value), datom.entity) {
+                Some(other) if other != datom.entity => return Err(conflict().into()),
+                _ => ()
+            }
+
+            let existing = self.datoms(Index::Aevt.a(datom.attribute).v(datom.value.clone()))?;
+            for existing in existing.iter().filter(|d| d.entity != datom.entity) {
+                let retracted = datoms.iter().any(|d| {
+                    d.status.is_retraction()
+                        && d.entity == existing.entity
+                        && d.attribute == existing.attribute
+                        && d.value == existing.value
+                });
+
+                if !retracted {
+                    return Err(conflict().into())
+                }
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// Reads the `AttributeInfo` of `attribute` from the datoms of the
+/// attribute entity.
+pub(crate) fn attribute_info_from_datoms(attribute: Attribute, datoms: &[Datom]) -> AttributeInfo {
+    let mut info = AttributeInfo {
+        cardinality_many: false,
+        doc: None,
+        unique: INDEXED_ATTRIBUTES.contains(&attribute),
+        tuple_attrs: None,
+        fulltext: false,
+        preds: vec![],
+        enumerated: false,
+    };
+
+    for datom in datoms {
+        match (datom.attribute, &datom.value) {
+            // TODO: Handle both matches fo cardinality/many
+            (attr::cardinality_many, _) => info.cardinality_many = datom.value != Value::Bool(false),
+            (attr::doc, Value::Str(s))  => info.doc = Some(s.to_string()),
+            (attr::doc, value)          => panic!("Invalid value {:?} for db/doc attribute of attribute {:?}", value, attribute),
+            (attr::unique, _)           => info.unique = info.unique || datom.value == Value::Bool(true),
+            (attr::tuple_attrs, value)  => info.tuple_attrs = Some(tuple_components(value)),
+            (attr::fulltext, _)         => info.fulltext = datom.value == Value::Bool(true),
+            (attr::attr_preds, Value::Str(s)) => info.preds.push(s.to_string()),
+            (attr::enumerated, _)       => info.enumerated = datom.value == Value::Bool(true),
+            _ => ()
+        }
+    }
+
+    info
+}
+
+fn tuple_components(value: &Value) -> Vec<Attribute> {
+    value.as_tuple()
+        .unwrap_or(&[])
+        .iter()
+        .filter_map(|component| match component {
+            Value::Ref(eid) => Some(Attribute(*eid)),
+            _ => None
+        })
+        .collect()
+}
+
+/// A single transaction in the format used by `Db::export`
+#[derive(Debug, Serialize, Deserialize)]
+struct ExportedTx {
+    t: TxId,
+    /// Entity, attribute, value and whether the datom was added or
+    /// retracted in this transaction
+    datoms: Vec<(EntityId, EntityId, Value, bool)>,
+}
+
+impl Db {
+    /// Calls `f` for every transaction in order with the datoms it
+    /// asserted and retracted. Skips the internal attributes every database
+    /// starts with.
+    fn each_tx<F>(&self, mut f: F) -> Result<(), Error>
+        where F: FnMut(TxId, Datoms) -> Result<(), Error>
+    {
+        let mut current: Option<(TxId, Datoms)> = None;
+
+        self.storage.scan_log(&mut |datom| {
+            // Skip the seed datoms
+            if datom.tx.0 == 0 {
+                return Ok(());
+            }
+
+            match current {
+                Some((tx, ref mut datoms)) if tx == datom.tx => datoms.push(datom),
+                _ => {
+                    if let Some((tx, datoms)) = current.take() {
+                        f(tx, datoms)?;
+                    }
+                    current = Some((datom.tx, vec![datom]));
+                }
+            }
+
+            Ok(())
+        })?;
+
+        if let Some((tx, datoms)) = current {
+            f(tx, datoms)?;
+        }
+
+        Ok(())
+    }
+
+    /// All transactions in order, each with the datoms it asserted and
+    /// retracted.
+    pub fn tx_log(&self) -> Result<Vec<(TxId, Datoms<'_>)>, Error> {
+        let mut log = vec![];
+        self.each_tx(|tx, datoms| {
+            log.push((tx, datoms));
+            Ok(())
+        })?;
+        Ok(log)
+    }
+
+    /// Writes the transaction log to `writer` as JSON lines, one line per
+    /// transaction in order. The internal attributes, which every database
+    /// starts with, are not exported.
+    pub fn export<W: io::Write>(&self, mut writer: W) -> Result<(), Error> {
+        self.each_tx(|t, datoms| {
+            let tx = ExportedTx {
+                t,
+                datoms: datoms.into_iter()
+                    .map(|d| (d.entity, d.attribute.0, d.value, d.status.is_assertion()))
+                    .collect(),
+            };
+
+            serde_json::to_writer(&mut writer, &tx)?;
+            writer.write_all(b"\n")?;
+            Ok(())
+        })?;
+
+        writer.flush()?;
+
+        Ok(())
+    }
+
+    /// Replays a transaction log written by `Db::export`. Entity ids and
+    /// transaction ids are preserved, so this is only possible for a
+    /// database without any transactions. If the log is invalid, nothing
+    /// of it is imported.
+    pub fn import<R: io::Read>(&mut self, reader: R) -> Result<(), Error> {
+        if self.highest_eid(Partition::Tx)? != Partition::Tx.start() {
+            return Err(Error::Import("Can only import into an empty database".into()))
+        }
+
+        self.begin_bulk()?;
+        match self.import_lines(reader) {
+            Ok(()) => self.end_bulk(),
+            Err(e) => {
+                self.abort_bulk()?;
+                Err(e)
+            }
+        }
+    }
+
+    fn import_lines<R: io::Read>(&mut self, reader: R) -> Result<(), Error> {
+        for line in io::BufReader::new(reader).lines() {
+            let line = line?;
+            if line.trim().is_empty() {
+                continue;
+            }
+
+            let ExportedTx { t, datoms } = serde_json::from_str(&line)?;
+            let datoms = datoms.into_iter()
+                .map(|(e, a, v, added)| Datom {
+                    entity: e,
+                    attribute: Attribute(a),
+                    value: v,
+                    tx: t,
+                    status: if added { Status::Asserted } else { Status::Retracted(t) },
+                })
+                .collect::<Vec<_>>();
+
+            self.store_datoms(&datoms)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Database for Db {
+    fn transact_operations(&mut self, operations: Vec<Operation>) -> Result<TransactionData, Error> {
+        Db::transact(self, operations)
+    }
+
+    fn datoms(&self, index: FilteredIndex) -> Result<Datoms<'_>, Error> {
+        Db::datoms(self, index)
+    }
+
+    fn entity(&self, entity: EntityId) -> Result<Entity<'_>, Error> {
+        Db::entity(self, entity)
+    }
+
+    // Entities and typed attributes resolve attributes through the trait,
+    // after `Db::entity` or `Db::attr` refreshed the schema
+
+    fn attribute(&self, attribute_name: &str) -> Option<Attribute> {
+        Db::cached_attribute(self, attribute_name)
+    }
+
+    fn attribute_name(&self, attribute: Attribute) -> Option<AttributeName> {
+        Db::cached_attribute_name(self, attribute)
+    }
+
+    fn attribute_info_for(&self, attribute: Attribute) -> Result<AttributeInfo, Error> {
+        Db::attribute_info_for(self, attribute)
+    }
+}
+
+impl Db {
+    pub fn has_attribute(&self, attribute_name: &str) -> bool {
+        self.attribute(attribute_name).is_some()
+    }
+
+    /// Resolves an ident, or the alias of a renamed attribute, to its
+    /// attribute. An alias resolves to the attribute which used it last.
+    /// `None` if it's unknown or reading the storage fails.
+    pub fn attribute(&self, attribute_name: &str) -> Option<Attribute> {
+        self.refresh_schema().ok()?;
+        self.cached_attribute(attribute_name)
+    }
+
+    /// Like `attribute`, without checking whether another process changed
+    /// the schema
+    pub(crate) fn cached_attribute(&self, attribute_name: &str) -> Option<Attribute> {
+        if self.basis.is_none() {
+            return self.schema().ok()?.attribute(attribute_name);
+        }
+
+        let value = Value::from(attribute_name);
+
+        self.datoms(Index::Avet.a(attr::ident).v(value.clone()))
+            .ok()?
+            .iter().next()
+            .map(|d| Attribute(d.entity))
+            .or_else(|| {
+                self.datoms(Index::Aevt.a(attr::alias)).ok()?
+                    .into_iter()
+                    .filter(|d| d.value == value)
+                    .max_by_key(|d| d.tx)
+                    .map(|d| Attribute(d.entity))
+            })
+    }
+
+    /// The current ident of `attribute`, `None` if it has none or reading
+    /// the storage fails
+    pub fn attribute_name(&self, attribute: Attribute) -> Option<AttributeName> {
+        self.refresh_schema().ok()?;
+        self.cached_attribute_name(attribute)
+    }
+
+    /// Like `attribute_name`, without checking whether another process
+    /// changed the schema
+    pub(crate) fn cached_attribute_name(&self, attribute: Attribute) -> Option<AttributeName> {
+        if self.basis.is_none() {
+            return self.schema().ok()?.attribute_name(attribute);
+        }
+
+        self.datoms(Index::Avet.e(attribute.0).a(attr::ident)).ok()?
+            .into_iter()
+            .next()
+            .and_then(|d| match d.value {
+                Value::Str(s) => Some(s),
+                _ => None
+            })
+    }
+
+    pub fn attribute_info<A: AsRef<str>>(&self, attribute: A) -> Result<AttributeInfo, Error> {
+        let attribute = self.attribute(attribute.as_ref()).unwrap();
+        self.attribute_info_for(attribute)
+    }
+
+    /// The `AttributeInfo` of `attribute` from the schema cache, without
+    /// checking whether another process changed the schema
+    pub(crate) fn attribute_info_for(&self, attribute: Attribute) -> Result<AttributeInfo, Error> {
+        if self.basis.is_none() {
+            return self.schema()?.attribute_info(&*self.storage, attribute);
+        }
+
+        let attribute_datoms = self.datoms(Index::Eavt.e(attribute.0))?;
+        Ok(attribute_info_from_datoms(attribute, &attribute_datoms))
+    }
+
+    /// Searches the string values of the `db/fulltext` attribute
+    /// `attribute_name`. The `query` syntax depends on the storage, e.g.
+    /// FTS5 queries for SQLite. Results are
+    /// ordered by relevance; a higher score means a better match.
+    pub fn fulltext(&self, attribute_name: &str, query: &str) -> Result<Vec<(EntityId, Value, f64)>, Error> {
+        let attribute = match self.attribute(attribute_name) {
+            Some(attribute) => attribute,
+            None => return Ok(vec![])
+        };
+
+        self.storage.fulltext(attribute, query)
+    }
+
+    /// Resolves a lookup ref: Returns the entity which has `value` for the
+    /// unique attribute `attribute_name`. Composite tuple attributes are
+    /// looked up by passing a `Value::Tuple` of their component values.
+    pub fn lookup<V: Into<Value>>(&self, attribute_name: &str, value: V) -> Result<Option<EntityId>, Error> {
+        self.refresh_schema()?;
+        self.cached_lookup(attribute_name, value)
+    }
+
+    /// Like `lookup`, without checking whether another process changed
+    /// the schema
+    pub(crate) fn cached_lookup<V: Into<Value>>(&self, attribute_name: &str, value: V) -> Result<Option<EntityId>, Error> {
+        let attribute = match self.cached_attribute(attribute_name) {
+            Some(attribute) => attribute,
+            None => return Ok(None)
+        };
+
+        Ok(self.datoms(Index::Avet.a(attribute).v(value.into()))?
+           .into_iter()
+           .next()
+           .map(|d| d.entity))
+    }
+}
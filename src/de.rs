@@ -0,0 +1,371 @@
+use super::{Database, Entity, EntityId, Value};
+
+use serde::de::{self, DeserializeSeed, Deserializer, MapAccess, SeqAccess, Visitor};
+use std::collections::BTreeMap;
+use std::{error, fmt, vec};
+
+/// How attribute idents are mapped to struct field names when
+/// deserializing an `Entity`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldNames {
+    /// Strip the namespace, `diary.entry/text` becomes `text` and the
+    /// entity id `db/id` becomes `id`. Deserializing fails if two
+    /// attributes of the entity end up with the same name.
+    StripNamespace,
+    /// Use the full ident, fields need `#[serde(rename = "diary.entry/text")]`
+    Ident,
+}
+
+impl FieldNames {
+    fn field_name(self, ident: &str) -> String {
+        match self {
+            FieldNames::StripNamespace => ident.rsplit('/').next().unwrap().to_string(),
+            FieldNames::Ident          => ident.to_string(),
+        }
+    }
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct DeserializeError(String);
+
+impl fmt::Display for DeserializeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "Failed to deserialize entity: {}", self.0)
+    }
+}
+
+impl error::Error for DeserializeError {}
+
+impl de::Error for DeserializeError {
+    fn custom<T: fmt::Display>(msg: T) -> Self {
+        DeserializeError(msg.to_string())
+    }
+}
+
+impl<'a> Entity<'a> {
+    /// Deserializes the entity into `T`, mapping attributes to fields by
+    /// their name without namespace. Values of `db.cardinality/many`
+    /// attributes deserialize to sequences, refs either to ids or, if
+    /// a struct or map is expected, to the referenced entity.
+    ///
+    /// Attributes without value are missing from the entity, so
+    /// cardinality many fields usually need `#[serde(default)]`.
+    pub fn deserialize<T: de::DeserializeOwned>(&self) -> Result<T, DeserializeError> {
+        self.deserialize_with(FieldNames::StripNamespace)
+    }
+
+    pub fn deserialize_with<T: de::DeserializeOwned>(&self, names: FieldNames) -> Result<T, DeserializeError> {
+        T::deserialize(EntityDeserializer::new(self, names)?)
+    }
+}
+
+impl<'de, 'a, 'e> Deserializer<'de> for &'e Entity<'a> {
+    type Error = DeserializeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeserializeError> {
+        EntityDeserializer::new(self, FieldNames::StripNamespace)?.deserialize_any(visitor)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+struct EntityDeserializer<'a> {
+    db: &'a dyn Database,
+    names: FieldNames,
+    /// Field name, values and whether the attribute is cardinality many
+    fields: vec::IntoIter<(String, Vec<Value>, bool)>,
+    next_value: Option<(Vec<Value>, bool)>,
+}
+
+impl<'a> EntityDeserializer<'a> {
+    fn new(entity: &Entity<'a>, names: FieldNames) -> Result<Self, DeserializeError> {
+        let mut fields = vec![(names.field_name("db/id"), vec![Value::Ref(entity.eid)], false)];
+        // The ident each field name was taken from
+        let mut idents = BTreeMap::new();
+        idents.insert(fields[0].0.clone(), "db/id".to_string());
+
+        for (attribute, values) in &entity.values {
+            let ident = entity.db.attribute_name(*attribute)
+                .ok_or_else(|| DeserializeError(format!("Unknown attribute {:?}", attribute)))?;
+            let many = entity.db.attribute_info_for(*attribute)
+                .map_err(|e| DeserializeError(e.to_string()))?
+                .cardinality_many;
+
+            let name = names.field_name(&ident);
+            if let Some(other) = idents.insert(name.clone(), ident.clone()) {
+                return Err(DeserializeError(format!("Attributes {} and {} are both deserialized as field {}", other, ident, name)));
+            }
+            fields.push((name, values.clone(), many));
+        }
+
+        Ok(EntityDeserializer {
+            db: entity.db,
+            names,
+            fields: fields.into_iter(),
+            next_value: None,
+        })
+    }
+}
+
+impl<'de, 'a> Deserializer<'de> for EntityDeserializer<'a> {
+    type Error = DeserializeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeserializeError> {
+        visitor.visit_map(self)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf option unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+impl<'de, 'a> MapAccess<'de> for EntityDeserializer<'a> {
+    type Error = DeserializeError;
+
+    fn next_key_seed<K: DeserializeSeed<'de>>(&mut self, seed: K) -> Result<Option<K::Value>, DeserializeError> {
+        match self.fields.next() {
+            Some((name, values, many)) => {
+                self.next_value = Some((values, many));
+                seed.deserialize(de::value::StringDeserializer::new(name)).map(Some)
+            },
+            None => Ok(None)
+        }
+    }
+
+    fn next_value_seed<V: DeserializeSeed<'de>>(&mut self, seed: V) -> Result<V::Value, DeserializeError> {
+        let (mut values, many) = self.next_value.take()
+            .expect("next_value_seed called before next_key_seed");
+
+        if many {
+            seed.deserialize(ValuesDeserializer { db: self.db, names: self.names, values })
+        } else {
+            let value = values.swap_remove(0);
+            seed.deserialize(ValueDeserializer { db: self.db, names: self.names, value })
+        }
+    }
+}
+
+/// Deserializer for a single value. Refs are followed if a map or struct
+/// is requested.
+struct ValueDeserializer<'a> {
+    db: &'a dyn Database,
+    names: FieldNames,
+    value: Value,
+}
+
+impl<'de, 'a> Deserializer<'de> for ValueDeserializer<'a> {
+    type Error = DeserializeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeserializeError> {
+        match self.value {
+            Value::Bool(b)          => visitor.visit_bool(b),
+            Value::Str(s)           => visitor.visit_string(s),
+            Value::Int(i)           => visitor.visit_i64(i),
+            Value::Ref(EntityId(e)) => visitor.visit_i64(e),
+            Value::DateTime(dt)     => visitor.visit_string(dt.to_rfc3339()),
+            Value::Tuple(values)    => ValuesDeserializer { db: self.db, names: self.names, values }.deserialize_any(visitor),
+        }
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeserializeError> {
+        visitor.visit_some(self)
+    }
+
+    fn deserialize_newtype_struct<V: Visitor<'de>>(self, _name: &'static str, visitor: V) -> Result<V::Value, DeserializeError> {
+        visitor.visit_newtype_struct(self)
+    }
+
+    fn deserialize_map<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeserializeError> {
+        if let Value::Ref(eid) = self.value {
+            let entity = self.db.entity(eid)
+                .map_err(|e| DeserializeError(e.to_string()))?;
+            EntityDeserializer::new(&entity, self.names)?.deserialize_any(visitor)
+        } else {
+            self.deserialize_any(visitor)
+        }
+    }
+
+    fn deserialize_struct<V: Visitor<'de>>(self, _name: &'static str, _fields: &'static [&'static str], visitor: V) -> Result<V::Value, DeserializeError> {
+        self.deserialize_map(visitor)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct seq tuple tuple_struct enum
+        identifier ignored_any
+    }
+}
+
+/// Deserializer for the values of cardinality many attributes and tuples.
+struct ValuesDeserializer<'a> {
+    db: &'a dyn Database,
+    names: FieldNames,
+    values: Vec<Value>,
+}
+
+impl<'de, 'a> Deserializer<'de> for ValuesDeserializer<'a> {
+    type Error = DeserializeError;
+
+    fn deserialize_any<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeserializeError> {
+        visitor.visit_seq(ValuesSeqAccess {
+            db: self.db,
+            names: self.names,
+            values: self.values.into_iter(),
+        })
+    }
+
+    fn deserialize_option<V: Visitor<'de>>(self, visitor: V) -> Result<V::Value, DeserializeError> {
+        visitor.visit_some(self)
+    }
+
+    forward_to_deserialize_any! {
+        bool i8 i16 i32 i64 i128 u8 u16 u32 u64 u128 f32 f64 char str string
+        bytes byte_buf unit unit_struct newtype_struct seq tuple
+        tuple_struct map struct enum identifier ignored_any
+    }
+}
+
+struct ValuesSeqAccess<'a> {
+    db: &'a dyn Database,
+    names: FieldNames,
+    values: vec::IntoIter<Value>,
+}
+
+impl<'de, 'a> SeqAccess<'de> for ValuesSeqAccess<'a> {
+    type Error = DeserializeError;
+
+    fn next_element_seed<T: DeserializeSeed<'de>>(&mut self, seed: T) -> Result<Option<T::Value>, DeserializeError> {
+        match self.values.next() {
+            Some(value) => seed.deserialize(ValueDeserializer { db: self.db, names: self.names, value }).map(Some),
+            None => Ok(None)
+        }
+    }
+
+    fn size_hint(&self) -> Option<usize> {
+        Some(self.values.len())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::*;
+    use super::*;
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Person {
+        id: EntityId,
+        name: String,
+        age: Option<i64>,
+        #[serde(default)]
+        nicknames: Vec<String>,
+        born: Option<chrono::DateTime<chrono::Utc>>,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct Album {
+        name: String,
+        artist: Person,
+        producer: EntityId,
+    }
+
+    #[derive(Debug, PartialEq, Deserialize)]
+    struct PersonIdent {
+        #[serde(rename = "db/id")]
+        id: EntityId,
+        #[serde(rename = "person/name")]
+        name: String,
+    }
+
+    fn test_db() -> (Db, EntityId, EntityId) {
+        let mut db = Db::new().unwrap();
+        let nicknames = tempid();
+        db.transact(&[(Assert, tempid(), "db/ident", Value::from("person/name")),
+                      (Assert, tempid(), "db/ident", Value::from("person/age")),
+                      (Assert, tempid(), "db/ident", Value::from("person/born")),
+                      (Assert, nicknames.clone(), "db/ident", Value::from("person/nicknames")),
+                      (Assert, nicknames, "db.cardinality/many", Value::Bool(true)),
+                      (Assert, tempid(), "db/ident", Value::from("album/name")),
+                      (Assert, tempid(), "db/ident", Value::from("album/artist")),
+                      (Assert, tempid(), "db/ident", Value::from("album/producer"))]).unwrap();
+
+        let karl = tempid();
+        let karl = db.transact(&[(Assert, karl.clone(), "person/name", Value::from("Karl")),
+                                 (Assert, karl.clone(), "person/nicknames", Value::from("Kalle")),
+                                 (Assert, karl.clone(), "person/nicknames", Value::from("Charly"))]).unwrap()
+            .tempid_mappings[&karl];
+
+        let album = tempid();
+        let album = db.transact(&[(Assert, album.clone(), "album/name", Value::from("Nevermind")),
+                                  (Assert, album.clone(), "album/artist", Value::Ref(karl)),
+                                  (Assert, album.clone(), "album/producer", Value::Ref(karl))]).unwrap()
+            .tempid_mappings[&album];
+
+        (db, karl, album)
+    }
+
+    #[test]
+    fn deserialize() {
+        let (db, karl, _) = test_db();
+
+        let person: Person = db.entity(karl).unwrap().deserialize().unwrap();
+        assert_eq!(person, Person {
+            id: karl,
+            name: "Karl".into(),
+            age: None,
+            nicknames: vec!["Charly".into(), "Kalle".into()],
+            born: None,
+        });
+    }
+
+    #[test]
+    fn deserialize_refs() {
+        let (db, karl, album) = test_db();
+
+        let album: Album = db.entity(album).unwrap().deserialize().unwrap();
+        assert_eq!(album.name, "Nevermind");
+        assert_eq!(album.artist.id, karl);
+        assert_eq!(album.artist.name, "Karl");
+        assert_eq!(album.producer, karl);
+    }
+
+    #[test]
+    fn deserialize_idents() {
+        let (db, karl, _) = test_db();
+
+        let person: PersonIdent = db.entity(karl).unwrap().deserialize_with(FieldNames::Ident).unwrap();
+        assert_eq!(person, PersonIdent { id: karl, name: "Karl".into() });
+    }
+
+    #[test]
+    fn deserialize_missing_attribute() {
+        let (db, _, album) = test_db();
+
+        let error = db.entity(album).unwrap().deserialize::<PersonIdent>().unwrap_err();
+        assert_eq!(error, DeserializeError("missing field `db/id`".into()));
+    }
+
+    #[test]
+    fn deserialize_duplicate_field_names() {
+        let (mut db, karl, _) = test_db();
+        db.transact(&[(Assert, tempid(), "db/ident", Value::from("person/id"))]).unwrap();
+        db.transact(&[(Assert, karl, "album/name", Value::from("Nevermind")),
+                      (Assert, karl, "person/id", Value::from("karl"))]).unwrap();
+
+        let error = db.entity(karl).unwrap().deserialize::<Person>().unwrap_err();
+        assert_eq!(error, DeserializeError("Attributes person/name and album/name are both deserialized as field name".into()));
+
+        db.transact(&[(Retract, karl, "album/name", Value::from("Nevermind"))]).unwrap();
+        let error = db.entity(karl).unwrap().deserialize::<Person>().unwrap_err();
+        assert_eq!(error, DeserializeError("Attributes db/id and person/id are both deserialized as field id".into()));
+
+        // Full idents can't collide
+        let person: PersonIdent = db.entity(karl).unwrap().deserialize_with(FieldNames::Ident).unwrap();
+        assert_eq!(person.name, "Karl");
+    }
+}
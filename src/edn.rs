@@ -0,0 +1,654 @@
+//! Reading and writing of EDN data, as used by Datomic for schema and
+//! transaction data.
+//!
+//! ```edn
+//! [{:db/id #db/id[:db.part/db] :db/ident :diary.entry/text}
+//!  [:db/add #db/id[:db.part/user -1] :diary.entry/text "Hello World!"]
+//!  [:db/add #db/id[:db.part/user -1] :diary.entry/date #inst "2018-05-01T12:00:00Z"]]
+//! ```
+
+use super::{tempid, Database, Datom, Entity, EntityId, Operation, TempId, Value};
+
+use chrono;
+use std::collections::BTreeMap;
+use std::{fmt, vec};
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Edn {
+    Nil,
+    Bool(bool),
+    Int(i64),
+    Float(f64),
+    Str(String),
+    /// Keyword without the leading colon
+    Keyword(String),
+    Symbol(String),
+    List(Vec<Edn>),
+    Vector(Vec<Edn>),
+    Map(Vec<(Edn, Edn)>),
+    Set(Vec<Edn>),
+    /// Tag without the leading `#` and the tagged element
+    Tagged(String, Box<Edn>),
+}
+
+#[derive(Debug, Fail, PartialEq, Eq)]
+pub enum EdnError {
+    #[fail(display = "Failed to parse EDN at position {}: {}", _0, _1)]
+    Parse(usize, String),
+    #[fail(display = "Invalid transaction data {}: {}", _0, _1)]
+    InvalidTransaction(String, String),
+}
+
+/// Parses all top-level EDN elements of `input`.
+pub fn parse(input: &str) -> Result<Vec<Edn>, EdnError> {
+    let mut reader = Reader { input, pos: 0 };
+    let mut elements = vec![];
+
+    reader.skip_whitespace();
+    while reader.peek().is_some() {
+        elements.push(reader.read()?);
+        reader.skip_whitespace();
+    }
+
+    Ok(elements)
+}
+
+struct Reader<'a> {
+    input: &'a str,
+    pos: usize,
+}
+
+impl<'a> Reader<'a> {
+    fn error<T, S: Into<String>>(&self, msg: S) -> Result<T, EdnError> {
+        Err(EdnError::Parse(self.pos, msg.into()))
+    }
+
+    fn peek(&self) -> Option<char> {
+        self.input[self.pos..].chars().next()
+    }
+
+    fn next(&mut self) -> Option<char> {
+        let c = self.peek()?;
+        self.pos += c.len_utf8();
+        Some(c)
+    }
+
+    /// Skips whitespace, commas, comments and `#_` discarded elements.
+    fn skip_whitespace(&mut self) {
+        loop {
+            match self.peek() {
+                Some(c) if c.is_whitespace() || c == ',' => { self.next(); },
+                Some(';') => {
+                    while let Some(c) = self.next() {
+                        if c == '\n' {
+                            break;
+                        }
+                    }
+                },
+                Some('#') if self.input[self.pos..].starts_with("#_") => {
+                    self.pos += 2;
+                    // Errors are reported when reading the next element
+                    let pos = self.pos;
+                    if self.read().is_err() {
+                        self.pos = pos;
+                        return;
+                    }
+                },
+                _ => return
+            }
+        }
+    }
+
+    fn read(&mut self) -> Result<Edn, EdnError> {
+        self.skip_whitespace();
+
+        match self.peek() {
+            None => self.error("Unexpected end of input"),
+            Some('(') => { self.next(); Ok(Edn::List(self.read_until(')')?)) },
+            Some('[') => { self.next(); Ok(Edn::Vector(self.read_until(']')?)) },
+            Some('{') => { self.next(); self.read_map() },
+            Some('"') => { self.next(); self.read_string() },
+            Some(':') => {
+                self.next();
+                let name = self.read_token();
+                if name.is_empty() {
+                    self.error("Empty keyword")
+                } else {
+                    Ok(Edn::Keyword(name))
+                }
+            },
+            Some('#') => {
+                self.next();
+                if self.peek() == Some('{') {
+                    self.next();
+                    Ok(Edn::Set(self.read_until('}')?))
+                } else if self.peek() == Some('#') {
+                    self.next();
+                    match &self.read_token()[..] {
+                        "NaN"  => Ok(Edn::Float(f64::NAN)),
+                        "Inf"  => Ok(Edn::Float(f64::INFINITY)),
+                        "-Inf" => Ok(Edn::Float(f64::NEG_INFINITY)),
+                        other  => self.error(format!("Invalid symbolic value ##{}", other))
+                    }
+                } else {
+                    let tag = self.read_token();
+                    if tag.is_empty() {
+                        return self.error("Expected tag after #")
+                    }
+                    Ok(Edn::Tagged(tag, Box::new(self.read()?)))
+                }
+            },
+            Some(c) if c == ')' || c == ']' || c == '}' => self.error(format!("Unexpected {}", c)),
+            Some(_) => {
+                let token = self.read_token();
+                self.parse_token(token)
+            }
+        }
+    }
+
+    fn read_until(&mut self, close: char) -> Result<Vec<Edn>, EdnError> {
+        let mut elements = vec![];
+        loop {
+            self.skip_whitespace();
+            match self.peek() {
+                None => return self.error(format!("Expected {}", close)),
+                Some(c) if c == close => {
+                    self.next();
+                    return Ok(elements)
+                },
+                Some(_) => elements.push(self.read()?)
+            }
+        }
+    }
+
+    fn read_map(&mut self) -> Result<Edn, EdnError> {
+        let elements = self.read_until('}')?;
+        if elements.len() % 2 != 0 {
+            return self.error("Map with odd number of elements")
+        }
+
+        let mut entries = vec![];
+        let mut elements = elements.into_iter();
+        while let (Some(k), Some(v)) = (elements.next(), elements.next()) {
+            entries.push((k, v));
+        }
+
+        Ok(Edn::Map(entries))
+    }
+
+    fn read_string(&mut self) -> Result<Edn, EdnError> {
+        let mut s = String::new();
+        loop {
+            match self.next() {
+                None => return self.error("Unterminated string"),
+                Some('"') => return Ok(Edn::Str(s)),
+                Some('\\') => match self.next() {
+                    Some('n')  => s.push('\n'),
+                    Some('t')  => s.push('\t'),
+                    Some('r')  => s.push('\r'),
+                    Some('"')  => s.push('"'),
+                    Some('\\') => s.push('\\'),
+                    other => return self.error(format!("Invalid escape {:?}", other))
+                },
+                Some(c) => s.push(c)
+            }
+        }
+    }
+
+    fn read_token(&mut self) -> String {
+        let start = self.pos;
+        while let Some(c) = self.peek() {
+            if c.is_whitespace() || "()[]{}\",;".contains(c) {
+                break;
+            }
+            self.next();
+        }
+        self.input[start..self.pos].to_string()
+    }
+
+    fn parse_token(&self, token: String) -> Result<Edn, EdnError> {
+        match &token[..] {
+            "nil"   => return Ok(Edn::Nil),
+            "true"  => return Ok(Edn::Bool(true)),
+            "false" => return Ok(Edn::Bool(false)),
+            _ => ()
+        }
+
+        let starts_numeric = {
+            let mut chars = token.chars();
+            match (chars.next(), chars.next()) {
+                (Some(c), _) if c.is_ascii_digit() => true,
+                (Some('-'), Some(c)) | (Some('+'), Some(c)) => c.is_ascii_digit(),
+                _ => false
+            }
+        };
+
+        if !starts_numeric {
+            return Ok(Edn::Symbol(token))
+        }
+
+        if let Ok(i) = token.trim_end_matches('N').parse::<i64>() {
+            Ok(Edn::Int(i))
+        } else if let Ok(f) = token.trim_end_matches('M').parse::<f64>() {
+            Ok(Edn::Float(f))
+        } else {
+            self.error(format!("Invalid number {}", token))
+        }
+    }
+}
+
+impl fmt::Display for Edn {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        fn write_all(f: &mut fmt::Formatter, elements: &[Edn]) -> fmt::Result {
+            for (i, e) in elements.iter().enumerate() {
+                if i > 0 { write!(f, " ")?; }
+                write!(f, "{}", e)?;
+            }
+            Ok(())
+        }
+
+        match self {
+            Edn::Nil             => write!(f, "nil"),
+            Edn::Bool(b)         => write!(f, "{}", b),
+            Edn::Int(i)          => write!(f, "{}", i),
+            Edn::Float(x) if x.is_nan() => write!(f, "##NaN"),
+            Edn::Float(x) if x.is_infinite() => write!(f, "{}", if *x > 0.0 { "##Inf" } else { "##-Inf" }),
+            Edn::Float(x) if x.fract() == 0.0 => write!(f, "{:.1}", x),
+            Edn::Float(x)        => write!(f, "{}", x),
+            Edn::Str(s)          => {
+                write!(f, "\"")?;
+                for c in s.chars() {
+                    match c {
+                        '"'  => write!(f, "\\\"")?,
+                        '\\' => write!(f, "\\\\")?,
+                        '\n' => write!(f, "\\n")?,
+                        '\t' => write!(f, "\\t")?,
+                        '\r' => write!(f, "\\r")?,
+                        c    => write!(f, "{}", c)?,
+                    }
+                }
+                write!(f, "\"")
+            },
+            Edn::Keyword(k)      => write!(f, ":{}", k),
+            Edn::Symbol(s)       => write!(f, "{}", s),
+            Edn::List(l)         => { write!(f, "(")?; write_all(f, l)?; write!(f, ")") },
+            Edn::Vector(v)       => { write!(f, "[")?; write_all(f, v)?; write!(f, "]") },
+            Edn::Set(s)          => { write!(f, "#{{")?; write_all(f, s)?; write!(f, "}}") },
+            Edn::Map(m)          => {
+                write!(f, "{{")?;
+                for (i, (k, v)) in m.iter().enumerate() {
+                    if i > 0 { write!(f, ", ")?; }
+                    write!(f, "{} {}", k, v)?;
+                }
+                write!(f, "}}")
+            },
+            Edn::Tagged(tag, e)  => write!(f, "#{} {}", tag, e),
+        }
+    }
+}
+
+impl<'a> From<&'a Value> for Edn {
+    fn from(value: &'a Value) -> Edn {
+        match value {
+            Value::Bool(b)          => Edn::Bool(*b),
+            Value::Str(s)           => Edn::Str(s.clone()),
+            Value::Int(i)           => Edn::Int(*i),
+            Value::Ref(EntityId(e)) => Edn::Int(*e),
+            Value::DateTime(dt)     => Edn::Tagged("inst".into(), Box::new(Edn::Str(dt.to_rfc3339()))),
+            Value::Tuple(values)    => Edn::Vector(values.iter().map(Edn::from).collect()),
+        }
+    }
+}
+
+impl Edn {
+    /// `#datom[e :attribute v tx added]`
+    pub fn from_datom(db: &dyn Database, datom: &Datom) -> Edn {
+        let attribute = db.attribute_name(datom.attribute)
+            .map(Edn::Keyword)
+            .unwrap_or_else(|| Edn::Int((datom.attribute.0).0));
+
+        Edn::Tagged("datom".into(), Box::new(Edn::Vector(vec![
+            Edn::Int(datom.entity.0),
+            attribute,
+            Edn::from(&datom.value),
+            Edn::Int(datom.tx.0),
+            Edn::Bool(datom.status.is_assertion()),
+        ])))
+    }
+
+    /// Entity map with `:db/id`. Values of `db.cardinality/many`
+    /// attributes are written as sets.
+    pub fn from_entity(entity: &Entity) -> Edn {
+        let mut entries = vec![(Edn::Keyword("db/id".into()), Edn::Int(entity.eid.0))];
+
+        for (attribute, values) in &entity.values {
+            let key = entity.db.attribute_name(*attribute)
+                .map(Edn::Keyword)
+                .unwrap_or_else(|| Edn::Int((attribute.0).0));
+            let many = entity.db.attribute_info_for(*attribute)
+                .map(|info| info.cardinality_many)
+                .unwrap_or(values.len() > 1);
+
+            let value = if many {
+                Edn::Set(values.iter().map(Edn::from).collect())
+            } else {
+                Edn::from(&values[0])
+            };
+
+            entries.push((key, value));
+        }
+
+        Edn::Map(entries)
+    }
+}
+
+/// Transaction data read from EDN. Can be passed to `Db::transact`
+/// directly.
+#[derive(Debug)]
+pub struct EdnTx {
+    pub operations: Vec<Operation>,
+    /// Tempids of string `db/id`s and of `#db/id[:db.part/user -1]`, keyed
+    /// by `-1`.
+    pub tempids: BTreeMap<String, TempId>,
+}
+
+impl IntoIterator for EdnTx {
+    type Item = Operation;
+    type IntoIter = vec::IntoIter<Operation>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.operations.into_iter()
+    }
+}
+
+#[derive(Debug, Clone)]
+enum Subject {
+    Entity(EntityId),
+    Tempid(TempId),
+}
+
+/// Reads a vector of transaction data: `[:db/add e a v]`,
+/// `[:db/retract e a v]` and entity maps.
+///
+/// Entities are either entity ids, tempid strings or `#db/id[partition]`
+/// (optionally with a negative number to refer to the same tempid
+/// multiple times). Keywords are written as strings without colon,
+/// `#inst` as `Value::DateTime` and vectors as tuples. In maps, sets and
+/// vectors assert multiple values and nested maps are new referenced
+/// entities. The vector has to be the only top-level element of `input`.
+pub fn parse_tx(input: &str) -> Result<EdnTx, EdnError> {
+    let mut elements = parse(input)?.into_iter();
+    let forms = match elements.next() {
+        Some(Edn::Vector(forms)) | Some(Edn::List(forms)) => forms,
+        other => return Err(invalid(&other.unwrap_or(Edn::Nil), "Expected a vector of transaction data"))
+    };
+    if let Some(trailing) = elements.next() {
+        return Err(invalid(&trailing, "Expected a single vector of transaction data"));
+    }
+
+    let mut tx = EdnTx {
+        operations: vec![],
+        tempids: BTreeMap::new(),
+    };
+
+    for form in forms {
+        match form {
+            Edn::Map(entries) => { tx.expand_map(entries)?; },
+            Edn::Vector(ref elements) if elements.len() == 4 => {
+                let subject = tx.subject(&elements[1])?;
+                let attribute = attribute_name(&elements[2])?;
+                let value = &elements[3];
+
+                let op = match elements[0] {
+                    Edn::Keyword(ref k) if k == "db/add"     => tx.assertion(subject, attribute, value)?,
+                    Edn::Keyword(ref k) if k == "db/retract" => {
+                        match subject {
+                            Subject::Entity(e) => Operation::Retraction(e, attribute, to_value(value)?),
+                            Subject::Tempid(_) => return Err(invalid(&form, "Can't retract from a tempid"))
+                        }
+                    },
+                    _ => return Err(invalid(&form, "Expected :db/add or :db/retract"))
+                };
+
+                tx.operations.push(op);
+            },
+            other => return Err(invalid(&other, "Expected a map or a [:db/add e a v] vector"))
+        }
+    }
+
+    Ok(tx)
+}
+
+impl EdnTx {
+    fn tempid(&mut self, name: String) -> TempId {
+        self.tempids.entry(name).or_insert_with(tempid).clone()
+    }
+
+    fn subject(&mut self, e: &Edn) -> Result<Subject, EdnError> {
+        match e {
+            Edn::Int(i) => Ok(Subject::Entity(EntityId(*i))),
+            Edn::Str(name) => Ok(Subject::Tempid(self.tempid(name.clone()))),
+            Edn::Tagged(tag, _) if tag == "db/id" => self.tagged_tempid(e),
+            other => Err(invalid(other, "Expected an entity id or tempid"))
+        }
+    }
+
+    /// `#db/id[:db.part/user]` or `#db/id[:db.part/user -1]`
+    fn tagged_tempid(&mut self, e: &Edn) -> Result<Subject, EdnError> {
+        match e {
+            Edn::Tagged(tag, inner) if tag == "db/id" => match **inner {
+                Edn::Vector(ref v) if v.len() == 1 => Ok(Subject::Tempid(tempid())),
+                Edn::Vector(ref v) if v.len() == 2 => match v[1] {
+                    Edn::Int(n) => Ok(Subject::Tempid(self.tempid(n.to_string()))),
+                    ref other => Err(invalid(other, "Expected tempid number"))
+                },
+                ref other => Err(invalid(other, "Expected [partition] or [partition n]"))
+            },
+            other => Err(invalid(other, "Expected #db/id"))
+        }
+    }
+
+    fn assertion(&mut self, subject: Subject, attribute: String, value: &Edn) -> Result<Operation, EdnError> {
+        let tempid_value = match value {
+            Edn::Tagged(tag, _) if tag == "db/id" => Some(self.tagged_tempid(value)?),
+            Edn::Map(entries) => Some(self.expand_map(entries.clone())?),
+            _ => None
+        };
+
+        Ok(match (subject, tempid_value) {
+            (Subject::Entity(e), None)                      => Operation::Assertion(e, attribute, to_value(value)?),
+            (Subject::Tempid(e), None)                      => Operation::TempidAssertion(e, attribute, to_value(value)?),
+            (Subject::Entity(e), Some(Subject::Entity(v)))  => Operation::Assertion(e, attribute, Value::Ref(v)),
+            (Subject::Tempid(e), Some(Subject::Entity(v)))  => Operation::TempidAssertion(e, attribute, Value::Ref(v)),
+            (Subject::Entity(e), Some(Subject::Tempid(v)))  => Operation::RefAssertion(e, attribute, v),
+            (Subject::Tempid(e), Some(Subject::Tempid(v)))  => Operation::TempidRefAssertion(e, attribute, v),
+        })
+    }
+
+    fn expand_map(&mut self, entries: Vec<(Edn, Edn)>) -> Result<Subject, EdnError> {
+        let db_id = Edn::Keyword("db/id".into());
+        let subject = match entries.iter().find(|(k, _)| *k == db_id) {
+            Some((_, e)) => self.subject(e)?,
+            None => Subject::Tempid(tempid())
+        };
+
+        for (k, v) in entries.into_iter().filter(|(k, _)| *k != db_id) {
+            let attribute = attribute_name(&k)?;
+            match v {
+                Edn::Nil => (),
+                Edn::Vector(values) | Edn::Set(values) | Edn::List(values) => {
+                    for value in values {
+                        let op = self.assertion(subject.clone(), attribute.clone(), &value)?;
+                        self.operations.push(op);
+                    }
+                },
+                value => {
+                    let op = self.assertion(subject.clone(), attribute, &value)?;
+                    self.operations.push(op);
+                }
+            }
+        }
+
+        Ok(subject)
+    }
+}
+
+fn invalid(edn: &Edn, msg: &str) -> EdnError {
+    EdnError::InvalidTransaction(edn.to_string(), msg.into())
+}
+
+fn attribute_name(edn: &Edn) -> Result<String, EdnError> {
+    match edn {
+        Edn::Keyword(k) => Ok(k.clone()),
+        other => Err(invalid(other, "Expected attribute keyword"))
+    }
+}
+
+/// Converts EDN to a `Value`: Keywords become strings, `#inst` a
+/// `Value::DateTime` and vectors tuples.
+pub fn to_value(edn: &Edn) -> Result<Value, EdnError> {
+    match edn {
+        Edn::Bool(b)    => Ok(Value::Bool(*b)),
+        Edn::Int(i)     => Ok(Value::Int(*i)),
+        Edn::Str(s)     => Ok(Value::Str(s.clone())),
+        Edn::Keyword(k) => Ok(Value::Str(k.clone())),
+        Edn::Vector(v)  => v.iter().map(to_value).collect::<Result<Vec<_>, _>>().map(Value::Tuple),
+        Edn::Tagged(tag, inner) if tag == "inst" => match **inner {
+            Edn::Str(ref s) => chrono::DateTime::parse_from_rfc3339(s)
+                .map(|dt| Value::DateTime(dt.with_timezone(&chrono::Utc)))
+                .map_err(|e| invalid(edn, &e.to_string())),
+            _ => Err(invalid(edn, "Expected #inst string"))
+        },
+        other => Err(invalid(other, "Can't convert to a value"))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::*;
+    use super::*;
+
+    #[test]
+    fn parse_elements() {
+        let edn = parse(r#"
+          ; Comment
+          [nil true false 42 -7 1.5 "a \"b\"\n" :foo/bar baz
+           (1 2) #{1} {:a 1, :b 2} #_ignored #inst "2018-05-01T12:00:00Z"]
+        "#).unwrap();
+
+        assert_eq!(edn, vec![Edn::Vector(vec![
+            Edn::Nil,
+            Edn::Bool(true),
+            Edn::Bool(false),
+            Edn::Int(42),
+            Edn::Int(-7),
+            Edn::Float(1.5),
+            Edn::Str("a \"b\"\n".into()),
+            Edn::Keyword("foo/bar".into()),
+            Edn::Symbol("baz".into()),
+            Edn::List(vec![Edn::Int(1), Edn::Int(2)]),
+            Edn::Set(vec![Edn::Int(1)]),
+            Edn::Map(vec![(Edn::Keyword("a".into()), Edn::Int(1)),
+                          (Edn::Keyword("b".into()), Edn::Int(2))]),
+            Edn::Tagged("inst".into(), Box::new(Edn::Str("2018-05-01T12:00:00Z".into()))),
+        ])]);
+    }
+
+    #[test]
+    fn parse_errors() {
+        assert_eq!(parse("[1 2"), Err(EdnError::Parse(4, "Expected ]".into())));
+        assert_eq!(parse("\"foo"), Err(EdnError::Parse(4, "Unterminated string".into())));
+        assert_eq!(parse("{:a}"), Err(EdnError::Parse(4, "Map with odd number of elements".into())));
+        assert_eq!(parse(")"), Err(EdnError::Parse(0, "Unexpected )".into())));
+    }
+
+    #[test]
+    fn symbolic_floats() {
+        let edn = parse("[##Inf ##-Inf ##NaN]").unwrap();
+        assert_eq!(edn[0].to_string(), "[##Inf ##-Inf ##NaN]");
+        match &edn[0] {
+            Edn::Vector(values) => match values[2] {
+                Edn::Float(x) => assert!(x.is_nan()),
+                _ => panic!("")
+            },
+            _ => panic!("")
+        }
+        assert!(parse("##Foo").is_err());
+    }
+
+    #[test]
+    fn roundtrip() {
+        let input = r#"[{:db/id 1, :foo/bar #{"a" "b"}} #inst "2018-05-01T12:00:00+00:00" (nil 1.0 -2)]"#;
+        let edn = parse(input).unwrap();
+        assert_eq!(edn[0].to_string(), input);
+    }
+
+    #[test]
+    fn transact() {
+        let mut db = Db::new().unwrap();
+
+        let schema = parse_tx(r#"
+          [{:db/id #db/id[:db.part/db] :db/ident :diary.entry/text :db/doc "The text"}
+           {:db/id #db/id[:db.part/db] :db/ident :diary.entry/date}
+           {:db/id #db/id[:db.part/db] :db/ident :diary.entry/author}
+           {:db/id #db/id[:db.part/db] :db/ident :diary.entry/tags :db.cardinality/many true}
+           {:db/id #db/id[:db.part/db] :db/ident :person/name}]
+        "#).unwrap();
+        db.transact(schema).unwrap();
+        assert_eq!(db.attribute_info("diary.entry/text").unwrap().doc, Some("The text".into()));
+
+        let tx = parse_tx(r#"
+          [[:db/add #db/id[:db.part/user -1] :diary.entry/text "Hello World!"]
+           [:db/add #db/id[:db.part/user -1] :diary.entry/date #inst "2018-05-01T12:00:00Z"]
+           [:db/add #db/id[:db.part/user -1] :diary.entry/author #db/id[:db.part/user -2]]
+           [:db/add #db/id[:db.part/user -2] :person/name "Karl"]
+           {:db/id "entry" :diary.entry/tags #{"hello" "world"}}]
+        "#).unwrap();
+        let entry = tx.tempids["-1"].clone();
+        let tagged = tx.tempids["entry"].clone();
+        let txd = db.transact(tx).unwrap();
+
+        let eid = txd.tempid_mappings[&entry];
+        let entry = db.entity(eid).unwrap();
+        assert_eq!(entry["diary.entry/text"], Value::from("Hello World!"));
+        assert_eq!(entry["diary.entry/date"].as_datetime().unwrap().to_rfc3339(), "2018-05-01T12:00:00+00:00");
+        assert_eq!(entry.follow_ref("diary.entry/author").unwrap()["person/name"], Value::from("Karl"));
+
+        let tagged = db.entity(txd.tempid_mappings[&tagged]).unwrap();
+        assert_eq!(tagged.get_many("diary.entry/tags"), &[Value::from("hello"), Value::from("world")]);
+
+        let retract = parse_tx(&format!(r#"[[:db/retract {} :diary.entry/text "Hello World!"]]"#, eid.0)).unwrap();
+        db.transact(retract).unwrap();
+        assert!(db.entity(eid).unwrap().get("diary.entry/text").is_none());
+    }
+
+    #[test]
+    fn transact_trailing_elements() {
+        let error = parse_tx(r#"[{:person/name "Karl"}] [{:person/name "Anna"}]"#).unwrap_err();
+        assert_eq!(error, EdnError::InvalidTransaction(r#"[{:person/name "Anna"}]"#.into(),
+                                                       "Expected a single vector of transaction data".into()));
+        assert!(parse_tx(r#"[{:person/name "Karl"}] ; Comment"#).is_ok());
+    }
+
+    #[test]
+    fn write() {
+        let mut db = Db::new().unwrap();
+        let tags = tempid();
+        db.transact(&[(Assert, tempid(), "db/ident", Value::from("diary.entry/text")),
+                      (Assert, tags.clone(), "db/ident", Value::from("diary.entry/tags")),
+                      (Assert, tags, "db.cardinality/many", Value::Bool(true))]).unwrap();
+
+        let entry = tempid();
+        let txd = db.transact(&[(Assert, entry.clone(), "diary.entry/text", Value::from("Hello")),
+                                (Assert, entry.clone(), "diary.entry/tags", Value::from("greeting"))]).unwrap();
+        let eid = txd.tempid_mappings[&entry];
+
+        let entity = db.entity(eid).unwrap();
+        assert_eq!(Edn::from_entity(&entity).to_string(),
+                   format!(r#"{{:db/id {}, :diary.entry/text "Hello", :diary.entry/tags #{{"greeting"}}}}"#, eid.0));
+
+        let datom = &db.datoms(Index::Eavt.e(eid).a(db.attribute("diary.entry/text").unwrap())).unwrap()[0];
+        assert_eq!(Edn::from_datom(&db, datom).to_string(),
+                   format!(r#"#datom [{} :diary.entry/text "Hello" {} true]"#, eid.0, txd.tx_id.0));
+    }
+}
@@ -1,11 +1,11 @@
-use super::{Db, EntityId, Attribute, Value};
+use super::{Database, EntityId, Attribute, Error, Value};
 
 use std::{fmt, ops};
 use std::collections::BTreeMap;
 
 #[allow(dead_code)]
 pub struct Entity<'a> {
-    pub db: &'a Db,
+    pub db: &'a dyn Database,
     pub eid: EntityId,
     pub values: BTreeMap<Attribute, Vec<Value>>,
 }
@@ -26,6 +26,8 @@ impl<'a> Entity<'a> {
             .unwrap_or_else(|| &EMPTY_VEC[..])
     }
 
+    /// The entity referred to by `ref_attribute`. Values of enum attributes
+    /// are idents and can't be followed.
     pub fn follow_ref(&'a self, ref_attribute: &'a str) -> Result<Entity<'a>, NoRefError> {
         match self[ref_attribute] {
             Value::Ref(eid) => Ok(self.db.entity(eid).unwrap()),
@@ -51,6 +53,26 @@ lazy_static! {
     static ref EMPTY_VEC: Vec<Value> = vec![];
 }
 
+/// Replaces the refs of enum attributes in `values` with the idents of
+/// the referred entities, see `AttributeInfo::enumerated`
+pub(crate) fn resolve_enum_values(db: &dyn Database, values: &mut BTreeMap<Attribute, Vec<Value>>) -> Result<(), Error> {
+    for (attribute, values) in values.iter_mut() {
+        // Only attributes with refs can be enums, which saves looking up
+        // the info of most attributes
+        if !values.iter().any(|v| v.as_ref_id().is_some()) || !db.attribute_info_for(*attribute)?.enumerated {
+            continue;
+        }
+
+        for value in values.iter_mut() {
+            if let Some(ident) = value.as_ref_id().and_then(|eid| db.attribute_name(Attribute(eid))) {
+                *value = Value::Str(ident);
+            }
+        }
+    }
+
+    Ok(())
+}
+
 /*
 impl<'a, D: Db> ops::Index<&'a str> for &'a Entity<'a, D> {
     type Output = Vec<Value>;
@@ -95,7 +117,7 @@ mod tests {
     fn test_db() -> Db {
         let mut db = Db::new().unwrap();
         let foo_bar = db.tempid();
-        let schema_tx = &[(Assert, foo_bar, "db/ident", Value::Str("foo/bar".into())),
+        let schema_tx = &[(Assert, foo_bar.clone(), "db/ident", Value::Str("foo/bar".into())),
                           (Assert, foo_bar, "db.cardinality/many", true.into()),
                           (Assert, tempid(), "db/ident", "some/ref".into())];
         db.transact(schema_tx).unwrap();
@@ -104,14 +126,6 @@ mod tests {
         db.transact(&[(Assert, TWO, "foo/bar", Value::Str("bar".to_string()))]).unwrap();
         db.transact(&[(Assert, TWO, "foo/bar", Value::Str("baz".to_string()))]).unwrap();
         db.transact(&[(Assert, TWO, "some/ref", ONE)]).unwrap();
-
-        // TODO: Implement `Assert` for Value::Ref with TempId
-        /*
-        let referred = tempid();
-        let referring = tempid();
-        db.transact(&[(Assert, referred, "foo/bar", Value::Int(42)),
-                      (Assert, referring, "some/ref", Value::Ref(referred))]).unwrap();
-         */
         db
     }
 
@@ -170,4 +184,18 @@ mod tests {
         assert_eq!(one.eid, ONE);
         assert_eq!(one.follow_ref("foo/bar").unwrap_err(), NoRefError);
     }
+
+    #[test]
+    fn follow_tempid_ref() {
+        let mut db = test_db();
+        let referred = tempid();
+        let referring = tempid();
+        let txd = db.transact(vec![Operation::from(&(Assert, referred.clone(), "foo/bar", Value::Int(42))),
+                                   Operation::from(&(Assert, referring.clone(), "some/ref", referred.clone()))]).unwrap();
+
+        let entity = db.entity(txd.tempid_mappings[&referring]).unwrap();
+        let referred_entity = entity.follow_ref("some/ref").unwrap();
+        assert_eq!(referred_entity.eid, txd.tempid_mappings[&referred]);
+        assert_eq!(referred_entity["foo/bar"], Value::Int(42));
+    }
 }
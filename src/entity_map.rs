@@ -0,0 +1,284 @@
+use super::{tempid, EntityId, Operation, TempId, Value};
+
+use serde::Serialize;
+use serde_json::{self, Map, Value as Json};
+use std::collections::BTreeMap;
+use std::vec;
+
+#[derive(Debug, Fail, PartialEq, Eq)]
+pub enum EntityMapError {
+    #[fail(display = "Expected an entity map or a list of entity maps, got {}", _0)]
+    NotAnEntityMap(String),
+    #[fail(display = "Invalid db/id {}. Expected an entity id or a tempid string", _0)]
+    InvalidEntityId(String),
+    #[fail(display = "Can't convert {} for attribute {} to a value", _1, _0)]
+    InvalidValue(String, String),
+    #[fail(display = "Failed to serialize entity map: {}", _0)]
+    Serialize(String),
+}
+
+/// Transaction data in map form: Each map describes one entity, keys
+/// are attribute idents. Expands to `Operation`s and can be passed to
+/// `Db::transact` directly.
+///
+/// ```json
+/// {"db/id": "entry",
+///  "diary.entry/text": "Hello World!",
+///  "diary.entry/tags": ["hello", "world"],
+///  "diary.entry/date": {"DateTime": "2018-05-01T12:00:00Z"},
+///  "diary.entry/author": {"person/name": "Karl"}}
+/// ```
+///
+/// - `db/id` is either the id of an existing entity or a string naming a
+///   tempid. Maps without `db/id` get a new tempid.
+/// - Arrays assert multiple values, arrays nested in arrays are tuples.
+/// - Nested maps are new entities (or existing ones if they have an
+///   integer `db/id`) which are referenced from the outer map.
+/// - Maps with a single `Value` variant as key (e.g. `{"Ref": 42}`) are
+///   taken as the serialized `Value`.
+/// - `null` values are ignored.
+#[derive(Debug)]
+pub struct EntityMaps {
+    pub operations: Vec<Operation>,
+    /// Tempids of `db/id` strings
+    pub tempids: BTreeMap<String, TempId>,
+}
+
+#[derive(Debug, Clone)]
+enum Subject {
+    Entity(EntityId),
+    Tempid(TempId),
+}
+
+const VALUE_VARIANTS: &[&str] = &["Bool", "Str", "Int", "Ref", "DateTime", "Tuple"];
+
+impl EntityMaps {
+    /// Expands a single entity map or an array of entity maps.
+    pub fn from_json(json: Json) -> Result<Self, EntityMapError> {
+        let mut maps = EntityMaps {
+            operations: vec![],
+            tempids: BTreeMap::new(),
+        };
+
+        match json {
+            Json::Object(map) => {
+                maps.expand_map(map)?;
+            },
+            Json::Array(items) => {
+                for item in items {
+                    match item {
+                        Json::Object(map) => { maps.expand_map(map)?; },
+                        other => return Err(EntityMapError::NotAnEntityMap(other.to_string()))
+                    }
+                }
+            },
+            other => return Err(EntityMapError::NotAnEntityMap(other.to_string()))
+        }
+
+        Ok(maps)
+    }
+
+    /// Expands any value serializing to an entity map (or a list of entity
+    /// maps), e.g. a struct with `#[serde(rename = "diary.entry/text")]`
+    /// fields.
+    pub fn from_serialize<T: Serialize>(value: &T) -> Result<Self, EntityMapError> {
+        let json = serde_json::to_value(value)
+            .map_err(|e| EntityMapError::Serialize(e.to_string()))?;
+        Self::from_json(json)
+    }
+
+    fn expand_map(&mut self, mut map: Map<String, Json>) -> Result<Subject, EntityMapError> {
+        let subject = match map.remove("db/id") {
+            None => Subject::Tempid(tempid()),
+            Some(Json::String(name)) => {
+                let tempid = self.tempids.entry(name).or_insert_with(tempid).clone();
+                Subject::Tempid(tempid)
+            },
+            Some(Json::Number(ref n)) if n.is_i64() => Subject::Entity(EntityId(n.as_i64().unwrap())),
+            Some(other) => return Err(EntityMapError::InvalidEntityId(other.to_string()))
+        };
+
+        for (attribute, json) in map {
+            match json {
+                Json::Null => (),
+                Json::Array(items) => {
+                    for item in items {
+                        self.expand_value(subject.clone(), &attribute, item)?;
+                    }
+                },
+                json => self.expand_value(subject.clone(), &attribute, json)?
+            }
+        }
+
+        Ok(subject)
+    }
+
+    fn expand_value(&mut self, subject: Subject, attribute: &str, json: Json) -> Result<(), EntityMapError> {
+        let operation = match json {
+            Json::Object(map) if !is_value(&map) => {
+                match (subject, self.expand_map(map)?) {
+                    (Subject::Entity(e), Subject::Entity(v)) => Operation::Assertion(e, attribute.into(), Value::Ref(v)),
+                    (Subject::Tempid(e), Subject::Entity(v)) => Operation::TempidAssertion(e, attribute.into(), Value::Ref(v)),
+                    (Subject::Entity(e), Subject::Tempid(v)) => Operation::RefAssertion(e, attribute.into(), v),
+                    (Subject::Tempid(e), Subject::Tempid(v)) => Operation::TempidRefAssertion(e, attribute.into(), v),
+                }
+            },
+            json => {
+                let value = to_value(attribute, json)?;
+                match subject {
+                    Subject::Entity(e) => Operation::Assertion(e, attribute.into(), value),
+                    Subject::Tempid(e) => Operation::TempidAssertion(e, attribute.into(), value),
+                }
+            }
+        };
+
+        self.operations.push(operation);
+        Ok(())
+    }
+}
+
+impl IntoIterator for EntityMaps {
+    type Item = Operation;
+    type IntoIter = vec::IntoIter<Operation>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.operations.into_iter()
+    }
+}
+
+fn is_value(map: &Map<String, Json>) -> bool {
+    map.len() == 1 && map.keys().all(|k| VALUE_VARIANTS.contains(&&k[..]))
+}
+
+fn to_value(attribute: &str, json: Json) -> Result<Value, EntityMapError> {
+    let invalid = |json: &Json| EntityMapError::InvalidValue(attribute.into(), json.to_string());
+
+    match json {
+        Json::Bool(b) => Ok(Value::Bool(b)),
+        Json::String(s) => Ok(Value::Str(s)),
+        Json::Number(ref n) if n.is_i64() => Ok(Value::Int(n.as_i64().unwrap())),
+        Json::Array(items) => {
+            items.into_iter()
+                .map(|item| to_value(attribute, item))
+                .collect::<Result<Vec<_>, _>>()
+                .map(Value::Tuple)
+        },
+        Json::Object(ref map) if is_value(map) => {
+            serde_json::from_value(json.clone()).map_err(|_| invalid(&json))
+        },
+        json => Err(invalid(&json))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::*;
+    use super::*;
+
+    fn test_db() -> Db {
+        let mut db = Db::new().unwrap();
+        let tags = tempid();
+        db.transact(&[(Assert, tempid(), "db/ident", Value::from("diary.entry/text")),
+                      (Assert, tempid(), "db/ident", Value::from("diary.entry/date")),
+                      (Assert, tempid(), "db/ident", Value::from("diary.entry/author")),
+                      (Assert, tempid(), "db/ident", Value::from("diary.entry/location")),
+                      (Assert, tempid(), "db/ident", Value::from("person/name")),
+                      (Assert, tags.clone(), "db/ident", Value::from("diary.entry/tags")),
+                      (Assert, tags, "db.cardinality/many", Value::Bool(true))]).unwrap();
+        db
+    }
+
+    #[test]
+    fn json() {
+        let mut db = test_db();
+
+        let json = serde_json::from_str(r#"
+          {"db/id": "entry",
+           "diary.entry/text": "Hello World!",
+           "diary.entry/date": {"DateTime": "2018-05-01T12:00:00Z"},
+           "diary.entry/tags": ["hello", "world"],
+           "diary.entry/location": [[52, 13]],
+           "diary.entry/author": {"person/name": "Karl"}}
+        "#).unwrap();
+
+        let maps = EntityMaps::from_json(json).unwrap();
+        let entry = maps.tempids["entry"].clone();
+        let txd = db.transact(maps).unwrap();
+
+        let entry = db.entity(txd.tempid_mappings[&entry]).unwrap();
+        assert_eq!(entry["diary.entry/text"], Value::Str("Hello World!".into()));
+        assert_eq!(entry["diary.entry/date"].as_datetime().unwrap().to_rfc3339(), "2018-05-01T12:00:00+00:00");
+        assert_eq!(entry.get_many("diary.entry/tags"), &[Value::from("hello"), Value::from("world")]);
+        assert_eq!(entry["diary.entry/location"], Value::Tuple(vec![Value::Int(52), Value::Int(13)]));
+
+        let author = entry.follow_ref("diary.entry/author").unwrap();
+        assert_eq!(author["person/name"], Value::Str("Karl".into()));
+    }
+
+    #[test]
+    fn existing_entities() {
+        let mut db = test_db();
+
+        let karl = tempid();
+        let karl = db.transact(&[(Assert, karl.clone(), "person/name", "Karl")]).unwrap()
+            .tempid_mappings[&karl];
+
+        let json = json!([{"db/id": karl.0, "person/name": "Karl Heinz"},
+                          {"db/id": "entry", "diary.entry/author": {"db/id": karl.0}}]);
+        let maps = EntityMaps::from_json(json).unwrap();
+        let entry = maps.tempids["entry"].clone();
+        let txd = db.transact(maps).unwrap();
+
+        let entry = db.entity(txd.tempid_mappings[&entry]).unwrap();
+        assert_eq!(entry["diary.entry/author"], Value::Ref(karl));
+        assert_eq!(db.entity(karl).unwrap()["person/name"], Value::Str("Karl Heinz".into()));
+    }
+
+    #[test]
+    fn serialize() {
+        #[derive(Serialize)]
+        struct Person {
+            #[serde(rename = "person/name")]
+            name: String,
+        }
+
+        #[derive(Serialize)]
+        struct DiaryEntry {
+            #[serde(rename = "db/id")]
+            id: &'static str,
+            #[serde(rename = "diary.entry/text")]
+            text: String,
+            #[serde(rename = "diary.entry/date")]
+            date: Option<Value>,
+            #[serde(rename = "diary.entry/author")]
+            author: Person,
+        }
+
+        let mut db = test_db();
+        let entry = DiaryEntry {
+            id: "entry",
+            text: "Hello World!".into(),
+            date: None,
+            author: Person { name: "Karl".into() },
+        };
+
+        let maps = EntityMaps::from_serialize(&entry).unwrap();
+        let entry = maps.tempids["entry"].clone();
+        let txd = db.transact(maps).unwrap();
+
+        let entry = db.entity(txd.tempid_mappings[&entry]).unwrap();
+        assert_eq!(entry["diary.entry/text"], Value::Str("Hello World!".into()));
+        assert!(entry.get("diary.entry/date").is_none());
+        assert_eq!(entry.follow_ref("diary.entry/author").unwrap()["person/name"], Value::Str("Karl".into()));
+    }
+
+    #[test]
+    fn invalid() {
+        assert_eq!(EntityMaps::from_json(json!("foo")).unwrap_err(),
+                   EntityMapError::NotAnEntityMap("\"foo\"".into()));
+        assert_eq!(EntityMaps::from_json(json!({"db/id": true})).unwrap_err(),
+                   EntityMapError::InvalidEntityId("true".into()));
+        assert_eq!(EntityMaps::from_json(json!({"foo/bar": 1.5})).unwrap_err(),
+                   EntityMapError::InvalidValue("foo/bar".into(), "1.5".into()));
+    }
+}
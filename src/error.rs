@@ -0,0 +1,22 @@
+use transaction::TransactionError;
+
+use rusqlite;
+use serde_json;
+use std::io;
+
+/// Errors of `Db`, its storages and the remote `Client`
+#[derive(Debug, Fail, From)]
+pub enum Error {
+    #[fail(display="Sqlite Error: {}", _0)]
+    Sqlite(rusqlite::Error),
+    #[fail(display="Transaction Error: {}", _0)]
+    TransactionError(TransactionError),
+    #[fail(display="IO Error: {}", _0)]
+    Io(io::Error),
+    #[fail(display="JSON Error: {}", _0)]
+    Json(serde_json::Error),
+    #[fail(display="Import Error: {}", _0)]
+    Import(String),
+    #[fail(display="Remote Error: {}", _0)]
+    Remote(String),
+}
@@ -0,0 +1,109 @@
+use super::{attr, Attribute, AttributeName, Db, EntityId, Error, Index, Partition, TransactionData, TransactionError, TxBuilder, Value};
+
+use std::collections::BTreeMap;
+
+/// What `Db::excise` removes: an entity with all its attributes, or one
+/// attribute of an entity
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Excision {
+    Entity(EntityId),
+    Attribute(EntityId, AttributeName),
+}
+
+impl Db {
+    /// Permanently removes all datoms of the `excisions`, including
+    /// retracted ones, so they're neither in the current database nor in
+    /// its history, e.g. to comply with a request to erase personal data.
+    ///
+    /// Each excised entity gets an excision entity in its partition with
+    /// `db/excise` referring to it and, unless the whole entity was
+    /// excised, the excised attributes as `db.excise/attrs`. Excising an
+    /// attribute also excises the composite tuple attributes it's a
+    /// component of. These are the
+    /// datoms of the returned transaction. Datoms of other entities
+    /// referring to an excised entity are kept.
+    ///
+    /// Only entities in `Partition::User` and custom partitions can be
+    /// excised, not attributes or transactions. Excising can't be undone
+    /// and rewrites the storage, e.g. vacuums SQLite databases.
+    pub fn excise(&mut self, excisions: &[Excision]) -> Result<TransactionData, Error> {
+        // `None` excises the whole entity
+        let mut targets = BTreeMap::<EntityId, Option<Vec<Attribute>>>::new();
+        for excision in excisions {
+            let entity = match excision {
+                Excision::Entity(entity) | Excision::Attribute(entity, _) => *entity,
+            };
+            match Partition::of(entity) {
+                Some(Partition::User) | Some(Partition::Custom(_)) => (),
+                _ => return Err(TransactionError::InvalidExcision(entity.0).into())
+            }
+
+            match excision {
+                Excision::Entity(_) => { targets.insert(entity, None); },
+                Excision::Attribute(_, attribute_name) => {
+                    let attribute = self.attribute(attribute_name)
+                        .ok_or_else(|| TransactionError::UnknownAttribute(attribute_name.clone()))?;
+                    if let Some(attributes) = targets.entry(entity).or_insert_with(|| Some(vec![])) {
+                        attributes.push(attribute);
+                    }
+                },
+            }
+        }
+
+        // Composite tuples contain the values of their components, so
+        // they're excised along with them
+        let composite_attributes = self.composite_attributes()?;
+        for attributes in targets.values_mut().flatten() {
+            let composites = composite_attributes.iter()
+                .filter(|(composite, components)| !attributes.contains(composite) && components.iter().any(|c| attributes.contains(c)))
+                .map(|(composite, _)| *composite)
+                .collect::<Vec<_>>();
+            attributes.extend(composites);
+        }
+
+        let mut tx = TxBuilder::new();
+        for (entity, attributes) in &targets {
+            let excision = self.tempid_in(Partition::of(*entity).unwrap());
+            tx.add(excision.clone(), "db/excise", Value::Ref(*entity));
+            for attribute in attributes.iter().flatten() {
+                tx.add(excision.clone(), "db.excise/attrs", Value::Ref(attribute.0));
+            }
+        }
+        let (record, data) = self.transaction_datoms(tx)?;
+
+        let targets = targets.into_iter()
+            .flat_map(|(entity, attributes)| match attributes {
+                Some(attributes) => attributes.into_iter().map(|a| (entity, Some(a))).collect(),
+                None => vec![(entity, None)],
+            })
+            .collect::<Vec<_>>();
+        self.excise_datoms(&targets, &record)?;
+
+        Ok(data)
+    }
+
+    /// The entities and attributes excised so far, oldest first
+    pub fn excisions(&self) -> Result<Vec<Excision>, Error> {
+        self.refresh_schema()?;
+        let mut excisions = vec![];
+
+        for datom in self.datoms(Index::Aevt.a(attr::excise))? {
+            let entity = match datom.value.as_ref_id() {
+                Some(entity) => entity,
+                None => continue
+            };
+            let attributes = self.datoms(Index::Eavt.e(datom.entity).a(attr::excise_attrs))?.into_iter()
+                .filter_map(|d| d.value.as_ref_id())
+                .filter_map(|a| self.cached_attribute_name(Attribute(a)))
+                .collect::<Vec<_>>();
+
+            if attributes.is_empty() {
+                excisions.push((datom.tx, Excision::Entity(entity)));
+            }
+            excisions.extend(attributes.into_iter().map(|a| (datom.tx, Excision::Attribute(entity, a))));
+        }
+        excisions.sort_by_key(|(tx, _)| *tx);
+
+        Ok(excisions.into_iter().map(|(_, excision)| excision).collect())
+    }
+}
@@ -0,0 +1,13 @@
+-- Separate from `schema.sql` so databases created before `db/fulltext`
+-- existed get these tables too.
+
+create table if not exists fulltext_attributes (
+  e integer not null unique
+);
+
+-- Current `Value::Str` values of all `db/fulltext` attributes
+create virtual table if not exists fulltext using fts5(
+  v,
+  e unindexed,
+  a unindexed
+);
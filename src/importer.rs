@@ -0,0 +1,230 @@
+use super::{Db, EntityId, Error, Operation, TempId, Value};
+
+use std::collections::BTreeMap;
+use std::time::{Duration, Instant};
+
+const BATCH_SIZE: usize = 10_000;
+
+/// Loads large amounts of operations into a `Db`, started by
+/// `Db::importer`.
+///
+/// Operations are transacted in batches of `batch_size`, each batch
+/// being a regular transaction with its own transaction entity. Tempids
+/// keep referring to the same entity across batches. A transaction reads
+/// the values its assertions replace with a single
+/// `Storage::current_values` call, so the number of reads depends on the
+/// number of batches rather than of operations. The storage may defer
+/// syncing and index maintenance until the import finishes.
+///
+/// If a batch fails, the batches before it stay transacted.
+pub struct Importer<'a> {
+    db: &'a mut Db,
+    batch_size: usize,
+    batch: Vec<Operation>,
+    tempid_mappings: BTreeMap<TempId, EntityId>,
+    stats: ImportStats,
+    started: Instant,
+    finished: bool,
+}
+
+/// Progress of an `Importer`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ImportStats {
+    pub transactions: usize,
+    pub operations: usize,
+    pub elapsed: Duration,
+}
+
+impl ImportStats {
+    pub fn operations_per_second(&self) -> f64 {
+        let seconds = self.elapsed.as_secs() as f64 + f64::from(self.elapsed.subsec_nanos()) / 1e9;
+        self.operations as f64 / seconds.max(1e-9)
+    }
+}
+
+impl Db {
+    /// Starts a bulk import, see `Importer`
+    pub fn importer(&mut self) -> Result<Importer<'_>, Error> {
+        self.begin_bulk()?;
+
+        Ok(Importer {
+            db: self,
+            batch_size: BATCH_SIZE,
+            batch: vec![],
+            tempid_mappings: BTreeMap::new(),
+            stats: ImportStats { transactions: 0, operations: 0, elapsed: Duration::from_secs(0) },
+            started: Instant::now(),
+            finished: false,
+        })
+    }
+}
+
+impl<'a> Importer<'a> {
+    /// Sets the number of operations per transaction
+    pub fn batch_size(mut self, batch_size: usize) -> Self {
+        self.batch_size = batch_size.max(1);
+        self
+    }
+
+    pub fn add<O: Into<Operation>>(&mut self, operation: O) -> Result<(), Error> {
+        let operation = self.resolve(operation.into());
+        self.batch.push(operation);
+
+        if self.batch.len() >= self.batch_size {
+            self.flush()?;
+        }
+        Ok(())
+    }
+
+    pub fn extend<O: Into<Operation>, I: IntoIterator<Item=O>>(&mut self, operations: I) -> Result<(), Error> {
+        for operation in operations {
+            self.add(operation)?;
+        }
+        Ok(())
+    }
+
+    /// The entities of all tempids transacted so far
+    pub fn tempid_mappings(&self) -> &BTreeMap<TempId, EntityId> {
+        &self.tempid_mappings
+    }
+
+    pub fn stats(&self) -> ImportStats {
+        ImportStats { elapsed: self.started.elapsed(), ..self.stats }
+    }
+
+    /// Transacts the remaining operations and ends the import
+    pub fn finish(mut self) -> Result<ImportStats, Error> {
+        self.flush()?;
+        self.finished = true;
+        self.db.end_bulk()?;
+        Ok(self.stats())
+    }
+
+    fn flush(&mut self) -> Result<(), Error> {
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+
+        let operations = self.batch.len();
+        let txd = self.db.transact(self.batch.drain(..))?;
+
+        self.tempid_mappings.extend(txd.tempid_mappings.into_iter().filter(|(tempid, _)| *tempid != TempId::Tx));
+        self.stats.transactions += 1;
+        self.stats.operations += operations;
+        Ok(())
+    }
+
+    /// Replaces tempids allocated by earlier batches with their entities
+    fn resolve(&self, operation: Operation) -> Operation {
+        let entity = |tempid: &TempId| if *tempid == TempId::Tx {
+            None
+        } else {
+            self.tempid_mappings.get(tempid).cloned()
+        };
+
+        match operation {
+            Operation::TempidAssertion(tempid, a, v) => match entity(&tempid) {
+                Some(e) => Operation::Assertion(e, a, v),
+                None => Operation::TempidAssertion(tempid, a, v),
+            },
+            Operation::RefAssertion(e, a, tempid) => match entity(&tempid) {
+                Some(v) => Operation::Assertion(e, a, Value::Ref(v)),
+                None => Operation::RefAssertion(e, a, tempid),
+            },
+            Operation::TempidRefAssertion(tempid, a, v) => match (entity(&tempid), entity(&v)) {
+                (Some(e), Some(v)) => Operation::Assertion(e, a, Value::Ref(v)),
+                (Some(e), None) => Operation::RefAssertion(e, a, v),
+                (None, Some(v)) => Operation::TempidAssertion(tempid, a, Value::Ref(v)),
+                (None, None) => Operation::TempidRefAssertion(tempid, a, v),
+            },
+            operation => operation,
+        }
+    }
+}
+
+impl<'a> Drop for Importer<'a> {
+    fn drop(&mut self) {
+        if !self.finished {
+            let _ = self.db.end_bulk();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {tempid, Assert};
+    use tests::counting::CountingStorage;
+
+    #[test]
+    fn batches_share_tempids() {
+        let mut db = Db::new().unwrap();
+        db.transact(&[(Assert, tempid(), "db/ident", "person/name"),
+                      (Assert, tempid(), "db/ident", "person/friend")]).unwrap();
+
+        let people = (0..25).map(|_| tempid()).collect::<Vec<_>>();
+        let stats = {
+            let mut importer = db.importer().unwrap().batch_size(10);
+            for (i, person) in people.iter().enumerate() {
+                importer.add(&(Assert, person.clone(), "person/name", format!("Person {}", i))).unwrap();
+                importer.add(&(Assert, person.clone(), "person/friend", people[0].clone())).unwrap();
+            }
+            importer.finish().unwrap()
+        };
+        assert_eq!(5, stats.transactions);
+        assert_eq!(50, stats.operations);
+
+        let person = |name: &str| {
+            let name_attribute = db.attribute("person/name").unwrap();
+            db.datoms(::Index::Aevt.a(name_attribute).v(name.into())).unwrap()[0].entity
+        };
+        let (first, last) = (person("Person 0"), person("Person 24"));
+        assert_eq!(db.entity(last).unwrap()["person/friend"], Value::Ref(first));
+        assert_eq!(25, db.datoms(::Index::Aevt.a(db.attribute("person/name").unwrap())).unwrap().len());
+    }
+
+    #[test]
+    fn failing_batch_keeps_earlier_batches() {
+        let mut db = Db::new().unwrap();
+        db.transact(&[(Assert, tempid(), "db/ident", "person/name")]).unwrap();
+
+        {
+            let mut importer = db.importer().unwrap().batch_size(2);
+            importer.add(&(Assert, tempid(), "person/name", "Karl")).unwrap();
+            importer.add(&(Assert, tempid(), "person/name", "Anna")).unwrap();
+            importer.add(&(Assert, tempid(), "person/name", "Otto")).unwrap();
+            assert!(importer.add(&(Assert, tempid(), "unknown/attribute", "x")).is_err());
+        }
+
+        assert_eq!(2, db.datoms(::Index::Aevt.a(db.attribute("person/name").unwrap())).unwrap().len());
+        // The storage is usable again after the import
+        db.transact(&[(Assert, tempid(), "person/name", "Otto")]).unwrap();
+    }
+
+    #[test]
+    fn batch_reads_replaced_values_at_once() {
+        let (storage, calls) = CountingStorage::new();
+        let mut db = Db::with_storage(storage).unwrap();
+        db.transact(&[(Assert, tempid(), "db/ident", "person/name")]).unwrap();
+        let people = (0..100).map(|i| (Assert, tempid(), "person/name", format!("Person {}", i))).collect::<Vec<_>>();
+        let people = db.transact(&people).unwrap().tempid_mappings.into_iter()
+            .filter(|(tempid, _)| *tempid != TempId::Tx)
+            .map(|(_, eid)| eid)
+            .collect::<Vec<_>>();
+
+        let mut reads = |batch_size| {
+            let before = calls.reads();
+            let mut importer = db.importer().unwrap().batch_size(batch_size);
+            for person in &people {
+                importer.add(&(Assert, *person, "person/name", "Renamed")).unwrap();
+            }
+            importer.finish().unwrap();
+            calls.reads() - before
+        };
+
+        // Reads depend on the number of transactions, not of operations
+        let single_batch = reads(100);
+        assert!(single_batch < 10);
+        assert_eq!(reads(10), 10 * single_batch);
+    }
+}
@@ -5,12 +5,14 @@ pub enum Index {
     Eavt,
     Aevt,
     Avet,
+    /// Datoms with `Value::Ref` values, for finding references to an entity
+    Vaet,
 }
 
 impl Index {
     pub fn e(self, e: EntityId)  -> FilteredIndex { FilteredIndex::new(self).e(e) }
     // TODO: Allow passing `AttributeName`
-    pub fn a(self, a: Attribute) -> FilteredIndex { FilteredIndex::new(self).a(a) }
+    pub fn a<A: Into<Attribute>>(self, a: A) -> FilteredIndex { FilteredIndex::new(self).a(a) }
     pub fn v(self, v: Value)     -> FilteredIndex { FilteredIndex::new(self).v(v) }
     pub fn t(self, t: TxId)      -> FilteredIndex { FilteredIndex::new(self).t(t) }
 }
@@ -31,7 +33,8 @@ impl FilteredIndex {
     }
     
     pub fn e(mut self, e: EntityId)  -> Self { self.e = Some(e); self }
-    pub fn a(mut self, a: Attribute) -> Self { self.a = Some(a); self }
+    /// An `Attribute` or a typed `&Attr`
+    pub fn a<A: Into<Attribute>>(mut self, a: A) -> Self { self.a = Some(a.into()); self }
     pub fn v(mut self, v: Value)     -> Self { self.v = Some(v); self }
     pub fn t(mut self, t: TxId)      -> Self { self.t = Some(t); self }
 
@@ -4,24 +4,81 @@
 #[macro_use] extern crate serde_derive;
 extern crate chrono;
 extern crate rusqlite;
-extern crate serde;
-extern crate serde_json;
+#[macro_use] extern crate serde;
+#[cfg_attr(any(test, feature = "server", feature = "client"), macro_use)] extern crate serde_json;
 #[macro_use] extern crate failure;
+#[cfg(feature = "derive")]
+extern crate hellschreiber_derive;
 
 mod index;
 pub use index::*;
 
 mod transaction;
-pub use transaction::{Assert, Retract, Operation, TransactionError, TransactionData};
+pub use transaction::{Assert, Retract, Operation, LookupRef, IntoOperations, TransactionError, TransactionData};
+
+mod tx_builder;
+pub use tx_builder::{TxBuilder, EntityBuilder, TxEntity, TxValue};
 
 mod entity;
 pub use entity::Entity;
+use entity::resolve_enum_values;
+
+mod typed_attr;
+pub use typed_attr::Attr;
 
 mod value;
 pub use value::Value;
 
-mod sqlite;
-pub use sqlite::Db;
+mod error;
+pub use error::Error;
+
+mod db;
+pub use db::Db;
+
+mod migration;
+pub use migration::Migration;
+
+mod partition;
+
+mod excision;
+pub use excision::Excision;
+
+mod predicates;
+
+mod schema_cache;
+
+mod importer;
+pub use importer::{Importer, ImportStats};
+
+mod database;
+pub use database::Database;
+
+pub mod storage;
+pub use storage::{Storage, SqliteStorage, MemoryStorage, SegmentStorage};
+
+pub mod edn;
+
+mod entity_map;
+pub use entity_map::{EntityMaps, EntityMapError};
+
+mod de;
+pub use de::{FieldNames, DeserializeError};
+
+mod model;
+pub use model::{Schema, FromEntity, IntoTx, FromValue, FromEntityError};
+
+#[cfg(feature = "derive")]
+pub use hellschreiber_derive::{Schema, FromEntity, IntoTx};
+
+#[cfg(feature = "server")]
+mod server;
+#[cfg(feature = "server")]
+pub use server::{Server, ServerError};
+
+#[cfg(feature = "client")]
+mod client;
+#[cfg(feature = "client")]
+pub use client::Client;
 
 use std::collections::{BTreeMap, BTreeSet};
 use std::sync::atomic;
@@ -29,9 +86,21 @@ use std::sync::atomic;
 #[derive(Debug, PartialEq, Eq, Clone, Copy, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct EntityId(i64);
 
+impl From<i64> for EntityId {
+    fn from(id: i64) -> Self {
+        EntityId(id)
+    }
+}
+
+impl From<EntityId> for i64 {
+    fn from(eid: EntityId) -> Self {
+        eid.0
+    }
+}
+
 pub type TxId = EntityId;
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy, PartialOrd, Ord, Hash)]
+#[derive(Debug, PartialEq, Eq, Clone, Copy, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub struct Attribute(EntityId);
 pub type AttributeName = String;
 
@@ -43,6 +112,11 @@ impl Attribute {
             || x == attr::ident
             || x == attr::doc
             || x == attr::cardinality_many
+            || x == attr::tuple_attrs
+            || x == attr::unique
+            || x == attr::fulltext
+            || x == attr::attr_preds
+            || x == attr::enumerated
     }
 }
 
@@ -74,8 +148,42 @@ pub struct Datom {
     pub status:    Status,
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Copy, PartialOrd, Ord)]
-pub struct TempId(pub i64);
+/// A placeholder for an entity which is allocated when a transaction
+/// using it is transacted. All uses of the same tempid in a transaction
+/// refer to the same entity.
+#[derive(Debug, PartialEq, Eq, Clone, PartialOrd, Ord, Serialize, Deserialize)]
+pub enum TempId {
+    /// Resolves to the entity of the transaction it's used in, to assert
+    /// facts about the transaction itself
+    Tx,
+    /// A number chosen by the caller
+    Id(i64),
+    /// Created by `TempId::named`, identified by its name
+    Named(String),
+    /// Allocated by `tempid()`, never equal to another tempid it returned
+    /// in the process
+    Generated(u64),
+    /// Allocated by `Db::tempid` or `Db::tempid_in`, never equal to another
+    /// tempid allocated by the same `Db`. Its entity is allocated in the
+    /// partition, or in the default one if it's `None`.
+    Allocated(u64, Option<Partition>),
+}
+
+impl TempId {
+    /// A tempid identified by `name`, so operations built independently
+    /// can refer to the same new entity without passing a tempid around.
+    pub fn named(name: &str) -> TempId {
+        TempId::Named(name.to_string())
+    }
+
+    /// The partition a tempid from `Db::tempid_in` is allocated in
+    fn partition(&self) -> Option<Partition> {
+        match *self {
+            TempId::Allocated(_, partition) => partition,
+            _ => None,
+        }
+    }
+}
 
 pub type Datoms<'a> = Vec<Datom>;
 
@@ -87,6 +195,19 @@ pub(crate) mod attr {
     pub const doc:              Attribute = Attribute(EntityId(12));
     pub const tx_instant:       Attribute = Attribute(EntityId(13));
     pub const cardinality_many: Attribute = Attribute(EntityId(14));
+    pub const tuple_attrs:      Attribute = Attribute(EntityId(15));
+    pub const unique:           Attribute = Attribute(EntityId(16));
+    pub const fulltext:         Attribute = Attribute(EntityId(17));
+    pub const migration:        Attribute = Attribute(EntityId(18));
+    pub const alias:            Attribute = Attribute(EntityId(19));
+    pub const partition:        Attribute = Attribute(EntityId(20));
+    pub const attr_preds:       Attribute = Attribute(EntityId(21));
+    pub const entity_attrs:     Attribute = Attribute(EntityId(22));
+    pub const entity_preds:     Attribute = Attribute(EntityId(23));
+    pub const ensure:           Attribute = Attribute(EntityId(24));
+    pub const enumerated:       Attribute = Attribute(EntityId(25));
+    pub const excise:           Attribute = Attribute(EntityId(26));
+    pub const excise_attrs:     Attribute = Attribute(EntityId(27));
 }
 
 fn seed_datoms() -> Datoms<'static> {
@@ -95,6 +216,19 @@ fn seed_datoms() -> Datoms<'static> {
      (attr::doc,              "db/doc"),
      (attr::tx_instant,       "db/tx_instant"),
      (attr::cardinality_many, "db.cardinality/many"),
+     (attr::tuple_attrs,      "db/tuple_attrs"),
+     (attr::unique,           "db/unique"),
+     (attr::fulltext,         "db/fulltext"),
+     (attr::migration,        "db/migration"),
+     (attr::alias,            "db/alias"),
+     (attr::partition,        "db/partition"),
+     (attr::attr_preds,       "db/attr_preds"),
+     (attr::entity_attrs,     "db.entity/attrs"),
+     (attr::entity_preds,     "db.entity/preds"),
+     (attr::ensure,           "db/ensure"),
+     (attr::enumerated,       "db/enum"),
+     (attr::excise,           "db/excise"),
+     (attr::excise_attrs,     "db.excise/attrs"),
     ].iter()
      .map(|(attr, ident)| {
          Datom {
@@ -104,44 +238,148 @@ fn seed_datoms() -> Datoms<'static> {
              tx: EntityId(0),
              status: Status::Asserted,
          }
-     }).collect::<Vec<Datom>>()
+     })
+     // An attribute keeps all idents it was renamed from, attributes and
+     // entity specs can have several predicates and an excision several
+     // attributes
+     .chain([attr::alias, attr::attr_preds, attr::entity_attrs, attr::entity_preds, attr::excise_attrs].iter().map(|attr| Datom {
+         entity: attr.0,
+         attribute: attr::cardinality_many,
+         value: Value::Bool(true),
+         tx: EntityId(0),
+         status: Status::Asserted,
+     }))
+     .collect::<Vec<Datom>>()
 }
 
 lazy_static! {
-    static ref LATEST_TEMPID: atomic::AtomicIsize  = 100.into();
+    static ref LATEST_TEMPID: atomic::AtomicU64 = 0.into();
 }
 
+/// A new tempid which is distinct from all other generated tempids and
+/// from tempids chosen with `TempId::Id` or `TempId::named`. `Db::tempid`
+/// allocates tempids scoped to a `Db` instead.
 pub fn tempid() -> TempId {
-    let i = LATEST_TEMPID.fetch_add(1, atomic::Ordering::SeqCst);
-    TempId(i as i64)
+    TempId::Generated(LATEST_TEMPID.fetch_add(1, atomic::Ordering::SeqCst))
 }
 
-#[repr(u64)]
-#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+/// A range of consecutive entity ids. Attributes are allocated in `Db`,
+/// transaction entities in `Tx` and all other entities in `User`, unless
+/// they're explicitly allocated in a `Custom` partition, see
+/// `Db::create_partition` and `Db::tempid_in`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
 pub enum Partition {
-    Db   = 2 << 10,
-    Tx   = 2 << 32,
-    User = 2 << 48,
+    Db,
+    Tx,
+    User,
+    /// User-defined partitions of 2^30 ids each, after `User`
+    Custom(u32),
 }
 
+const CUSTOM_PARTITIONS_START: i64 = 2 << 49;
+const CUSTOM_PARTITION_SIZE: i64 = 1 << 30;
+
 impl Partition {
-    #[allow(unused)]
-    fn contains(&self, eid: EntityId) -> bool {
-        let i = *self as i64;
-        (i & eid.0) == i
+    /// The first entity id of the partition
+    pub fn start(&self) -> EntityId {
+        EntityId(match *self {
+            Partition::Db        => 2 << 10,
+            Partition::Tx        => 2 << 32,
+            Partition::User      => 2 << 48,
+            Partition::Custom(n) => CUSTOM_PARTITIONS_START + i64::from(n) * CUSTOM_PARTITION_SIZE,
+        })
+    }
+
+    /// The first entity id after the partition
+    pub fn end(&self) -> EntityId {
+        match *self {
+            Partition::Db        => Partition::Tx.start(),
+            Partition::Tx        => Partition::User.start(),
+            Partition::User      => Partition::Custom(0).start(),
+            Partition::Custom(_) => EntityId(self.start().0 + CUSTOM_PARTITION_SIZE),
+        }
+    }
+
+    pub fn contains(&self, eid: EntityId) -> bool {
+        self.start() <= eid && eid < self.end()
+    }
+
+    /// The partition of `eid`, `None` for the built-in attributes
+    pub fn of(eid: EntityId) -> Option<Partition> {
+        [Partition::Db, Partition::Tx, Partition::User].iter().cloned()
+            .find(|p| p.contains(eid))
+            .or_else(|| {
+                let n = (eid.0 - CUSTOM_PARTITIONS_START) / CUSTOM_PARTITION_SIZE;
+                if eid.0 >= CUSTOM_PARTITIONS_START && n <= i64::from(u32::MAX) {
+                    Some(Partition::Custom(n as u32))
+                } else {
+                    None
+                }
+            })
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone)]
 pub struct AttributeInfo {
     // pub entity: EntityId,
     pub cardinality_many: bool,
-    pub doc: Option<String>
+    pub doc: Option<String>,
+    pub unique: bool,
+    /// Component attributes if this is a composite tuple attribute
+    pub tuple_attrs: Option<Vec<Attribute>>,
+    pub fulltext: bool,
+    /// Names of the predicates values have to satisfy, see
+    /// `Db::register_attr_pred`
+    pub preds: Vec<String>,
+    /// Whether values are refs to ident entities, which are asserted and
+    /// read as their idents
+    pub enumerated: bool,
 }
 
 #[cfg(test)]
 pub mod tests {
-    mod db;
     mod data;
     mod usage;
+    pub(crate) mod counting;
+
+    // The database tests run against every storage backend
+
+    mod sqlite {
+        fn new_db() -> ::Db {
+            ::Db::new().unwrap()
+        }
+
+        mod db { include!("tests/db.rs"); }
+    }
+
+    mod memory {
+        fn new_db() -> ::Db {
+            ::Db::with_storage(::MemoryStorage::new()).unwrap()
+        }
+
+        mod db { include!("tests/db.rs"); }
+    }
+
+    mod segment {
+        fn new_db() -> ::Db {
+            let mut storage = ::SegmentStorage::open(super::temp_dir()).unwrap();
+            // Index often to test reading from and merging segments
+            storage.set_index_threshold(8);
+            ::Db::with_storage(storage).unwrap()
+        }
+
+        mod db { include!("tests/db.rs"); }
+    }
+
+    /// A new directory for a test database
+    pub(crate) fn temp_dir() -> ::std::path::PathBuf {
+        use std::sync::atomic::{AtomicUsize, Ordering};
+        static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+        let dir = ::std::env::temp_dir().join(format!("hellschreiber-{}-{}",
+                                                      ::std::process::id(),
+                                                      COUNTER.fetch_add(1, Ordering::SeqCst)));
+        let _ = ::std::fs::remove_dir_all(&dir);
+        dir
+    }
 }
@@ -0,0 +1,57 @@
+use super::{attr, Db, Error, Index, Operation, TempId, TransactionData, TxId, Value};
+
+/// A named change to the database, usually to its schema, which
+/// `Db::migrate` applies exactly once.
+#[derive(Debug, Clone)]
+pub struct Migration {
+    pub name: String,
+    pub operations: Vec<Operation>,
+}
+
+impl Migration {
+    pub fn new<N, O, I>(name: N, operations: I) -> Self
+        where N: Into<String>, O: Into<Operation>, I: IntoIterator<Item=O>
+    {
+        Migration {
+            name: name.into(),
+            operations: operations.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+
+impl Db {
+    /// Applies the `migrations` which haven't been applied yet in order,
+    /// each in its own transaction. The name of a migration is asserted as
+    /// `db/migration` on its transaction entity, so running the same
+    /// migrations again doesn't change the database.
+    ///
+    /// Returns the transactions of the migrations which were applied.
+    /// Stops at the first failing migration.
+    pub fn migrate(&mut self, migrations: &[Migration]) -> Result<Vec<TransactionData>, Error> {
+        let mut applied = vec![];
+
+        for migration in migrations {
+            if self.lookup("db/migration", migration.name.as_str())?.is_some() {
+                continue;
+            }
+
+            let mut operations = migration.operations.clone();
+            operations.push(Operation::TempidAssertion(TempId::Tx, "db/migration".into(), Value::from(migration.name.as_str())));
+            applied.push(self.transact(operations)?);
+        }
+
+        Ok(applied)
+    }
+
+    /// The names of all applied migrations and the transactions which
+    /// applied them, oldest first. `db/tx_instant` of the transaction
+    /// entity is the time a migration ran.
+    pub fn migrations(&self) -> Result<Vec<(String, TxId)>, Error> {
+        let mut migrations = self.datoms(Index::Aevt.a(attr::migration))?.into_iter()
+            .filter_map(|d| Some((d.value.as_string()?, d.entity)))
+            .collect::<Vec<_>>();
+        migrations.sort_by_key(|(_, tx)| *tx);
+
+        Ok(migrations)
+    }
+}
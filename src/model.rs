@@ -0,0 +1,69 @@
+use super::{Entity, EntityId, Operation, TempId, Value};
+use chrono;
+
+/// Rust types whose fields are stored as attributes. Usually derived
+/// via `#[derive(Schema)]`.
+pub trait Schema {
+    /// Operations asserting the `db/ident` (and `db/doc`,
+    /// `db.cardinality/many`) of every attribute of the type.
+    fn schema() -> Vec<Operation>;
+}
+
+/// Conversion from an `Entity`. Usually derived via
+/// `#[derive(FromEntity)]`.
+pub trait FromEntity: Sized {
+    fn from_entity(entity: &Entity) -> Result<Self, FromEntityError>;
+}
+
+/// Conversion to `Operation`s asserting all attributes of a value for a
+/// new entity. Usually derived via `#[derive(IntoTx)]`.
+pub trait IntoTx {
+    #[allow(clippy::wrong_self_convention)]
+    fn into_tx(&self, entity: TempId) -> Vec<Operation>;
+}
+
+#[derive(Debug, Fail, PartialEq, Eq)]
+pub enum FromEntityError {
+    #[fail(display = "Entity has no value for attribute {}", _0)]
+    MissingAttribute(String),
+    #[fail(display = "Invalid value {:?} for attribute {}", _1, _0)]
+    InvalidValue(String, Value),
+}
+
+/// Conversion of single `Value`s to Rust types, used by the code
+/// generated by `#[derive(FromEntity)]`.
+pub trait FromValue: Sized {
+    fn from_value(value: &Value) -> Option<Self>;
+}
+
+impl FromValue for Value {
+    fn from_value(value: &Value) -> Option<Self> { Some(value.clone()) }
+}
+
+impl FromValue for bool {
+    fn from_value(value: &Value) -> Option<Self> {
+        if let Value::Bool(b) = value { Some(*b) } else { None }
+    }
+}
+
+impl FromValue for String {
+    fn from_value(value: &Value) -> Option<Self> { value.as_string() }
+}
+
+impl FromValue for i64 {
+    fn from_value(value: &Value) -> Option<Self> { value.as_int() }
+}
+
+impl FromValue for EntityId {
+    fn from_value(value: &Value) -> Option<Self> {
+        if let Value::Ref(eid) = value { Some(*eid) } else { None }
+    }
+}
+
+impl FromValue for chrono::DateTime<chrono::Utc> {
+    fn from_value(value: &Value) -> Option<Self> { value.as_datetime() }
+}
+
+impl FromValue for Vec<Value> {
+    fn from_value(value: &Value) -> Option<Self> { value.as_tuple().map(|t| t.to_vec()) }
+}
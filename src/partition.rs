@@ -0,0 +1,57 @@
+use super::{attr, Assert, Attribute, Db, Error, Index, Partition, Value};
+
+impl Db {
+    /// Declares a custom partition named `name`, or returns the partition
+    /// if it's already declared.
+    ///
+    /// A partition is an entity with a `db/ident` and the number of its
+    /// `Partition::Custom` as `db/partition`, so it can also be declared in
+    /// a regular transaction. Use `Db::tempid_in` to allocate entities in
+    /// it.
+    pub fn create_partition(&mut self, name: &str) -> Result<Partition, Error> {
+        if let Some(partition) = self.partition(name)? {
+            return Ok(partition);
+        }
+
+        let next = self.partitions()?.into_iter()
+            .filter_map(|(_, partition)| match partition {
+                Partition::Custom(n) => Some(i64::from(n) + 1),
+                _ => None
+            })
+            .max()
+            .unwrap_or(0);
+
+        let entity = self.tempid();
+        self.transact(&[(Assert, entity.clone(), "db/ident", Value::from(name)),
+                        (Assert, entity, "db/partition", Value::Int(next))])?;
+
+        Ok(Partition::Custom(next as u32))
+    }
+
+    /// The custom partition declared as `name`
+    pub fn partition(&self, name: &str) -> Result<Option<Partition>, Error> {
+        let entity = match self.lookup("db/ident", name)? {
+            Some(entity) => entity,
+            None => return Ok(None)
+        };
+
+        Ok(self.datoms(Index::Eavt.e(entity).a(attr::partition))?.into_iter()
+           .filter_map(|d| d.value.as_int())
+           .map(|n| Partition::Custom(n as u32))
+           .next())
+    }
+
+    /// The names of all custom partitions and their partitions
+    pub fn partitions(&self) -> Result<Vec<(String, Partition)>, Error> {
+        self.refresh_schema()?;
+        let mut partitions = vec![];
+        for datom in self.datoms(Index::Aevt.a(attr::partition))? {
+            if let (Some(name), Some(n)) = (self.cached_attribute_name(Attribute(datom.entity)), datom.value.as_int()) {
+                partitions.push((name, Partition::Custom(n as u32)));
+            }
+        }
+        partitions.sort_by_key(|(_, partition)| *partition);
+
+        Ok(partitions)
+    }
+}
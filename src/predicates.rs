@@ -0,0 +1,153 @@
+use super::{attr, resolve_enum_values, Attribute, Datom, Db, Entity, EntityId, Error, Index, Status, TransactionError, Value};
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+
+type AttributePredicate = Box<dyn Fn(&Value) -> bool + Send>;
+type EntityPredicate = Box<dyn Fn(&Entity) -> bool + Send>;
+
+/// Named predicates which can be referred to by `db/attr_preds` of an
+/// attribute and `db.entity/preds` of an entity spec.
+///
+/// Predicates are code, so they aren't stored in the database and have to
+/// be registered with `Db::register_attr_pred` and
+/// `Db::register_entity_pred` whenever a `Db` is opened. Transactions
+/// using an unregistered predicate fail with
+/// `TransactionError::UnknownPredicate`.
+pub(crate) struct Predicates {
+    attribute: HashMap<String, AttributePredicate>,
+    entity: HashMap<String, EntityPredicate>,
+}
+
+impl Default for Predicates {
+    fn default() -> Self {
+        let mut predicates = Predicates { attribute: HashMap::new(), entity: HashMap::new() };
+
+        predicates.attribute.insert("db.pred/non_negative".into(), Box::new(|v| match v.as_int() { Some(i) => i >= 0, None => false }));
+        predicates.attribute.insert("db.pred/positive".into(), Box::new(|v| match v.as_int() { Some(i) => i > 0, None => false }));
+        predicates.attribute.insert("db.pred/non_empty".into(), Box::new(|v| match v {
+            Value::Str(s) => !s.is_empty(),
+            Value::Tuple(values) => !values.is_empty(),
+            _ => true
+        }));
+
+        predicates
+    }
+}
+
+impl fmt::Debug for Predicates {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Predicates")
+            .field("attribute", &self.attribute.keys().collect::<Vec<_>>())
+            .field("entity", &self.entity.keys().collect::<Vec<_>>())
+            .finish()
+    }
+}
+
+impl Db {
+    /// Registers a predicate which attributes can require of their values
+    /// by asserting `name` as `db/attr_preds`
+    pub fn register_attr_pred<F>(&mut self, name: &str, predicate: F)
+        where F: Fn(&Value) -> bool + Send + 'static
+    {
+        self.predicates.attribute.insert(name.to_string(), Box::new(predicate));
+    }
+
+    /// Registers a predicate which entity specs can require of entities by
+    /// asserting `name` as `db.entity/preds`. The entity passed to the
+    /// predicate has the values as of after the transaction, its `db` is
+    /// the database before the transaction.
+    pub fn register_entity_pred<F>(&mut self, name: &str, predicate: F)
+        where F: Fn(&Entity) -> bool + Send + 'static
+    {
+        self.predicates.entity.insert(name.to_string(), Box::new(predicate));
+    }
+
+    /// Checks the `db/attr_preds` of the values asserted by `datoms` and
+    /// the specs `ensure`d for entities
+    pub(crate) fn check_predicates(&self, datoms: &[Datom], ensure: &[(EntityId, Value)]) -> Result<(), Error> {
+        for datom in datoms.iter().filter(|d| d.status.is_assertion()) {
+            let info = self.attribute_info_for(datom.attribute)?;
+            for name in &info.preds {
+                let predicate = self.predicates.attribute.get(name)
+                    .ok_or_else(|| TransactionError::UnknownPredicate(name.clone()))?;
+
+                if !predicate(&datom.value) {
+                    let attribute_name = self.cached_attribute_name(datom.attribute).unwrap_or_default();
+                    return Err(TransactionError::AttributePredicate(attribute_name, name.clone(), format!("{:?}", datom.value)).into())
+                }
+            }
+        }
+
+        for (entity, spec) in ensure {
+            self.check_spec(*entity, spec, datoms)?;
+        }
+
+        Ok(())
+    }
+
+    fn check_spec(&self, entity: EntityId, spec: &Value, datoms: &[Datom]) -> Result<(), Error> {
+        let spec_entity = match spec {
+            Value::Ref(eid) => Some(*eid),
+            Value::Str(ident) => self.cached_lookup("db/ident", ident.as_str())?,
+            _ => None
+        };
+        let spec_datoms = match spec_entity {
+            Some(spec_entity) => self.datoms(Index::Eavt.e(spec_entity))?,
+            None => vec![]
+        };
+        let spec_name = spec_datoms.iter()
+            .find(|d| d.attribute == attr::ident)
+            .and_then(|d| d.value.as_string())
+            .unwrap_or_else(|| format!("{:?}", spec));
+
+        let required = spec_datoms.iter()
+            .filter(|d| d.attribute == attr::entity_attrs)
+            .filter_map(|d| d.value.as_ref_id().map(Attribute))
+            .collect::<Vec<_>>();
+        let predicates = spec_datoms.iter()
+            .filter(|d| d.attribute == attr::entity_preds)
+            .filter_map(|d| d.value.as_string())
+            .collect::<Vec<_>>();
+        if required.is_empty() && predicates.is_empty() {
+            return Err(TransactionError::UnknownEntitySpec(spec_name).into())
+        }
+
+        let values = self.values_after(entity, datoms)?;
+        for attribute in required {
+            if !values.contains_key(&attribute) {
+                let attribute_name = self.cached_attribute_name(attribute).unwrap_or_default();
+                return Err(TransactionError::MissingAttribute(spec_name, entity.0, attribute_name).into())
+            }
+        }
+
+        let entity_after = Entity { db: self, eid: entity, values };
+        for name in predicates {
+            let predicate = self.predicates.entity.get(&name)
+                .ok_or_else(|| TransactionError::UnknownPredicate(name.clone()))?;
+
+            if !predicate(&entity_after) {
+                return Err(TransactionError::EntityPredicate(spec_name, entity.0, name).into())
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The values of `entity` after storing `datoms`
+    fn values_after(&self, entity: EntityId, datoms: &[Datom]) -> Result<BTreeMap<Attribute, Vec<Value>>, Error> {
+        let mut values = self.entity_values(entity)?;
+
+        for datom in datoms.iter().filter(|d| d.entity == entity) {
+            let attribute_values = values.entry(datom.attribute).or_default();
+            match datom.status {
+                Status::Asserted => attribute_values.push(datom.value.clone()),
+                Status::Retracted(_) => attribute_values.retain(|v| *v != datom.value),
+            }
+        }
+        values.retain(|_, values| !values.is_empty());
+        resolve_enum_values(self, &mut values)?;
+
+        Ok(values)
+    }
+}
@@ -0,0 +1,142 @@
+use super::{attr, Attribute, AttributeInfo, AttributeName, Datom, Index, Value};
+use error::Error;
+use db::attribute_info_from_datoms;
+use storage::Storage;
+
+use std::collections::HashMap;
+
+/// Idents and `AttributeInfo`s of the current schema of a `Db`, so
+/// resolving attributes doesn't query the storage.
+///
+/// Idents are loaded at once, `AttributeInfo`s on first use. The cache is
+/// dropped whenever a transaction changes the schema and reloaded if
+/// `Storage::data_version` shows another process wrote to the storage.
+#[derive(Debug)]
+pub(crate) struct SchemaCache {
+    data_version: u64,
+    /// Idents and the aliases of renamed attributes
+    attributes: HashMap<AttributeName, Attribute>,
+    names: HashMap<Attribute, AttributeName>,
+    infos: HashMap<Attribute, AttributeInfo>,
+}
+
+impl SchemaCache {
+    pub(crate) fn load(storage: &dyn Storage) -> Result<Self, Error> {
+        let data_version = storage.data_version()?;
+        let mut attributes = HashMap::new();
+        let mut names = HashMap::new();
+
+        // Idents take precedence over aliases, and later aliases over
+        // earlier ones
+        let mut aliases = storage.datoms(&Index::Aevt.a(attr::alias), None)?;
+        aliases.sort_by_key(|d| d.tx);
+        for datom in aliases {
+            if let Value::Str(alias) = datom.value {
+                attributes.insert(alias, Attribute(datom.entity));
+            }
+        }
+
+        for datom in storage.datoms(&Index::Aevt.a(attr::ident), None)? {
+            if let Value::Str(ident) = datom.value {
+                attributes.insert(ident.clone(), Attribute(datom.entity));
+                names.insert(Attribute(datom.entity), ident);
+            }
+        }
+
+        Ok(SchemaCache { data_version, attributes, names, infos: HashMap::new() })
+    }
+
+    pub(crate) fn is_stale(&self, storage: &dyn Storage) -> Result<bool, Error> {
+        Ok(storage.data_version()? != self.data_version)
+    }
+
+    pub(crate) fn attribute(&self, attribute_name: &str) -> Option<Attribute> {
+        self.attributes.get(attribute_name).cloned()
+    }
+
+    pub(crate) fn attribute_name(&self, attribute: Attribute) -> Option<AttributeName> {
+        self.names.get(&attribute).cloned()
+    }
+
+    pub(crate) fn attribute_info(&mut self, storage: &dyn Storage, attribute: Attribute) -> Result<AttributeInfo, Error> {
+        if let Some(info) = self.infos.get(&attribute) {
+            return Ok(info.clone());
+        }
+
+        let datoms = storage.datoms(&Index::Eavt.e(attribute.0), None)?;
+        let info = attribute_info_from_datoms(attribute, &datoms);
+        self.infos.insert(attribute, info.clone());
+        Ok(info)
+    }
+
+    /// Whether storing `datoms` changes idents or attribute definitions
+    pub(crate) fn is_affected_by(datoms: &[Datom]) -> bool {
+        datoms.iter().any(|d| d.attribute.is_internal() || d.attribute == attr::alias)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use {Assert, Database, Db};
+    use tests::counting::CountingStorage;
+
+    #[test]
+    fn checks_data_version_once_per_call() {
+        let (storage, calls) = CountingStorage::new();
+        let mut db = Db::with_storage(storage).unwrap();
+        db.transact(&[(Assert, ::tempid(), "db/ident", "person/name"),
+                      (Assert, ::tempid(), "db/ident", "person/nick")]).unwrap();
+        db.attribute("person/name").unwrap();
+
+        let karl = ::tempid();
+        let before = calls.data_version();
+        let data = db.transact(&[(Assert, karl.clone(), "person/name", "Karl"),
+                                 (Assert, karl.clone(), "person/nick", "Kalle"),
+                                 (Assert, ::tempid(), "person/name", "Heinz")]).unwrap();
+        assert_eq!(calls.data_version() - before, 1);
+
+        let before = calls.data_version();
+        let entity = db.entity(data.tempid_mappings[&karl]).unwrap();
+        assert_eq!(entity["person/name"], "Karl".into());
+        assert_eq!(entity["person/nick"], "Kalle".into());
+        assert!(format!("{:?}", entity).contains("Kalle"));
+        assert_eq!(calls.data_version() - before, 1);
+    }
+
+    #[test]
+    fn no_attribute_on_storage_failure() {
+        let (storage, calls) = CountingStorage::new();
+        let mut db = Db::with_storage(storage).unwrap();
+        db.transact(&[(Assert, ::tempid(), "db/ident", "person/name")]).unwrap();
+        let attribute = db.attribute("person/name").unwrap();
+
+        calls.fail();
+        assert_eq!(None, db.attribute("person/name"));
+        assert_eq!(None, db.attribute_name(attribute));
+
+        // Loading the cache fails as well
+        let (storage, calls) = CountingStorage::new();
+        let mut db = Db::with_storage(storage).unwrap();
+        db.transact(&[(Assert, ::tempid(), "db/ident", "person/name")]).unwrap();
+        calls.fail();
+        assert_eq!(None, db.attribute("person/name"));
+        assert_eq!(None, Database::attribute(&db, "person/name"));
+        assert_eq!(None, Database::attribute_name(&db, attribute));
+    }
+
+    #[test]
+    fn reload_after_external_write() {
+        let path = ::tests::temp_dir().with_extension("sqlite");
+        let mut writer = Db::open(&path).unwrap();
+        let reader = Db::open(&path).unwrap();
+
+        assert!(reader.attribute("person/name").is_none());
+        writer.transact(&[(Assert, ::tempid(), "db/ident", "person/name")]).unwrap();
+        assert_eq!(writer.attribute("person/name"), reader.attribute("person/name"));
+        assert!(!reader.attribute_info("person/name").unwrap().unique);
+
+        let attribute = writer.attribute("person/name").unwrap();
+        writer.transact(&[(Assert, attribute.0, "db/unique", true)]).unwrap();
+        assert!(reader.attribute_info("person/name").unwrap().unique);
+    }
+}
@@ -0,0 +1,331 @@
+extern crate tiny_http;
+
+use super::{Db, Datom, Entity, EntityId, EntityMaps, EntityMapError, FilteredIndex, Index, Operation, Value};
+use error::Error;
+
+use serde_json::{self, Value as Json};
+use std::io;
+use std::net::{SocketAddr, ToSocketAddrs};
+
+#[derive(Debug, Fail)]
+pub enum ServerError {
+    #[fail(display = "Failed to start server: {}", _0)]
+    Start(String),
+    #[fail(display = "IO Error: {}", _0)]
+    Io(io::Error),
+}
+
+/// HTTP server exposing a `Db` with JSON bodies. Values are serialized
+/// like `Value` (e.g. `{"Str": "Karl"}`), entities as maps from attribute
+/// ident to value (an array of values for `db.cardinality/many`).
+///
+/// - `POST /transact`: Entity maps as accepted by `EntityMaps::from_json`.
+///   Returns `{"tx": tx, "tempids": {"name": eid}}`.
+/// - `POST /operations`: A list of serialized `Operation`s. Returns the
+///   serialized `TransactionData`.
+/// - `GET /entity/<eid>`: The entity with `db/id`.
+/// - `POST /datoms`: `{"index": "aevt", "e": eid, "a": "ident", "v":
+///   value, "t": tx}`, all but `index` optional. Returns a list of
+///   `{"e", "a", "v", "tx", "added"}`.
+/// - `POST /query`: `{"attribute": "ident", "value": value}` or
+///   `{"attribute": "ident", "fulltext": "query"}`. Returns the list of
+///   matching entities.
+/// - `GET /tx-log`: List of `{"t": tx, "datoms": [...]}`.
+///
+/// Errors are returned as `{"error": "message"}` with status 400 for
+/// invalid requests and transactions, 404 if nothing is found and 500 if
+/// e.g. the storage fails.
+pub struct Server {
+    db: Db,
+    http: tiny_http::Server,
+}
+
+struct HttpError(u16, String);
+
+impl From<Error> for HttpError {
+    fn from(e: Error) -> Self {
+        let status = match e {
+            Error::TransactionError(_) | Error::Json(_) | Error::Import(_) => 400,
+            _ => 500,
+        };
+        HttpError(status, e.to_string())
+    }
+}
+
+impl From<EntityMapError> for HttpError {
+    fn from(e: EntityMapError) -> Self {
+        HttpError(400, e.to_string())
+    }
+}
+
+impl From<serde_json::Error> for HttpError {
+    fn from(e: serde_json::Error) -> Self {
+        HttpError(400, e.to_string())
+    }
+}
+
+/// Reading the request body failed
+impl From<io::Error> for HttpError {
+    fn from(e: io::Error) -> Self {
+        HttpError(400, e.to_string())
+    }
+}
+
+impl Server {
+    pub fn new<A: ToSocketAddrs>(db: Db, addr: A) -> Result<Self, ServerError> {
+        let http = tiny_http::Server::http(addr)
+            .map_err(|e| ServerError::Start(e.to_string()))?;
+        Ok(Server { db, http })
+    }
+
+    /// The address the server is listening on, useful when binding to
+    /// port 0.
+    pub fn local_addr(&self) -> Option<SocketAddr> {
+        self.http.server_addr().to_ip()
+    }
+
+    /// Handles requests until the process exits.
+    pub fn run(mut self) -> Result<(), ServerError> {
+        loop {
+            let mut request = self.http.recv().map_err(ServerError::Io)?;
+
+            let (status, body) = match self.handle(&mut request) {
+                Ok(json) => (200, json),
+                Err(HttpError(status, message)) => (status, json!({ "error": message })),
+            };
+
+            let content_type = tiny_http::Header::from_bytes(&b"Content-Type"[..], &b"application/json"[..])
+                .expect("Valid header");
+            let response = tiny_http::Response::from_string(body.to_string())
+                .with_status_code(status)
+                .with_header(content_type);
+
+            // The client might have gone away, which isn't our problem
+            let _ = request.respond(response);
+        }
+    }
+
+    fn handle(&mut self, request: &mut tiny_http::Request) -> Result<Json, HttpError> {
+        use self::tiny_http::Method::{Get, Post};
+
+        let url = request.url().to_string();
+        let segments = url.trim_matches('/').split('/').collect::<Vec<_>>();
+
+        match (request.method(), &segments[..]) {
+            (Post, ["transact"]) => self.transact(read_json(request)?),
+            (Post, ["operations"]) => {
+                let operations = serde_json::from_value(read_json(request)?)?;
+                Ok(json!(self.db.transact::<Vec<Operation>>(operations)?))
+            },
+            (Get,  ["entity", eid]) => {
+                let eid = eid.parse::<i64>()
+                    .map_err(|_| HttpError(404, format!("Invalid entity id {}", eid)))?;
+                Ok(self.entity_json(&self.db.entity(EntityId(eid))?))
+            },
+            (Post, ["datoms"]) => self.datoms(&read_json(request)?),
+            (Post, ["query"]) => self.query(&read_json(request)?),
+            (Get,  ["tx-log"]) => self.tx_log(),
+            _ => Err(HttpError(404, format!("Not found: {} {}", request.method(), url)))
+        }
+    }
+
+    fn transact(&mut self, json: Json) -> Result<Json, HttpError> {
+        let maps = EntityMaps::from_json(json)?;
+        let txd = self.db.transact(maps.operations)?;
+
+        let tempids = maps.tempids.into_iter()
+            .filter_map(|(name, tempid)| txd.tempid_mappings.get(&tempid).map(|eid| (name, json!(eid))))
+            .collect::<serde_json::Map<_, _>>();
+
+        Ok(json!({ "tx": txd.tx_id, "tempids": tempids }))
+    }
+
+    fn datoms(&self, json: &Json) -> Result<Json, HttpError> {
+        let index = match json["index"].as_str() {
+            Some("eavt") => Index::Eavt,
+            Some("aevt") => Index::Aevt,
+            Some("avet") => Index::Avet,
+            Some("vaet") => Index::Vaet,
+            _ => return Err(HttpError(400, "Expected index eavt, aevt, avet or vaet".into()))
+        };
+
+        let mut filtered = FilteredIndex::new(index);
+        if let Some(e) = json.get("e") {
+            filtered = filtered.e(serde_json::from_value(e.clone())?);
+        }
+        if let Some(a) = json.get("a") {
+            filtered = filtered.a(self.attribute(a)?);
+        }
+        if let Some(v) = json.get("v") {
+            filtered = filtered.v(serde_json::from_value(v.clone())?);
+        }
+        if let Some(t) = json.get("t") {
+            filtered = filtered.t(serde_json::from_value(t.clone())?);
+        }
+
+        let datoms = self.db.datoms(filtered)?;
+        Ok(Json::Array(datoms.iter().map(|d| self.datom_json(d)).collect()))
+    }
+
+    fn query(&self, json: &Json) -> Result<Json, HttpError> {
+        let attribute = self.attribute(&json["attribute"])?;
+        let name = json["attribute"].as_str().unwrap_or_default();
+
+        let eids = if let Some(query) = json["fulltext"].as_str() {
+            self.db.fulltext(name, query)?.into_iter()
+                .map(|(e, _, _)| e)
+                .collect::<Vec<_>>()
+        } else {
+            let value: Value = serde_json::from_value(json["value"].clone())?;
+            self.db.datoms(Index::Aevt.a(attribute).v(value))?.into_iter()
+                .map(|d| d.entity)
+                .collect::<Vec<_>>()
+        };
+
+        eids.into_iter()
+            .map(|e| Ok(self.entity_json(&self.db.entity(e)?)))
+            .collect::<Result<Vec<_>, HttpError>>()
+            .map(Json::Array)
+    }
+
+    fn tx_log(&self) -> Result<Json, HttpError> {
+        let log = self.db.tx_log()?.into_iter()
+            .map(|(t, datoms)| json!({
+                "t": t,
+                "datoms": datoms.iter().map(|d| self.datom_json(d)).collect::<Vec<_>>(),
+            }))
+            .collect();
+        Ok(Json::Array(log))
+    }
+
+    fn attribute(&self, json: &Json) -> Result<super::Attribute, HttpError> {
+        let name = json.as_str()
+            .ok_or_else(|| HttpError(400, format!("Expected attribute ident, got {}", json)))?;
+        self.db.attribute(name)
+            .ok_or_else(|| HttpError(400, format!("Unknown attribute {}", name)))
+    }
+
+    fn datom_json(&self, datom: &Datom) -> Json {
+        json!({
+            "e": datom.entity,
+            "a": self.db.attribute_name(datom.attribute),
+            "v": datom.value,
+            "tx": datom.tx,
+            "added": datom.status.is_assertion(),
+        })
+    }
+
+    fn entity_json(&self, entity: &Entity) -> Json {
+        let mut map = serde_json::Map::new();
+        map.insert("db/id".into(), json!(entity.eid));
+
+        for (attribute, values) in &entity.values {
+            let name = match self.db.attribute_name(*attribute) {
+                Some(name) => name,
+                None => continue
+            };
+            let many = self.db.attribute_info_for(*attribute)
+                .map(|info| info.cardinality_many)
+                .unwrap_or(values.len() > 1);
+
+            let value = if many { json!(values) } else { json!(values[0]) };
+            map.insert(name, value);
+        }
+
+        Json::Object(map)
+    }
+}
+
+fn read_json(request: &mut tiny_http::Request) -> Result<Json, HttpError> {
+    let mut body = String::new();
+    request.as_reader().read_to_string(&mut body)?;
+    Ok(serde_json::from_str(&body)?)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::io::{Read, Write};
+    use std::net::TcpStream;
+    use std::sync::mpsc;
+    use std::thread;
+    use tests::counting::CountingStorage;
+
+    fn start() -> SocketAddr {
+        start_with(Db::new().unwrap())
+    }
+
+    fn start_with(db: Db) -> SocketAddr {
+        let (sender, receiver) = mpsc::channel();
+
+        thread::spawn(move || {
+            let server = Server::new(db, "127.0.0.1:0").unwrap();
+            sender.send(server.local_addr().unwrap()).unwrap();
+            server.run().unwrap();
+        });
+
+        receiver.recv().unwrap()
+    }
+
+    fn request(addr: SocketAddr, method: &str, path: &str, body: Json) -> (u16, Json) {
+        let body = body.to_string();
+        let mut stream = TcpStream::connect(addr).unwrap();
+        write!(stream, "{} {} HTTP/1.0\r\nContent-Length: {}\r\n\r\n{}", method, path, body.len(), body).unwrap();
+
+        let mut response = String::new();
+        stream.read_to_string(&mut response).unwrap();
+
+        let status = response[9..12].parse().unwrap();
+        let body = &response[response.find("\r\n\r\n").unwrap() + 4..];
+        (status, serde_json::from_str(body).unwrap())
+    }
+
+    #[test]
+    fn transact_and_read() {
+        let addr = start();
+
+        let (status, _) = request(addr, "POST", "/transact", json!([
+            {"db/ident": "person/name", "db/unique": true},
+            {"db/ident": "person/tags", "db.cardinality/many": true},
+        ]));
+        assert_eq!(status, 200);
+
+        let (status, tx) = request(addr, "POST", "/transact",
+                                   json!({"db/id": "karl", "person/name": "Karl", "person/tags": ["a", "b"]}));
+        assert_eq!(status, 200);
+        let karl = tx["tempids"]["karl"].as_i64().unwrap();
+
+        let (_, entity) = request(addr, "GET", &format!("/entity/{}", karl), Json::Null);
+        assert_eq!(entity["person/name"], json!({"Str": "Karl"}));
+        assert_eq!(entity["person/tags"], json!([{"Str": "a"}, {"Str": "b"}]));
+
+        let (_, datoms) = request(addr, "POST", "/datoms", json!({"index": "aevt", "a": "person/name"}));
+        assert_eq!(datoms[0]["e"], json!(karl));
+        assert_eq!(datoms[0]["v"], json!({"Str": "Karl"}));
+
+        let (_, entities) = request(addr, "POST", "/query", json!({"attribute": "person/name", "value": {"Str": "Karl"}}));
+        assert_eq!(entities[0]["db/id"], json!(karl));
+
+        let (_, log) = request(addr, "GET", "/tx-log", Json::Null);
+        assert_eq!(log.as_array().unwrap().len(), 2);
+        assert_eq!(log[1]["t"], tx["tx"]);
+    }
+
+    #[test]
+    fn errors() {
+        let addr = start();
+
+        let (status, error) = request(addr, "POST", "/transact", json!({"unknown/attribute": 1}));
+        assert_eq!(status, 400);
+        assert!(error["error"].as_str().unwrap().contains("unknown/attribute"));
+
+        let (status, _) = request(addr, "GET", "/nothing", Json::Null);
+        assert_eq!(status, 404);
+
+        let (storage, calls) = CountingStorage::new();
+        let addr = start_with(Db::with_storage(storage).unwrap());
+        calls.fail();
+        let (status, _) = request(addr, "GET", "/entity/1", Json::Null);
+        assert_eq!(status, 500);
+    }
+}
@@ -0,0 +1,243 @@
+use super::{excised, update_unique, visible, Storage, INDEXED_ATTRIBUTES};
+use {attr, Attribute, Datom, Datoms, EntityId, FilteredIndex, Index, Partition, Status, TxId, Value};
+use error::Error;
+
+use std::collections::{BTreeMap, BTreeSet};
+
+const MIN_EID: EntityId = EntityId(i64::MIN);
+const MIN_VALUE: Value = Value::Bool(false);
+
+/// Storage in sorted in-memory indexes, for tests and ephemeral
+/// databases. Fulltext search uses the default `Storage::fulltext`.
+#[derive(Debug)]
+pub struct MemoryStorage {
+    /// All datoms ever asserted and the transaction which retracted them
+    eavt: BTreeMap<(EntityId, Attribute, Value, TxId), Option<TxId>>,
+    aevt: BTreeSet<(Attribute, EntityId, Value, TxId)>,
+    avet: BTreeSet<(Attribute, Value, EntityId, TxId)>,
+    /// Only datoms with `Value::Ref` values
+    vaet: BTreeSet<(Value, Attribute, EntityId, TxId)>,
+    /// Attributes in the Avet index
+    unique: BTreeSet<Attribute>,
+    /// The highest transaction at `begin_bulk`
+    bulk_start: Option<TxId>,
+}
+
+impl MemoryStorage {
+    pub fn new() -> Self {
+        MemoryStorage {
+            eavt: BTreeMap::new(),
+            aevt: BTreeSet::new(),
+            avet: BTreeSet::new(),
+            vaet: BTreeSet::new(),
+            unique: INDEXED_ATTRIBUTES.iter().cloned().collect(),
+            bulk_start: None,
+        }
+    }
+
+    fn retracted_tx(&self, e: EntityId, a: Attribute, v: &Value, t: TxId) -> Option<TxId> {
+        self.eavt[&(e, a, v.clone(), t)]
+    }
+}
+
+impl Default for MemoryStorage {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Storage for MemoryStorage {
+    fn store_datoms(&mut self, datoms: &[Datom]) -> Result<(), Error> {
+        let (asserted, retracted): (Vec<&Datom>, Vec<&Datom>) = datoms.iter()
+            .partition(|d| d.status.is_assertion());
+
+        let mut inserted = vec![];
+        for d in asserted {
+            let key = (d.entity, d.attribute, d.value.clone(), d.tx);
+            if self.eavt.insert(key.clone(), None).is_none() {
+                inserted.push(key);
+            }
+            self.aevt.insert((d.attribute, d.entity, d.value.clone(), d.tx));
+            self.avet.insert((d.attribute, d.value.clone(), d.entity, d.tx));
+            if d.value.as_ref_id().is_some() {
+                self.vaet.insert((d.value.clone(), d.attribute, d.entity, d.tx));
+            }
+        }
+
+        // Find all retracted datoms before marking any of them, so a
+        // failing retraction doesn't leave a partial transaction behind
+        let mut retractions = vec![];
+        for d in retracted {
+            let start = (d.entity, d.attribute, d.value.clone(), MIN_EID);
+            let keys = self.eavt.range(start..)
+                .take_while(|((e, a, v, _), _)| *e == d.entity && *a == d.attribute && *v == d.value)
+                .filter(|((_, _, _, t), retracted_tx)| *t != d.tx && retracted_tx.is_none())
+                .map(|(key, _)| key.clone())
+                .collect::<Vec<_>>();
+
+            if keys.len() != 1 {
+                for (e, a, v, t) in inserted {
+                    self.aevt.remove(&(a, e, v.clone(), t));
+                    self.avet.remove(&(a, v.clone(), e, t));
+                    self.vaet.remove(&(v.clone(), a, e, t));
+                    self.eavt.remove(&(e, a, v, t));
+                }
+                panic!("Retraction has to match exactly one datom, matched {}. Datom: {:?}", keys.len(), d);
+            }
+
+            let retracted_tx = match d.status {
+                Status::Retracted(tx) => tx,
+                _ => unreachable!()
+            };
+            retractions.push((keys.into_iter().next().unwrap(), retracted_tx));
+        }
+
+        for (key, retracted_tx) in retractions {
+            self.eavt.insert(key, Some(retracted_tx));
+        }
+
+        update_unique(&mut self.unique, datoms);
+
+        Ok(())
+    }
+
+    fn datoms(&self, index: &FilteredIndex, basis: Option<TxId>) -> Result<Datoms<'_>, Error> {
+        let keys: Box<dyn Iterator<Item=(EntityId, Attribute, Value, TxId)>> = match index.index {
+            Index::Eavt => {
+                let start = (index.e.unwrap_or(MIN_EID), index.a.unwrap_or(Attribute(MIN_EID)), MIN_VALUE, MIN_EID);
+                Box::new(self.eavt.range(start..)
+                         .map(|(key, _)| key)
+                         .take_while(move |(e, _, _, _)| match index.e {
+                             Some(i) => i == *e,
+                             None => true,
+                         })
+                         .cloned())
+            },
+            Index::Aevt => {
+                let start = (index.a.unwrap_or(Attribute(MIN_EID)), index.e.unwrap_or(MIN_EID), MIN_VALUE, MIN_EID);
+                Box::new(self.aevt.range(start..)
+                         .take_while(move |(a, _, _, _)| match index.a {
+                             Some(i) => i == *a,
+                             None => true,
+                         })
+                         .map(|(a, e, v, t)| (*e, *a, v.clone(), *t)))
+            },
+            Index::Avet => {
+                let start = (index.a.unwrap_or(Attribute(MIN_EID)), index.v.clone().unwrap_or(MIN_VALUE), MIN_EID, MIN_EID);
+                Box::new(self.avet.range(start..)
+                         .take_while(move |(a, _, _, _)| match index.a {
+                             Some(i) => i == *a,
+                             None => true,
+                         })
+                         .filter(move |(a, _, _, _)| self.unique.contains(a))
+                         .map(|(a, v, e, t)| (*e, *a, v.clone(), *t)))
+            },
+            Index::Vaet => {
+                let start = (index.v.clone().unwrap_or(MIN_VALUE), index.a.unwrap_or(Attribute(MIN_EID)), MIN_EID, MIN_EID);
+                Box::new(self.vaet.range(start..)
+                         .take_while(move |(v, _, _, _)| match index.v {
+                             Some(ref i) => i == v,
+                             None => true,
+                         })
+                         .map(|(v, a, e, t)| (*e, *a, v.clone(), *t)))
+            },
+        };
+
+        Ok(keys
+           .filter(|(e, a, v, t)| visible(*t, self.retracted_tx(*e, *a, v, *t), basis))
+           .map(|(entity, attribute, value, tx)| Datom { entity, attribute, value, tx, status: Status::Asserted })
+           .filter(|d| index.matches(d))
+           .collect())
+    }
+
+    fn excise(&mut self, targets: &[(EntityId, Option<Attribute>)], record: &[Datom]) -> Result<(), Error> {
+        let mut keys = vec![];
+        for (entity, _) in targets {
+            let start = (*entity, Attribute(MIN_EID), MIN_VALUE, MIN_EID);
+            keys.extend(self.eavt.range(start..)
+                        .take_while(|((e, _, _, _), _)| e == entity)
+                        .filter(|((e, a, _, _), _)| excised(targets, *e, *a))
+                        .map(|(key, _)| key.clone()));
+        }
+
+        for (e, a, v, t) in keys {
+            self.aevt.remove(&(a, e, v.clone(), t));
+            self.avet.remove(&(a, v.clone(), e, t));
+            self.vaet.remove(&(v.clone(), a, e, t));
+            self.eavt.remove(&(e, a, v, t));
+        }
+
+        self.store_datoms(record)
+    }
+
+    fn begin_bulk(&mut self) -> Result<(), Error> {
+        self.bulk_start = Some(self.highest_eid(Partition::Tx)?);
+        Ok(())
+    }
+
+    fn end_bulk(&mut self) -> Result<(), Error> {
+        self.bulk_start = None;
+        Ok(())
+    }
+
+    /// Removes the datoms of all transactions after `begin_bulk` and undoes
+    /// their retractions
+    fn abort_bulk(&mut self) -> Result<(), Error> {
+        let start = match self.bulk_start.take() {
+            Some(start) => start,
+            None => return Ok(()),
+        };
+
+        let keys = self.eavt.keys()
+            .filter(|(_, _, _, t)| *t > start)
+            .cloned()
+            .collect::<Vec<_>>();
+        for (e, a, v, t) in keys {
+            self.aevt.remove(&(a, e, v.clone(), t));
+            self.avet.remove(&(a, v.clone(), e, t));
+            self.vaet.remove(&(v.clone(), a, e, t));
+            self.eavt.remove(&(e, a, v, t));
+        }
+        for retracted_tx in self.eavt.values_mut() {
+            if let Some(r) = *retracted_tx {
+                if r > start {
+                    *retracted_tx = None;
+                }
+            }
+        }
+
+        let unique = self.eavt.iter()
+            .filter(|((_, a, v, _), retracted_tx)| *a == attr::unique && *v == Value::Bool(true) && retracted_tx.is_none())
+            .map(|((e, _, _, _), _)| Attribute(*e));
+        self.unique = INDEXED_ATTRIBUTES.iter().cloned().chain(unique).collect();
+
+        Ok(())
+    }
+
+    fn highest_eid(&self, partition: Partition) -> Result<EntityId, Error> {
+        let start = (partition.start(), Attribute(MIN_EID), MIN_VALUE, MIN_EID);
+        let end = (partition.end(), Attribute(MIN_EID), MIN_VALUE, MIN_EID);
+        Ok(self.eavt.range(start..end).next_back()
+           .map(|((e, _, _, _), _)| *e)
+           .unwrap_or_else(|| partition.start()))
+    }
+
+    fn scan_log(&self, f: &mut dyn FnMut(Datom) -> Result<(), Error>) -> Result<(), Error> {
+        let mut log = Vec::with_capacity(self.eavt.len());
+
+        for ((e, a, v, t), retracted_tx) in &self.eavt {
+            log.push(Datom { entity: *e, attribute: *a, value: v.clone(), tx: *t, status: Status::Asserted });
+            if let Some(r) = *retracted_tx {
+                log.push(Datom { entity: *e, attribute: *a, value: v.clone(), tx: r, status: Status::Retracted(r) });
+            }
+        }
+
+        log.sort_by_key(|d| (d.tx, !d.status.is_assertion()));
+
+        for datom in log {
+            f(datom)?;
+        }
+
+        Ok(())
+    }
+}
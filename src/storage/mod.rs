@@ -0,0 +1,179 @@
+//! Backends storing the datoms of a `Db`.
+
+use super::{attr, Attribute, Datom, Datoms, EntityId, FilteredIndex, Index, Partition, TxId, Value};
+use error::Error;
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt;
+
+mod sqlite;
+pub use self::sqlite::SqliteStorage;
+
+mod memory;
+pub use self::memory::MemoryStorage;
+
+mod segment;
+pub use self::segment::SegmentStorage;
+
+/// Attributes which are always in the Avet index
+pub(crate) const INDEXED_ATTRIBUTES: &[Attribute] = &[attr::ident, attr::migration, attr::partition];
+
+/// Persistence of datoms and index scans over them. `Db` implements
+/// transactions, the schema and entities on top of it.
+///
+/// A retraction doesn't remove a datom but marks it as retracted by a
+/// transaction, so older states of the database stay readable.
+pub trait Storage: fmt::Debug + Send {
+    /// Stores the assertions and retractions of a transaction atomically.
+    /// Assertions are stored before retractions, as a transaction can
+    /// assert and retract the same value. A retraction marks the single
+    /// asserted datom with the same entity, attribute and value from an
+    /// earlier transaction.
+    ///
+    /// Changes to `db/unique` and `db/fulltext` have to update the Avet
+    /// and fulltext indexes.
+    fn store_datoms(&mut self, datoms: &[Datom]) -> Result<(), Error>;
+
+    /// Datoms matching `index` in index order. Only datoms asserted up to
+    /// and not retracted at `basis` are returned, or all current datoms
+    /// if `basis` is `None`. Avet only contains `db/unique` attributes.
+    fn datoms(&self, index: &FilteredIndex, basis: Option<TxId>) -> Result<Datoms<'_>, Error>;
+
+    /// The current values of each entity and attribute of `keys`, in
+    /// Eavt order, leaving out keys without values. `Db::transact` reads
+    /// the values its assertions replace with one call. The default
+    /// implementation scans Eavt once per key.
+    fn current_values(&self, keys: &[(EntityId, Attribute)]) -> Result<BTreeMap<(EntityId, Attribute), Vec<Value>>, Error> {
+        let mut values = BTreeMap::new();
+        for &(e, a) in keys {
+            let current = self.datoms(&Index::Eavt.e(e).a(a), None)?.into_iter()
+                .map(|d| d.value)
+                .collect::<Vec<_>>();
+            if !current.is_empty() {
+                values.insert((e, a), current);
+            }
+        }
+        Ok(values)
+    }
+
+    /// The highest entity id in `partition`, or the start of the partition
+    /// if it's empty.
+    fn highest_eid(&self, partition: Partition) -> Result<EntityId, Error>;
+
+    /// Calls `f` for every assertion and retraction in transaction order,
+    /// assertions of a transaction before its retractions. `tx` of a
+    /// retraction is the retracting transaction.
+    fn scan_log(&self, f: &mut dyn FnMut(Datom) -> Result<(), Error>) -> Result<(), Error>;
+
+    /// Permanently removes all datoms, current and retracted, of each
+    /// target entity, or only those of the given attribute of the entity,
+    /// including them in `scan_log` and in files on disk, and stores the
+    /// datoms of the transaction `record`ing the excision like
+    /// `store_datoms`. Storages with transactions do both atomically,
+    /// others remove the datoms first. Used by `Db::excise`.
+    fn excise(&mut self, targets: &[(EntityId, Option<Attribute>)], record: &[Datom]) -> Result<(), Error>;
+
+    /// Called before storing many transactions in a row, e.g. by
+    /// `Importer`. Storages may defer syncing and index maintenance until
+    /// `end_bulk` or `abort_bulk`, one of which is always called
+    /// afterwards.
+    fn begin_bulk(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    fn end_bulk(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Ends a bulk operation like `end_bulk`, but discards all datoms
+    /// stored since `begin_bulk`. Used by `Db::import` when the log is
+    /// invalid. Storages which can't discard datoms keep them.
+    fn abort_bulk(&mut self) -> Result<(), Error> {
+        self.end_bulk()
+    }
+
+    /// A number which changes whenever another connection or process
+    /// writes to the storage, so cached reads can be invalidated. Storages
+    /// which can't be shared return a constant.
+    fn data_version(&self) -> Result<u64, Error> {
+        Ok(0)
+    }
+
+    /// Searches the current string values of the `db/fulltext` attribute
+    /// `attribute`, ordered by relevance. The default implementation scans
+    /// all values and matches entities containing all words of `query`.
+    fn fulltext(&self, attribute: Attribute, query: &str) -> Result<Vec<(EntityId, Value, f64)>, Error> {
+        let enabled = self.datoms(&Index::Eavt.e(attribute.0).a(attr::fulltext), None)?
+            .iter()
+            .any(|d| d.value == Value::Bool(true));
+        if !enabled {
+            return Ok(vec![]);
+        }
+
+        let terms = words(query);
+        let mut results = self.datoms(&Index::Aevt.a(attribute), None)?
+            .into_iter()
+            .filter_map(|d| {
+                let score = {
+                    let text = words(d.value.as_str()?);
+                    if terms.is_empty() || !terms.iter().all(|t| text.contains(t)) {
+                        return None;
+                    }
+                    text.iter().filter(|w| terms.contains(w)).count()
+                };
+                Some((d.entity, d.value, score as f64))
+            })
+            .collect::<Vec<_>>();
+
+        results.sort_by(|a, b| b.2.partial_cmp(&a.2).unwrap());
+        Ok(results)
+    }
+}
+
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(|w| w.to_lowercase())
+        .collect()
+}
+
+/// Keeps the set of attributes in the Avet index in sync with the
+/// `db/unique` datoms in `datoms`. Retractions are applied first, as
+/// changing the value retracts the old one in the same transaction.
+fn update_unique(unique: &mut BTreeSet<Attribute>, datoms: &[Datom]) {
+    let (asserted, retracted): (Vec<&Datom>, Vec<&Datom>) = datoms.iter()
+        .filter(|d| d.attribute == attr::unique)
+        .partition(|d| d.status.is_assertion());
+
+    for d in retracted {
+        unique.remove(&Attribute(d.entity));
+    }
+
+    for d in asserted {
+        if d.value == Value::Bool(true) {
+            unique.insert(Attribute(d.entity));
+        } else {
+            unique.remove(&Attribute(d.entity));
+        }
+    }
+}
+
+/// Whether a datom asserted in `tx` and retracted in `retracted_tx` is
+/// visible at `basis`
+fn visible(tx: TxId, retracted_tx: Option<TxId>, basis: Option<TxId>) -> bool {
+    match basis {
+        Some(basis) => tx <= basis && match retracted_tx {
+            Some(r) => r > basis,
+            None => true,
+        },
+        None => retracted_tx.is_none(),
+    }
+}
+
+/// Whether the datoms of `e` and `a` are removed by excising `targets`
+fn excised(targets: &[(EntityId, Option<Attribute>)], e: EntityId, a: Attribute) -> bool {
+    targets.iter().any(|(entity, attribute)| *entity == e && match attribute {
+        Some(attribute) => *attribute == a,
+        None => true,
+    })
+}
@@ -0,0 +1,753 @@
+use super::{excised, update_unique, visible, Storage, INDEXED_ATTRIBUTES};
+use {attr, Attribute, Datom, Datoms, EntityId, FilteredIndex, Index, Partition, Status, TxId, Value};
+use error::Error;
+
+use serde_json;
+use std::cell::RefCell;
+use std::cmp::{self, Ordering};
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+
+/// Entries per block of a segment. Segments keep the first key of every
+/// block in memory to find where a range scan starts.
+const BLOCK_SIZE: usize = 256;
+
+/// Default number of datoms transacted between two indexings
+const INDEX_THRESHOLD: usize = 100_000;
+
+const MIN_EID: EntityId = EntityId(i64::MIN);
+const MIN_VALUE: Value = Value::Bool(false);
+
+const INDEXES: [Index; 4] = [Index::Eavt, Index::Aevt, Index::Avet, Index::Vaet];
+
+type Key = (EntityId, Attribute, Value, TxId);
+
+/// A datom in a segment and the transaction which retracted it
+type Entry = (Key, Option<TxId>);
+
+/// A datom in the log and whether it was asserted. The transaction of a
+/// retraction is the retracting transaction.
+type LogDatom = (EntityId, Attribute, Value, TxId, bool);
+
+/// Storage in a directory of immutable sorted segment files, one for each
+/// of the covering indexes Eavt, Aevt, Avet and Vaet, and an append-only
+/// log of all transacted datoms.
+///
+/// Transactions are appended to the log and kept in memory until
+/// `index` merges them into a new generation of segments, which happens
+/// automatically every `set_index_threshold` datoms. Opening the
+/// directory replays the log written since the last indexing. The log is
+/// never truncated and doubles as the transaction log of `scan_log`.
+///
+/// Fulltext search uses the default `Storage::fulltext`.
+#[derive(Debug)]
+pub struct SegmentStorage {
+    dir: PathBuf,
+    log: File,
+    generation: u64,
+    /// Generation of the log, which `excise` rewrites
+    log_generation: u64,
+    /// The segment of each index in `INDEXES`
+    segments: Vec<Segment>,
+    /// Datoms asserted since the last indexing and the transaction which
+    /// retracted them
+    asserted: BTreeMap<Key, Option<TxId>>,
+    /// Datoms in the segments retracted since the last indexing
+    retracted: BTreeMap<Key, TxId>,
+    /// Attributes in the Avet index
+    unique: BTreeSet<Attribute>,
+    /// Cache of `highest_eid`
+    highest: RefCell<Vec<(Partition, EntityId)>>,
+    index_threshold: usize,
+    /// Length of the log at `begin_bulk`, until `end_bulk`
+    bulk: Option<u64>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Manifest {
+    /// Generation of the current segments, 0 if there are none
+    generation: u64,
+    /// Length of the log covered by the current segments
+    log_offset: u64,
+    /// Generation of the current log, 0 if it was never rewritten
+    #[serde(default)]
+    log_generation: u64,
+}
+
+impl SegmentStorage {
+    /// Opens the database in the directory `dir`, creating it if it
+    /// doesn't exist
+    pub fn open<P: AsRef<Path>>(dir: P) -> Result<Self, Error> {
+        let dir = dir.as_ref().to_path_buf();
+        fs::create_dir_all(&dir)?;
+
+        let manifest = match File::open(dir.join("manifest")) {
+            Ok(file) => serde_json::from_reader(BufReader::new(file))?,
+            Err(ref e) if e.kind() == io::ErrorKind::NotFound => Manifest { generation: 0, log_offset: 0, log_generation: 0 },
+            Err(e) => return Err(e.into()),
+        };
+
+        let segments = INDEXES.iter()
+            .map(|index| Segment::open(&dir, *index, manifest.generation))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let log = OpenOptions::new().read(true).append(true).create(true).open(log_path(&dir, manifest.log_generation))?;
+
+        let mut storage = SegmentStorage {
+            dir,
+            log,
+            generation: manifest.generation,
+            log_generation: manifest.log_generation,
+            segments,
+            asserted: BTreeMap::new(),
+            retracted: BTreeMap::new(),
+            unique: INDEXED_ATTRIBUTES.iter().cloned().collect(),
+            highest: RefCell::new(vec![]),
+            index_threshold: INDEX_THRESHOLD,
+            bulk: None,
+        };
+
+        for ((e, _, v, _), retracted_tx) in storage.segment(Index::Aevt).range(&Index::Aevt.a(attr::unique))? {
+            if retracted_tx.is_none() && v == Value::Bool(true) {
+                storage.unique.insert(Attribute(e));
+            }
+        }
+
+        storage.replay(manifest.log_offset)?;
+
+        Ok(storage)
+    }
+
+    /// Sets the number of datoms transacted after which `index` runs
+    pub fn set_index_threshold(&mut self, datoms: usize) {
+        self.index_threshold = datoms;
+    }
+
+    /// Merges the datoms transacted since the last indexing into a new
+    /// generation of segments and removes the old ones.
+    pub fn index(&mut self) -> Result<(), Error> {
+        let log_offset = self.log.metadata()?.len();
+        let segments = self.merge(&|_| true)?;
+        let log_generation = self.log_generation;
+        self.switch(segments, log_offset, log_generation)
+    }
+
+    /// Writes the next generation of segments with the datoms transacted
+    /// since the last indexing, leaving out the datoms `keep` rejects
+    fn merge(&self, keep: &dyn Fn(&Key) -> bool) -> Result<Vec<Segment>, Error> {
+        let generation = self.generation + 1;
+
+        let mut segments = Vec::with_capacity(INDEXES.len());
+        for segment in &self.segments {
+            let index = segment.index;
+            let mut recent = self.asserted.iter()
+                .filter(|(key, _)| in_index(index, key) && keep(key))
+                .map(|(key, retracted_tx)| (key.clone(), *retracted_tx))
+                .collect::<Vec<_>>();
+            recent.sort_by(|a, b| compare(index, &a.0, &b.0));
+            let mut recent = recent.into_iter().peekable();
+
+            let mut writer = SegmentWriter::create(&self.dir, index, generation)?;
+            for entry in segment.entries()? {
+                let (key, retracted_tx) = entry?;
+                if !keep(&key) {
+                    continue;
+                }
+                while let Some((next, _)) = recent.peek() {
+                    if compare(index, next, &key) != Ordering::Less {
+                        break;
+                    }
+                    writer.push(&recent.next().unwrap())?;
+                }
+                let retracted_tx = retracted_tx.or_else(|| self.retracted.get(&key).cloned());
+                writer.push(&(key, retracted_tx))?;
+            }
+            for entry in recent {
+                writer.push(&entry)?;
+            }
+            segments.push(writer.finish()?);
+        }
+
+        Ok(segments)
+    }
+
+    /// Switches to the segments written by `merge`, which cover the log of
+    /// `log_generation` up to `log_offset`, and removes the old ones
+    fn switch(&mut self, segments: Vec<Segment>, log_offset: u64, log_generation: u64) -> Result<(), Error> {
+        let generation = self.generation + 1;
+
+        // Replacing the manifest switches to the new segments and log
+        // atomically
+        let manifest = self.dir.join("manifest.tmp");
+        {
+            let mut file = File::create(&manifest)?;
+            serde_json::to_writer(&mut file, &Manifest { generation, log_offset, log_generation })?;
+            file.sync_all()?;
+        }
+        fs::rename(&manifest, self.dir.join("manifest"))?;
+
+        for segment in ::std::mem::replace(&mut self.segments, segments) {
+            segment.remove()?;
+        }
+        self.generation = generation;
+        self.asserted.clear();
+        self.retracted.clear();
+
+        Ok(())
+    }
+
+    fn log_path(&self) -> PathBuf {
+        log_path(&self.dir, self.log_generation)
+    }
+
+    fn segment(&self, index: Index) -> &Segment {
+        self.segments.iter().find(|s| s.index == index).unwrap()
+    }
+
+    /// Datoms asserted since the last indexing which `filter` might match
+    fn recent<'a>(&'a self, filter: &FilteredIndex) -> Box<dyn Iterator<Item=(&'a Key, &'a Option<TxId>)> + 'a> {
+        match filter.e {
+            Some(e) => Box::new(self.asserted.range((e, Attribute(MIN_EID), MIN_VALUE, MIN_EID)..)
+                                .take_while(move |(key, _)| key.0 == e)),
+            None => Box::new(self.asserted.iter()),
+        }
+    }
+
+    /// Finds the datom retracted by each retraction in `datoms`. Like the
+    /// other backends this panics unless a retraction matches exactly one
+    /// datom.
+    fn retractions(&self, datoms: &[Datom]) -> Result<Vec<(Key, TxId)>, Error> {
+        let mut retractions = vec![];
+
+        for d in datoms.iter().filter(|d| !d.status.is_assertion()) {
+            let filter = Index::Eavt.e(d.entity).a(d.attribute).v(d.value.clone());
+
+            let mut keys = self.segment(Index::Eavt).range(&filter)?.into_iter()
+                .filter(|(key, retracted_tx)| retracted_tx.is_none() && !self.retracted.contains_key(key))
+                .map(|(key, _)| key)
+                .collect::<Vec<_>>();
+            keys.extend(self.recent(&filter)
+                        .filter(|(key, retracted_tx)| retracted_tx.is_none() && compare_prefix(&filter, key) == Ordering::Equal)
+                        .map(|(key, _)| key.clone()));
+            keys.extend(datoms.iter()
+                        .filter(|a| a.status.is_assertion() && filter.matches(a))
+                        .map(|a| (a.entity, a.attribute, a.value.clone(), a.tx)));
+            keys.retain(|key| key.3 != d.tx);
+
+            if keys.len() != 1 {
+                panic!("Retraction has to match exactly one datom, matched {}. Datom: {:?}", keys.len(), d);
+            }
+
+            let retracted_tx = match d.status {
+                Status::Retracted(tx) => tx,
+                _ => unreachable!()
+            };
+            retractions.push((keys.pop().unwrap(), retracted_tx));
+        }
+
+        Ok(retractions)
+    }
+
+    fn apply(&mut self, datoms: &[Datom], retractions: Vec<(Key, TxId)>) {
+        for d in datoms.iter().filter(|d| d.status.is_assertion()) {
+            self.asserted.entry((d.entity, d.attribute, d.value.clone(), d.tx)).or_insert(None);
+
+            for (partition, highest) in self.highest.get_mut().iter_mut() {
+                if partition.contains(d.entity) && d.entity > *highest {
+                    *highest = d.entity;
+                }
+            }
+        }
+
+        for (key, retracted_tx) in retractions {
+            match self.asserted.get_mut(&key) {
+                Some(r) => *r = Some(retracted_tx),
+                None => { self.retracted.insert(key, retracted_tx); },
+            }
+        }
+
+        update_unique(&mut self.unique, datoms);
+    }
+
+    /// Applies the transactions logged after `offset`. An incomplete last
+    /// line, left by a crash while writing it, is removed from the log.
+    fn replay(&mut self, offset: u64) -> Result<(), Error> {
+        let mut reader = BufReader::new(File::open(self.log_path())?);
+        reader.seek(SeekFrom::Start(offset))?;
+
+        let mut position = offset;
+        let mut line = String::new();
+        loop {
+            line.clear();
+            let read = reader.read_line(&mut line)?;
+            if read == 0 {
+                break;
+            }
+            if !line.ends_with('\n') {
+                self.log.set_len(position)?;
+                break;
+            }
+
+            let datoms = serde_json::from_str::<Vec<LogDatom>>(&line)?.into_iter()
+                .map(datom_from_log)
+                .collect::<Vec<_>>();
+            let retractions = self.retractions(&datoms)?;
+            self.apply(&datoms, retractions);
+
+            position += read as u64;
+        }
+
+        Ok(())
+    }
+}
+
+impl Storage for SegmentStorage {
+    fn store_datoms(&mut self, datoms: &[Datom]) -> Result<(), Error> {
+        let retractions = self.retractions(datoms)?;
+
+        let logged = datoms.iter()
+            .map(|d| match d.status {
+                Status::Asserted => (d.entity, d.attribute, d.value.clone(), d.tx, true),
+                Status::Retracted(tx) => (d.entity, d.attribute, d.value.clone(), tx, false),
+            })
+            .collect::<Vec<LogDatom>>();
+        let mut line = serde_json::to_vec(&logged)?;
+        line.push(b'\n');
+        self.log.write_all(&line)?;
+        if self.bulk.is_none() {
+            self.log.sync_data()?;
+        }
+
+        self.apply(datoms, retractions);
+
+        if self.bulk.is_none() && self.asserted.len() + self.retracted.len() >= self.index_threshold {
+            self.index()?;
+        }
+
+        Ok(())
+    }
+
+    /// Defers syncing the log and indexing until `end_bulk`
+    fn begin_bulk(&mut self) -> Result<(), Error> {
+        self.bulk = Some(self.log.metadata()?.len());
+        Ok(())
+    }
+
+    fn end_bulk(&mut self) -> Result<(), Error> {
+        self.bulk = None;
+        self.log.sync_data()?;
+
+        if self.asserted.len() + self.retracted.len() >= self.index_threshold {
+            self.index()?;
+        }
+        Ok(())
+    }
+
+    /// Truncates the log to its length at `begin_bulk` and replays it.
+    /// Nothing is indexed during a bulk operation, so the segments don't
+    /// contain any of the discarded datoms.
+    fn abort_bulk(&mut self) -> Result<(), Error> {
+        let log_len = match self.bulk.take() {
+            Some(log_len) => log_len,
+            None => return Ok(()),
+        };
+
+        self.log.set_len(log_len)?;
+        self.log.sync_data()?;
+
+        let index_threshold = self.index_threshold;
+        *self = SegmentStorage::open(&self.dir)?;
+        self.index_threshold = index_threshold;
+        Ok(())
+    }
+
+    fn datoms(&self, filter: &FilteredIndex, basis: Option<TxId>) -> Result<Datoms<'_>, Error> {
+        let index = filter.index;
+
+        let mut entries = self.segment(index).range(filter)?.into_iter()
+            .map(|(key, retracted_tx)| {
+                let retracted_tx = retracted_tx.or_else(|| self.retracted.get(&key).cloned());
+                (key, retracted_tx)
+            })
+            .collect::<Vec<_>>();
+        entries.extend(self.recent(filter)
+                       .filter(|(key, _)| in_index(index, key) && compare_prefix(filter, key) == Ordering::Equal)
+                       .map(|(key, retracted_tx)| (key.clone(), *retracted_tx)));
+        entries.sort_by(|a, b| compare(index, &a.0, &b.0));
+
+        Ok(entries.into_iter()
+           .filter(|(key, _)| index != Index::Avet || self.unique.contains(&key.1))
+           .filter(|(key, retracted_tx)| visible(key.3, *retracted_tx, basis))
+           .map(|((entity, attribute, value, tx), _)| Datom { entity, attribute, value, tx, status: Status::Asserted })
+           .filter(|d| filter.matches(d))
+           .collect())
+    }
+
+    fn highest_eid(&self, partition: Partition) -> Result<EntityId, Error> {
+        if let Some((_, highest)) = self.highest.borrow().iter().find(|(p, _)| *p == partition) {
+            return Ok(*highest);
+        }
+
+        let start = (partition.start(), Attribute(MIN_EID), MIN_VALUE, MIN_EID);
+        let end = (partition.end(), Attribute(MIN_EID), MIN_VALUE, MIN_EID);
+        let recent = self.asserted.range(start..end).next_back()
+            .map(|((e, _, _, _), _)| *e);
+        let indexed = self.segment(Index::Eavt).highest(partition)?;
+        let highest = cmp::max(recent, indexed).unwrap_or_else(|| partition.start());
+
+        self.highest.borrow_mut().push((partition, highest));
+        Ok(highest)
+    }
+
+    /// Writes a generation of segments and the next generation of the log
+    /// without the excised datoms, switches to both with the manifest and
+    /// appends `record` to the new log. A crash before the manifest is
+    /// replaced leaves the old segments and log, which still contain the
+    /// datoms, so `excise` has to be repeated.
+    fn excise(&mut self, targets: &[(EntityId, Option<Attribute>)], record: &[Datom]) -> Result<(), Error> {
+        let keep = |(e, a, _, _): &Key| !excised(targets, *e, *a);
+        let segments = self.merge(&keep)?;
+
+        let log_generation = self.log_generation + 1;
+        let path = log_path(&self.dir, log_generation);
+        {
+            let reader = BufReader::new(File::open(self.log_path())?);
+            let mut writer = BufWriter::new(File::create(&path)?);
+            for line in reader.lines() {
+                let datoms = serde_json::from_str::<Vec<LogDatom>>(&line?)?.into_iter()
+                    .filter(|(e, a, _, _, _)| !excised(targets, *e, *a))
+                    .collect::<Vec<_>>();
+                serde_json::to_writer(&mut writer, &datoms)?;
+                writer.write_all(b"\n")?;
+            }
+            writer.into_inner().map_err(|e| e.into_error())?.sync_all()?;
+        }
+        let log = OpenOptions::new().read(true).append(true).open(&path)?;
+
+        // The new segments contain everything logged so far
+        let log_offset = log.metadata()?.len();
+        self.switch(segments, log_offset, log_generation)?;
+
+        let old_log = self.log_path();
+        self.log = log;
+        self.log_generation = log_generation;
+        fs::remove_file(old_log)?;
+
+        self.store_datoms(record)
+    }
+
+    fn scan_log(&self, f: &mut dyn FnMut(Datom) -> Result<(), Error>) -> Result<(), Error> {
+        let reader = BufReader::new(File::open(self.log_path())?);
+
+        for line in reader.lines() {
+            let mut datoms = serde_json::from_str::<Vec<LogDatom>>(&line?)?.into_iter()
+                .map(datom_from_log)
+                .collect::<Vec<_>>();
+            datoms.sort_by_key(|d| (d.tx, !d.status.is_assertion()));
+
+            for datom in datoms {
+                f(datom)?;
+            }
+        }
+
+        Ok(())
+    }
+}
+
+/// An immutable file of the entries of an index in index order, one JSON
+/// array per line, and the first key and file offset of each block.
+#[derive(Debug)]
+struct Segment {
+    index: Index,
+    path: PathBuf,
+    blocks: Vec<(Key, u64)>,
+}
+
+impl Segment {
+    fn path(dir: &Path, index: Index, generation: u64) -> PathBuf {
+        let name = match index {
+            Index::Eavt => "eavt",
+            Index::Aevt => "aevt",
+            Index::Avet => "avet",
+            Index::Vaet => "vaet",
+        };
+        dir.join(format!("{}-{}.segment", name, generation))
+    }
+
+    fn open(dir: &Path, index: Index, generation: u64) -> Result<Self, Error> {
+        let path = Self::path(dir, index, generation);
+        let blocks = if generation == 0 {
+            vec![]
+        } else {
+            serde_json::from_reader(BufReader::new(File::open(path.with_extension("blocks"))?))?
+        };
+
+        Ok(Segment { index, path, blocks })
+    }
+
+    fn remove(self) -> Result<(), Error> {
+        // Before the first indexing there are no segment files
+        if self.path.exists() {
+            fs::remove_file(&self.path)?;
+            fs::remove_file(self.path.with_extension("blocks"))?;
+        }
+        Ok(())
+    }
+
+    fn reader_at(&self, offset: u64) -> Result<BufReader<File>, Error> {
+        let mut reader = BufReader::new(File::open(&self.path)?);
+        reader.seek(SeekFrom::Start(offset))?;
+        Ok(reader)
+    }
+
+    fn entries(&self) -> Result<Box<dyn Iterator<Item=Result<Entry, Error>>>, Error> {
+        if self.blocks.is_empty() {
+            return Ok(Box::new(::std::iter::empty()));
+        }
+
+        Ok(Box::new(self.reader_at(0)?.lines()
+                    .map(|line| Ok(serde_json::from_str(&line?)?))))
+    }
+
+    /// Entries in the range of keys `filter` can match, without applying
+    /// the rest of the filter
+    fn range(&self, filter: &FilteredIndex) -> Result<Vec<Entry>, Error> {
+        let mut entries = vec![];
+        if self.blocks.is_empty() {
+            return Ok(entries);
+        }
+
+        // The range starts in the last block beginning before it
+        let start = partition_point(&self.blocks, |(key, _)| compare_prefix(filter, key) == Ordering::Less)
+            .saturating_sub(1);
+
+        for line in self.reader_at(self.blocks[start].1)?.lines() {
+            let entry: Entry = serde_json::from_str(&line?)?;
+            match compare_prefix(filter, &entry.0) {
+                Ordering::Less    => continue,
+                Ordering::Equal   => entries.push(entry),
+                Ordering::Greater => break,
+            }
+        }
+
+        Ok(entries)
+    }
+
+    /// The highest entity in `partition`. Only valid for Eavt segments.
+    fn highest(&self, partition: Partition) -> Result<Option<EntityId>, Error> {
+        // The last block starting before the end of the partition contains
+        // its highest entity, if there is any
+        let block = partition_point(&self.blocks, |((e, _, _, _), _)| *e < partition.end());
+        if block == 0 {
+            return Ok(None);
+        }
+
+        let mut highest = None;
+        for line in self.reader_at(self.blocks[block - 1].1)?.lines().take(BLOCK_SIZE) {
+            let ((e, _, _, _), _): Entry = serde_json::from_str(&line?)?;
+            if partition.contains(e) {
+                highest = Some(e);
+            }
+        }
+
+        Ok(highest)
+    }
+}
+
+struct SegmentWriter {
+    segment: Segment,
+    file: BufWriter<File>,
+    offset: u64,
+    count: usize,
+}
+
+impl SegmentWriter {
+    fn create(dir: &Path, index: Index, generation: u64) -> Result<Self, Error> {
+        let path = Segment::path(dir, index, generation);
+        let file = BufWriter::new(File::create(&path)?);
+        Ok(SegmentWriter { segment: Segment { index, path, blocks: vec![] }, file, offset: 0, count: 0 })
+    }
+
+    fn push(&mut self, entry: &Entry) -> Result<(), Error> {
+        // The entry is the first of a new block
+        if self.count == self.segment.blocks.len() * BLOCK_SIZE {
+            self.segment.blocks.push((entry.0.clone(), self.offset));
+        }
+
+        let mut line = serde_json::to_vec(entry)?;
+        line.push(b'\n');
+        self.file.write_all(&line)?;
+        self.offset += line.len() as u64;
+        self.count += 1;
+
+        Ok(())
+    }
+
+    fn finish(self) -> Result<Segment, Error> {
+        let file = self.file.into_inner().map_err(|e| e.into_error())?;
+        file.sync_all()?;
+
+        let mut blocks = File::create(self.segment.path.with_extension("blocks"))?;
+        serde_json::to_writer(&mut blocks, &self.segment.blocks)?;
+        blocks.sync_all()?;
+
+        Ok(self.segment)
+    }
+}
+
+/// The log of generation 0 is `log`, later generations written by
+/// `excise` are `log.1`, `log.2` and so on
+fn log_path(dir: &Path, generation: u64) -> PathBuf {
+    if generation == 0 {
+        dir.join("log")
+    } else {
+        dir.join(format!("log.{}", generation))
+    }
+}
+
+fn datom_from_log((entity, attribute, value, tx, added): LogDatom) -> Datom {
+    let status = if added { Status::Asserted } else { Status::Retracted(tx) };
+    Datom { entity, attribute, value, tx, status }
+}
+
+/// Vaet only contains datoms with `Value::Ref` values
+fn in_index(index: Index, key: &Key) -> bool {
+    index != Index::Vaet || key.2.as_ref_id().is_some()
+}
+
+fn compare(index: Index, a: &Key, b: &Key) -> Ordering {
+    let ((e1, a1, v1, t1), (e2, a2, v2, t2)) = (a, b);
+    match index {
+        Index::Eavt => (e1, a1, v1, t1).cmp(&(e2, a2, v2, t2)),
+        Index::Aevt => (a1, e1, v1, t1).cmp(&(a2, e2, v2, t2)),
+        Index::Avet => (a1, v1, e1, t1).cmp(&(a2, v2, e2, t2)),
+        Index::Vaet => (v1, a1, e1, t1).cmp(&(v2, a2, e2, t2)),
+    }
+}
+
+/// Compares `key` with the leading components of `filter` in the order
+/// of its index. `Equal` if the key is in the range of keys `filter` can
+/// match.
+fn compare_prefix(filter: &FilteredIndex, key: &Key) -> Ordering {
+    let (e, a, v, _) = key;
+    let e = filter.e.map(|f| e.cmp(&f));
+    let a = filter.a.map(|f| a.cmp(&f));
+    let v = filter.v.as_ref().map(|f| v.cmp(f));
+
+    let components = match filter.index {
+        Index::Eavt => [e, a, v],
+        Index::Aevt => [a, e, v],
+        Index::Avet => [a, v, e],
+        Index::Vaet => [v, a, e],
+    };
+
+    for component in &components {
+        match *component {
+            Some(Ordering::Equal) => continue,
+            Some(ordering) => return ordering,
+            None => break,
+        }
+    }
+    Ordering::Equal
+}
+
+/// The number of leading elements of `slice` for which `pred` holds, if
+/// it holds for a prefix of the slice
+fn partition_point<T, F: Fn(&T) -> bool>(slice: &[T], pred: F) -> usize {
+    slice.binary_search_by(|x| if pred(x) { Ordering::Less } else { Ordering::Greater })
+        .unwrap_err()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {Assert, Db};
+
+    fn open(dir: &Path) -> Db {
+        let mut storage = SegmentStorage::open(dir).unwrap();
+        storage.set_index_threshold(16);
+        Db::with_storage(storage).unwrap()
+    }
+
+    #[test]
+    fn reopen() {
+        let dir = ::tests::temp_dir();
+
+        let (karl, log) = {
+            let mut db = open(&dir);
+            db.transact(&[(Assert, ::tempid(), "db/ident", "person/name")]).unwrap();
+
+            let karl = ::tempid();
+            let karl = db.transact(&[(Assert, karl.clone(), "person/name", "Karl")]).unwrap()
+                .tempid_mappings[&karl];
+            for i in 0..20 {
+                db.transact(&[(Assert, ::tempid(), "person/name", format!("Person {}", i))]).unwrap();
+            }
+            db.transact(&[(Assert, karl, "person/name", "Karl Marx")]).unwrap();
+
+            (karl, db.tx_log().unwrap())
+        };
+
+        let db = open(&dir);
+        assert_eq!(log, db.tx_log().unwrap());
+        assert_eq!(db.entity(karl).unwrap()["person/name"], Value::from("Karl Marx"));
+        assert_eq!(21, db.datoms(Index::Aevt.a(db.attribute("person/name").unwrap())).unwrap().len());
+    }
+
+    fn log(dir: &Path) -> PathBuf {
+        let manifest = File::open(dir.join("manifest")).unwrap();
+        let manifest: Manifest = serde_json::from_reader(manifest).unwrap();
+        log_path(dir, manifest.log_generation)
+    }
+
+    #[test]
+    fn incomplete_log_line() {
+        let dir = ::tests::temp_dir();
+
+        {
+            let mut db = open(&dir);
+            db.transact(&[(Assert, ::tempid(), "db/ident", "person/name")]).unwrap();
+        }
+
+        let mut log = OpenOptions::new().append(true).open(dir.join("log")).unwrap();
+        log.write_all(b"[[1,").unwrap();
+
+        let mut db = open(&dir);
+        assert!(db.attribute("person/name").is_some());
+        db.transact(&[(Assert, ::tempid(), "person/name", "Karl")]).unwrap();
+        assert_eq!(1, db.datoms(Index::Aevt.a(db.attribute("person/name").unwrap())).unwrap().len());
+    }
+
+    #[test]
+    fn excise_and_reopen() {
+        let dir = ::tests::temp_dir();
+
+        let karl = {
+            let mut db = open(&dir);
+            db.transact(&[(Assert, ::tempid(), "db/ident", "person/name")]).unwrap();
+
+            let karl = ::tempid();
+            let karl = db.transact(&[(Assert, karl.clone(), "person/name", "Karl")]).unwrap()
+                .tempid_mappings[&karl];
+            for i in 0..20 {
+                db.transact(&[(Assert, ::tempid(), "person/name", format!("Person {}", i))]).unwrap();
+            }
+            db.transact(&[(Assert, karl, "person/name", "Karl Marx")]).unwrap();
+
+            db.excise(&[::Excision::Entity(karl)]).unwrap();
+            karl
+        };
+
+        let log = fs::read_to_string(log(&dir)).unwrap();
+        assert!(!log.contains("Karl"));
+        assert!(!dir.join("log").exists());
+
+        let mut db = open(&dir);
+        assert!(db.all_datoms().iter().all(|d| d.entity != karl));
+        assert_eq!(20, db.datoms(Index::Aevt.a(db.attribute("person/name").unwrap())).unwrap().len());
+        db.transact(&[(Assert, ::tempid(), "person/name", "Anna")]).unwrap();
+        assert_eq!(21, db.datoms(Index::Aevt.a(db.attribute("person/name").unwrap())).unwrap().len());
+    }
+}
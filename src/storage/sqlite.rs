@@ -0,0 +1,590 @@
+extern crate rusqlite;
+
+use super::{Storage, INDEXED_ATTRIBUTES};
+use {attr, Attribute, Datom, Datoms, EntityId, FilteredIndex, Index, Partition, Status, TxId, Value};
+use error::Error;
+
+use std::cmp;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+/// Storage in a SQLite database file. Fulltext search uses an FTS5 index
+/// and supports its query syntax.
+#[derive(Debug)]
+pub struct SqliteStorage {
+    conn: rusqlite::Connection,
+}
+
+impl SqliteStorage {
+    pub fn in_memory() -> Result<Self, Error> {
+        Self::new(rusqlite::Connection::open_in_memory()?)
+    }
+
+    pub fn open<P: AsRef<Path>>(path: P) -> Result<Self, Error> {
+        Self::new(rusqlite::Connection::open(path)?)
+    }
+
+    fn new(conn: rusqlite::Connection) -> Result<Self, Error> {
+        if !Self::has_sqlite_table(&conn, "datoms")? {
+            conn.execute_batch(include_str!("../schema.sql"))?
+        }
+
+        conn.execute_batch(include_str!("../fulltext.sql"))?;
+
+        for unique in INDEXED_ATTRIBUTES {
+            conn.execute("insert or ignore into unique_attributes (e) values (?1)", &[&unique.0])?;
+        }
+
+        // Databases created by older versions lack the sequences
+        if !Self::has_sqlite_table(&conn, "sequences")? {
+            conn.execute_batch(
+                "create table sequences (
+                   partition integer primary key,
+                   highest   integer not null
+                 )")?;
+
+            let entities = conn.prepare("select distinct e from datoms")?
+                .query_map(&[], |row| EntityId(row.get(0)))?
+                .collect::<Result<Vec<_>, _>>()?;
+            Self::update_sequences(&conn, entities.into_iter())?;
+        }
+
+        conn.execute("pragma foreign_keys = on", &[])?;
+
+        // Keys of `current_values`, per connection
+        conn.execute_batch(
+            "create temp table if not exists current_keys (
+               e integer not null,
+               a integer not null,
+               primary key (e, a)
+             )")?;
+
+        Ok(SqliteStorage { conn })
+    }
+
+    /// Raises the highest entity id of the partitions of `entities`.
+    /// `sequences` replaces scanning `datoms` for the highest id in a
+    /// partition, which can't use an index.
+    fn update_sequences<I: Iterator<Item=EntityId>>(conn: &rusqlite::Connection, entities: I) -> Result<(), Error> {
+        let mut highest: BTreeMap<Partition, EntityId> = BTreeMap::new();
+        for entity in entities {
+            if let Some(partition) = Partition::of(entity) {
+                let h = highest.entry(partition).or_insert(entity);
+                *h = cmp::max(*h, entity);
+            }
+        }
+
+        let mut insert = conn.prepare_cached(
+            "insert or ignore into sequences (partition, highest) values (?1, ?2)"
+        )?;
+        let mut update = conn.prepare_cached(
+            "update sequences set highest = max(highest, ?2) where partition = ?1"
+        )?;
+        for (partition, entity) in highest {
+            insert.execute(&[&partition.start().0, &entity.0])?;
+            update.execute(&[&partition.start().0, &entity.0])?;
+        }
+
+        Ok(())
+    }
+
+    /// Deletes the datoms of the excision `targets` and their fulltext
+    /// index entries
+    fn delete_excised(&self, targets: &[(EntityId, Option<Attribute>)]) -> Result<(), Error> {
+        for (entity, attribute) in targets {
+            match attribute {
+                Some(attribute) => {
+                    self.conn.execute("delete from datoms where e = ?1 and a = ?2", &[&entity.0, &attribute.0])?;
+                    self.conn.execute("delete from fulltext where e = ?1 and a = ?2", &[&entity.0, &attribute.0])?;
+                },
+                None => {
+                    self.conn.execute("delete from datoms where e = ?1", &[&entity.0])?;
+                    self.conn.execute("delete from fulltext where e = ?1", &[&entity.0])?;
+                },
+            }
+        }
+        Ok(())
+    }
+
+    fn has_sqlite_table(conn: &rusqlite::Connection, table: &str) -> Result<bool, rusqlite::Error> {
+        conn.prepare("SELECT name FROM sqlite_master WHERE type='table' AND name=?1")?.exists(&[&table])
+    }
+}
+
+impl Storage for SqliteStorage {
+    fn store_datoms(&mut self, datoms: &[Datom]) -> Result<(), Error> {
+        // A savepoint nests in the transaction of `begin_bulk`
+        let tx = self.conn.savepoint()?;
+
+        {
+            // A single transaction can assert and retract the same value so
+            // we have to persist all assertions before doing any
+            // retractions as our implementation will set the `retracted_tx`
+            // attribute on the database row.
+
+            let (asserted, retracted): (Vec<&Datom>, Vec<&Datom>) = datoms.iter()
+                .partition(|d| d.status.is_assertion());
+
+            let mut insert = tx.prepare_cached(
+                "insert into datoms (e,a,v,t) values (?1, ?2, ?3, ?4)"
+            )?;
+
+            for d in &asserted {
+                assert!(d.status.is_assertion());
+                insert.execute(&[&(d.entity.0),
+                                 &d.attribute.0,
+                                 &d.value,
+                                 &d.tx.0])?;
+            }
+
+            Self::update_sequences(&tx, asserted.iter().map(|d| d.entity))?;
+
+            // To retract we set the `retracted_tx` column on our datom. We
+            // have to make sure we aren't updating any datoms from our
+            // current transactions which were inserted earlier, so we
+            // explicitly check for `datoms.t != d.tx`. This must affect
+            // exactly one single row. If an UPDATE affects multiple rows we
+            // just panic to bail out.
+            let mut retract = tx.prepare_cached(
+                "update datoms set retracted_tx = ?1
+                 where e = ?2
+                   and a = ?3
+                   and v = ?4
+                   and t != ?5
+                   and retracted_tx is null"
+            ).unwrap();
+
+
+            for d in retracted {
+                assert!(d.status.is_retraction());
+                let retracted_tx = match d.status {
+                    Status::Retracted(tx) => tx,
+                    _ => unreachable!()
+                };
+
+                let row_count = retract.execute(&[&retracted_tx.0,
+                                                  &d.entity.0,
+                                                  &d.attribute.0,
+                                                  &d.value,
+                                                  &d.tx])?;
+                if row_count != 1 {
+                    panic!("UPDATE to change datoms.retracted_tx affected more than one row. Datom: {:?}", d);
+                }
+            }
+
+            // Keep `unique_attributes` in sync with `db/unique`. Retractions
+            // are applied first as changing the value of `db/unique`
+            // retracts the old value in the same transaction.
+            let (unique_asserted, unique_retracted): (Vec<&Datom>, Vec<&Datom>) = datoms.iter()
+                .filter(|d| d.attribute == attr::unique)
+                .partition(|d| d.status.is_assertion());
+
+            let mut insert_unique = tx.prepare_cached(
+                "insert or ignore into unique_attributes (e) values (?1)"
+            )?;
+            let mut delete_unique = tx.prepare_cached(
+                "delete from unique_attributes where e = ?1"
+            )?;
+
+            for d in unique_retracted {
+                delete_unique.execute(&[&d.entity.0])?;
+            }
+
+            for d in unique_asserted {
+                if d.value == Value::Bool(true) {
+                    insert_unique.execute(&[&d.entity.0])?;
+                } else {
+                    delete_unique.execute(&[&d.entity.0])?;
+                }
+            }
+
+            // Index string values of `db/fulltext` attributes. Retractions
+            // only remove a single row as the same value might have been
+            // asserted again in this transaction.
+            let mut insert_fulltext = tx.prepare_cached(
+                "insert into fulltext (v, e, a)
+                 select ?1, ?2, ?3
+                 where exists (select 1 from fulltext_attributes where e = ?3)"
+            )?;
+            let mut delete_fulltext = tx.prepare_cached(
+                "delete from fulltext
+                 where rowid = (select rowid from fulltext
+                                where v = ?1 and e = ?2 and a = ?3
+                                limit 1)"
+            )?;
+
+            for d in datoms {
+                if let Value::Str(ref text) = d.value {
+                    let params: &[&dyn rusqlite::types::ToSql] = &[text, &d.entity.0, &d.attribute.0];
+                    match d.status {
+                        Status::Asserted     => insert_fulltext.execute(params)?,
+                        Status::Retracted(_) => delete_fulltext.execute(params)?,
+                    };
+                }
+            }
+
+            // Changes to `db/fulltext` itself (re)build or drop the index of
+            // the attribute.
+            let (fulltext_asserted, fulltext_retracted): (Vec<&Datom>, Vec<&Datom>) = datoms.iter()
+                .filter(|d| d.attribute == attr::fulltext)
+                .partition(|d| d.status.is_assertion());
+
+            let mut insert_fulltext_attribute = tx.prepare_cached(
+                "insert or ignore into fulltext_attributes (e) values (?1)"
+            )?;
+            let mut delete_fulltext_attribute = tx.prepare_cached(
+                "delete from fulltext_attributes where e = ?1"
+            )?;
+            let mut index_attribute = tx.prepare_cached(
+                "insert into fulltext (v, e, a)
+                 select json_extract(v, '$.Str'), e, a from datoms
+                 where a = ?1
+                   and retracted_tx is null
+                   and json_type(v, '$.Str') = 'text'"
+            )?;
+            let mut unindex_attribute = tx.prepare_cached(
+                "delete from fulltext where a = ?1"
+            )?;
+
+            for d in fulltext_retracted {
+                delete_fulltext_attribute.execute(&[&d.entity.0])?;
+                unindex_attribute.execute(&[&d.entity.0])?;
+            }
+
+            for d in fulltext_asserted {
+                if d.value == Value::Bool(true) {
+                    if insert_fulltext_attribute.execute(&[&d.entity.0])? == 1 {
+                        index_attribute.execute(&[&d.entity.0])?;
+                    }
+                } else {
+                    delete_fulltext_attribute.execute(&[&d.entity.0])?;
+                    unindex_attribute.execute(&[&d.entity.0])?;
+                }
+            }
+        }
+
+        tx.commit()?;
+
+        Ok(())
+    }
+
+    fn datoms(&self, index: &FilteredIndex, basis: Option<TxId>) -> Result<Datoms<'_>, Error> {
+        let order_statement = match index.index {
+            Index::Eavt => "order by datoms.e, datoms.a, datoms.v, datoms.t asc",
+            Index::Aevt => "order by datoms.a, datoms.e, datoms.v, datoms.t asc",
+            Index::Avet => "order by datoms.a, datoms.v, datoms.e, datoms.t asc",
+            Index::Vaet => "order by datoms.v, datoms.a, datoms.e, datoms.t asc",
+        };
+
+        let join_clause = match index.index {
+            Index::Avet => "join unique_attributes on unique_attributes.e = datoms.a",
+            _ => ""
+        };
+
+        let ref_clause = match index.index {
+            Index::Vaet => r#"and datoms.v like '{"Ref":%'"#,
+            _ => ""
+        };
+
+        let mut query = self.conn.prepare_cached(&format!(
+            "select distinct datoms.e, datoms.a, datoms.v, datoms.t
+             from datoms
+             {}
+             where case when ?5 notnull
+                          then datoms.t <= ?5 and (retracted_tx is null or retracted_tx > ?5)
+                          else retracted_tx is null end
+               and case when ?1 notnull then datoms.e == ?1 else 1 end
+               and case when ?2 notnull then datoms.a == ?2 else 1 end
+               and case when ?3 notnull then datoms.v == ?3 else 1 end
+               and case when ?4 notnull then datoms.t == ?4 else 1 end
+               {}
+             {}
+      ", join_clause, ref_clause, order_statement))?;
+
+        let entity_query_input = match index.e {
+            Some(EntityId(id)) => rusqlite::types::Value::Integer(id),
+            None              => rusqlite::types::Value::Null,
+        };
+
+        let attribute_query_input = match index.a {
+            Some(Attribute(EntityId(id))) => rusqlite::types::Value::Integer(id),
+            None              => rusqlite::types::Value::Null,
+        };
+
+        use rusqlite::types::{ToSql,ToSqlOutput};
+        let value_query_input = match index.v {
+            Some(ref value) => value.to_sql().expect("Failed to convert to SQL type"),
+            None        => ToSqlOutput::Owned(rusqlite::types::Value::Null),
+        };
+
+        let tx_query_input = match index.t {
+            Some(EntityId(id)) => rusqlite::types::Value::Integer(id),
+            None              => rusqlite::types::Value::Null,
+        };
+
+        let basis_query_input = match basis {
+            Some(EntityId(id)) => rusqlite::types::Value::Integer(id),
+            None              => rusqlite::types::Value::Null,
+        };
+
+        let datoms = query.query_map(&[&entity_query_input,
+                                       &attribute_query_input,
+                                       &value_query_input,
+                                       &tx_query_input,
+                                       &basis_query_input], |row| {
+            Datom {
+                entity:    EntityId(row.get(0)),
+                attribute: Attribute(EntityId(row.get(1))),
+                value:     row.get(2),
+                tx:        row.get(3),
+                status:    Status::Asserted,
+            }
+        })?
+        .map(|r| r.map_err(|e| e.into()))
+            .collect::<Result<Vec<_>, _>>();
+
+        datoms
+    }
+
+    /// Joins a temporary table of the keys with the datoms, which uses the
+    /// eavt index, unlike the filters of `datoms`
+    fn current_values(&self, keys: &[(EntityId, Attribute)]) -> Result<BTreeMap<(EntityId, Attribute), Vec<Value>>, Error> {
+        self.conn.execute("delete from current_keys", &[])?;
+        let mut insert = self.conn.prepare_cached(
+            "insert or ignore into current_keys (e, a) values (?1, ?2)"
+        )?;
+        for (e, a) in keys {
+            insert.execute(&[&e.0, &(a.0).0])?;
+        }
+
+        let mut query = self.conn.prepare_cached(
+            "select datoms.e, datoms.a, datoms.v
+             from current_keys
+             join datoms on datoms.e = current_keys.e and datoms.a = current_keys.a
+             where datoms.retracted_tx is null
+             order by datoms.e, datoms.a, datoms.v, datoms.t asc"
+        )?;
+        let rows = query.query_map(&[], |row| {
+            ((EntityId(row.get(0)), Attribute(EntityId(row.get(1)))), row.get::<_, Value>(2))
+        })?;
+
+        let mut values = BTreeMap::new();
+        for row in rows {
+            let (key, value) = row?;
+            values.entry(key).or_insert_with(Vec::new).push(value);
+        }
+        Ok(values)
+    }
+
+    /// Deletes the datoms and their fulltext index entries and vacuums the
+    /// database file, so no deleted data is left in free pages
+    fn excise(&mut self, targets: &[(EntityId, Option<Attribute>)], record: &[Datom]) -> Result<(), Error> {
+        // The savepoint of `store_datoms` nests in this one, so the record
+        // is stored atomically with the deletions
+        self.conn.execute_batch("savepoint excise")?;
+        let result = self.delete_excised(targets).and_then(|()| self.store_datoms(record));
+        if let Err(e) = result {
+            self.conn.execute_batch("rollback to excise; release excise")?;
+            return Err(e);
+        }
+        self.conn.execute_batch("release excise")?;
+
+        // Deleted rows only mark entries of the fulltext index as deleted,
+        // merging its segments removes them
+        self.conn.execute_batch("insert into fulltext(fulltext) values('optimize')")?;
+        Ok(self.conn.execute_batch("vacuum")?)
+    }
+
+    fn highest_eid(&self, partition: Partition) -> Result<EntityId, Error> {
+        let mut stmt = self.conn.prepare_cached(
+            "select highest from sequences where partition = ?1"
+        )?;
+
+        match stmt.query_row(&[&partition.start().0], |row| EntityId(row.get(0))) {
+            Ok(highest) => Ok(highest),
+            Err(rusqlite::Error::QueryReturnedNoRows) => Ok(partition.start()),
+            Err(e) => Err(e.into()),
+        }
+    }
+
+    fn scan_log(&self, f: &mut dyn FnMut(Datom) -> Result<(), Error>) -> Result<(), Error> {
+        let mut stmt = self.conn.prepare(
+            "select t as tx, e, a, v, 1 as added from datoms
+             union all
+             select retracted_tx as tx, e, a, v, 0 as added from datoms where retracted_tx is not null
+             order by tx, added desc"
+        )?;
+
+        let mut rows = stmt.query(&[])?;
+        while let Some(row) = rows.next() {
+            let row = row?;
+            let t = EntityId(row.get(0));
+            f(Datom {
+                entity:    EntityId(row.get(1)),
+                attribute: Attribute(EntityId(row.get(2))),
+                value:     row.get(3),
+                tx:        t,
+                status:    if row.get::<_, i64>(4) == 1 { Status::Asserted } else { Status::Retracted(t) },
+            })?;
+        }
+
+        Ok(())
+    }
+
+    /// Stores all transactions until `end_bulk` in a single SQLite
+    /// transaction
+    fn begin_bulk(&mut self) -> Result<(), Error> {
+        Ok(self.conn.execute_batch("begin")?)
+    }
+
+    fn end_bulk(&mut self) -> Result<(), Error> {
+        Ok(self.conn.execute_batch("commit")?)
+    }
+
+    fn abort_bulk(&mut self) -> Result<(), Error> {
+        Ok(self.conn.execute_batch("rollback")?)
+    }
+
+    fn data_version(&self) -> Result<u64, Error> {
+        let version: i64 = self.conn.prepare_cached("pragma data_version")?
+            .query_row(&[], |row| row.get(0))?;
+        Ok(version as u64)
+    }
+
+    fn fulltext(&self, attribute: Attribute, query: &str) -> Result<Vec<(EntityId, Value, f64)>, Error> {
+        let mut stmt = self.conn.prepare_cached(
+            "select e, v, -bm25(fulltext) from fulltext
+             where fulltext match ?2
+               and a = ?1
+             order by rank"
+        )?;
+
+        let results = stmt.query_map(&[&(attribute.0).0, &query], |row| {
+            (EntityId(row.get(0)), Value::Str(row.get(1)), row.get(2))
+        })?
+        .map(|r| r.map_err(|e| e.into()))
+            .collect::<Result<Vec<_>, _>>();
+
+        results
+    }
+}
+
+mod type_impls {
+    use super::*;
+
+    use rusqlite::types;
+    use rusqlite::types::{ValueRef, ToSqlOutput, FromSqlResult};
+
+    impl types::FromSql for Status {
+        fn column_result(value: types::ValueRef) -> FromSqlResult<Self> {
+            match value {
+                ValueRef::Null        => Ok(Status::Asserted),
+                ValueRef::Integer(tx) => Ok(Status::Retracted(EntityId(tx))),
+                _                     => unreachable!()
+            }
+        }
+    }
+
+    impl types::ToSql for Status {
+        fn to_sql(&self) -> rusqlite::Result<ToSqlOutput> {
+            match *self {
+                Status::Asserted => Ok(types::Null.into()),
+                Status::Retracted(EntityId(tx)) => Ok(tx.into()),
+            }
+        }
+    }
+
+    impl types::FromSql for Value {
+        fn column_result(value: ValueRef) -> FromSqlResult<Self> {
+            if let ValueRef::Text(json) = value {
+                // ValueRef::Text(t)    => Ok(Value::Str(t.into())),
+                serde_json::from_str(json)
+                    .map_err(|err| types::FromSqlError::Other(Box::new(err)))
+            } else {
+                Err(types::FromSqlError::InvalidType)
+            }
+        }
+    }
+
+    impl types::ToSql for Value {
+        fn to_sql(&self) -> rusqlite::Result<ToSqlOutput> {
+            let json = serde_json::to_string(self).unwrap();
+            Ok(ToSqlOutput::Owned(types::Value::Text(json)))
+        }
+    }
+
+    impl types::FromSql for EntityId {
+        fn column_result(value: types::ValueRef) -> FromSqlResult<Self> {
+            value.as_i64().map(EntityId)
+        }
+    }
+
+    impl types::ToSql for EntityId {
+        fn to_sql(&self) -> rusqlite::Result<ToSqlOutput> {
+            Ok(types::Value::Integer(self.0).into())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::SqliteStorage;
+    use {attr, tempid, Assert, Datom, Db, EntityId, Excision, Index, Partition, Status, Storage, Value};
+    use std::fs;
+
+    #[test]
+    fn reopen_keeps_sequences() {
+        let path = ::tests::temp_dir().with_extension("sqlite");
+        let (user, tx) = {
+            let mut db = Db::open(&path).unwrap();
+            db.transact(&[(Assert, tempid(), "db/ident", "person/name")]).unwrap();
+            db.transact(&[(Assert, tempid(), "person/name", "Karl")]).unwrap();
+            (db.highest_eid(Partition::User).unwrap(), db.highest_eid(Partition::Tx).unwrap())
+        };
+
+        let db = Db::open(&path).unwrap();
+        assert_eq!(user, db.highest_eid(Partition::User).unwrap());
+        assert_eq!(tx, db.highest_eid(Partition::Tx).unwrap());
+        assert_eq!(Some(Partition::User), Partition::of(user));
+        assert_eq!(Some(Partition::Custom(3)), Partition::of(::EntityId(Partition::Custom(3).end().0 - 1)));
+        assert_eq!(None, Partition::of(::EntityId(11)));
+    }
+
+    #[test]
+    fn excise_leaves_nothing_in_file() {
+        let path = ::tests::temp_dir().with_extension("sqlite");
+        let mut db = Db::open(&path).unwrap();
+        let text = tempid();
+        db.transact(&[(Assert, text.clone(), "db/ident", Value::from("diary.entry/text")),
+                      (Assert, text, "db/fulltext", Value::Bool(true))]).unwrap();
+        let entry = tempid();
+        let entry = db.transact(&[(Assert, entry.clone(), "diary.entry/text", "Meeting in Zanzibar")]).unwrap()
+            .tempid_mappings[&entry];
+        db.transact(&[(Assert, tempid(), "diary.entry/text", "Meeting in Hamburg")]).unwrap();
+
+        db.excise(&[Excision::Entity(entry)]).unwrap();
+
+        // The fulltext index stores words in lower case
+        let contains = |word: &[u8]| fs::read(&path).unwrap().windows(word.len()).any(|w| w.eq_ignore_ascii_case(word));
+        assert!(!contains(b"zanzibar"));
+        assert!(contains(b"hamburg"));
+    }
+
+    #[test]
+    fn failing_excision_record_keeps_datoms() {
+        let mut storage = SqliteStorage::in_memory().unwrap();
+        let datom = |e: i64, tx: i64| Datom {
+            entity: EntityId(e),
+            attribute: attr::doc,
+            value: Value::from("Karl"),
+            tx: EntityId(tx),
+            status: Status::Asserted,
+        };
+        let entity = Partition::User.start();
+        storage.store_datoms(&[datom(entity.0, 1)]).unwrap();
+
+        // Entity ids can't be negative
+        assert!(storage.excise(&[(entity, None)], &[datom(-1, 2)]).is_err());
+        assert_eq!(1, storage.datoms(&Index::Eavt.e(entity), None).unwrap().len());
+    }
+}
@@ -0,0 +1,89 @@
+use {Attribute, Datom, Datoms, EntityId, FilteredIndex, MemoryStorage, Partition, Storage, TxId, Value};
+use error::Error;
+
+use std::collections::BTreeMap;
+use std::io;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+
+/// Calls of a `CountingStorage` so far
+#[derive(Debug, Default)]
+pub struct Calls {
+    data_version: AtomicUsize,
+    /// Calls of `datoms` and `current_values`
+    reads: AtomicUsize,
+    /// Whether reads and `data_version` fail
+    failing: AtomicBool,
+}
+
+impl Calls {
+    pub fn data_version(&self) -> usize {
+        self.data_version.load(Ordering::SeqCst)
+    }
+
+    pub fn reads(&self) -> usize {
+        self.reads.load(Ordering::SeqCst)
+    }
+
+    /// Makes all further reads and `data_version` calls fail
+    pub fn fail(&self) {
+        self.failing.store(true, Ordering::SeqCst);
+    }
+
+    fn check(&self) -> Result<(), Error> {
+        if self.failing.load(Ordering::SeqCst) {
+            return Err(io::Error::from(io::ErrorKind::Other).into());
+        }
+        Ok(())
+    }
+}
+
+/// A `MemoryStorage` counting how often `Db` reads from it
+#[derive(Debug)]
+pub struct CountingStorage {
+    storage: MemoryStorage,
+    calls: Arc<Calls>,
+}
+
+impl CountingStorage {
+    pub fn new() -> (Self, Arc<Calls>) {
+        let calls = Arc::new(Calls::default());
+        (CountingStorage { storage: MemoryStorage::new(), calls: calls.clone() }, calls)
+    }
+}
+
+impl Storage for CountingStorage {
+    fn store_datoms(&mut self, datoms: &[Datom]) -> Result<(), Error> {
+        self.storage.store_datoms(datoms)
+    }
+
+    fn datoms(&self, index: &FilteredIndex, basis: Option<TxId>) -> Result<Datoms<'_>, Error> {
+        self.calls.reads.fetch_add(1, Ordering::SeqCst);
+        self.calls.check()?;
+        self.storage.datoms(index, basis)
+    }
+
+    fn current_values(&self, keys: &[(EntityId, Attribute)]) -> Result<BTreeMap<(EntityId, Attribute), Vec<Value>>, Error> {
+        self.calls.reads.fetch_add(1, Ordering::SeqCst);
+        self.calls.check()?;
+        self.storage.current_values(keys)
+    }
+
+    fn highest_eid(&self, partition: Partition) -> Result<EntityId, Error> {
+        self.storage.highest_eid(partition)
+    }
+
+    fn scan_log(&self, f: &mut dyn FnMut(Datom) -> Result<(), Error>) -> Result<(), Error> {
+        self.storage.scan_log(f)
+    }
+
+    fn excise(&mut self, targets: &[(EntityId, Option<Attribute>)], record: &[Datom]) -> Result<(), Error> {
+        self.storage.excise(targets, record)
+    }
+
+    fn data_version(&self) -> Result<u64, Error> {
+        self.calls.data_version.fetch_add(1, Ordering::SeqCst);
+        self.calls.check()?;
+        Ok(0)
+    }
+}
@@ -1,7 +1,7 @@
 use ::*;
 
 fn db() -> Db {
-    Db::new().unwrap()
+    super::new_db()
 }
 
 fn validate_datoms(datoms: &[Datom]) {
@@ -170,7 +170,7 @@ fn test_aevt_datoms() {
     let karl = db.tempid();
     let heinz = db.tempid();
 
-    let data_tx = &[(Assert, karl, "person/name", Value::Str("Karl".into())),
+    let data_tx = &[(Assert, karl.clone(), "person/name", Value::Str("Karl".into())),
                     (Assert, karl, "person/age", 42.into()),
                     (Assert, heinz, "person/name", "Heinz".into())];
     db.transact(data_tx).unwrap();
@@ -186,8 +186,8 @@ fn test_aevt_datoms() {
 fn test_fn_attribute() {
     let mut db = db();
     // TODO: Use `str` as db/ident
-    let schema = &[(Assert, TempId(42), "db/ident", Value::Str("person_name".into())),
-                   (Assert, TempId(42), "db/doc", Value::Str("The name of a person".into()))];
+    let schema = &[(Assert, TempId::Id(42), "db/ident", Value::Str("person_name".into())),
+                   (Assert, TempId::Id(42), "db/doc", Value::Str("The name of a person".into()))];
     db.transact(schema).unwrap();
     assert!(db.attribute("person_name").is_some());
 }
@@ -213,21 +213,307 @@ fn test_string_attributes() {
 fn test_highest_eid() {
     let mut db = db();
     for &partition in &[Partition::Db, Partition::Tx, Partition::User] {
-        assert_eq!(db.highest_eid(partition).0 & partition as i64, partition as i64);
+        assert!(partition.contains(db.highest_eid(partition).unwrap()));
     }
 
     // After the transaction of a new `db/ident` `highest_eid` should
     // return a bigger value for the tx and the db but not for the user
     // part.
-    let old_db = db.highest_eid(Partition::Db);
-    let old_tx = db.highest_eid(Partition::Tx);
-    let old_user = db.highest_eid(Partition::User);
+    let old_db = db.highest_eid(Partition::Db).unwrap();
+    let old_tx = db.highest_eid(Partition::Tx).unwrap();
+    let old_user = db.highest_eid(Partition::User).unwrap();
 
     db.transact(&[(Assert, tempid(), "db/ident", "foo/bar")]).unwrap();
 
-    assert_eq!(old_db.0 + 1, db.highest_eid(Partition::Db).0);
-    assert_eq!(old_tx.0 + 1, db.highest_eid(Partition::Tx).0);
-    assert_eq!(old_user, db.highest_eid(Partition::User));
+    assert_eq!(old_db.0 + 1, db.highest_eid(Partition::Db).unwrap().0);
+    assert_eq!(old_tx.0 + 1, db.highest_eid(Partition::Tx).unwrap().0);
+    assert_eq!(old_user, db.highest_eid(Partition::User).unwrap());
+}
+
+#[test]
+fn test_custom_partition() {
+    let mut db = db();
+    db.transact(&[(Assert, tempid(), "db/ident", "tenant/name")]).unwrap();
+
+    let tenant = Partition::Custom(7);
+    let first = db.allocate_eid(tenant).unwrap();
+    let second = db.allocate_eid(tenant).unwrap();
+    assert_eq!(Some(tenant), Partition::of(first));
+    assert_eq!(first.0 + 1, second.0);
+
+    db.transact(&[(Assert, second, "tenant/name", "ACME")]).unwrap();
+    assert_eq!(second, db.highest_eid(tenant).unwrap());
+    assert_eq!(Partition::Custom(8).start(), db.highest_eid(Partition::Custom(8)).unwrap());
+
+    // Entities in other partitions don't move the highest id
+    let user = db.highest_eid(Partition::User).unwrap();
+    db.transact(&[(Assert, tempid(), "tenant/name", "Initech")]).unwrap();
+    assert_eq!(user.0 + 1, db.highest_eid(Partition::User).unwrap().0);
+    assert_eq!(second, db.highest_eid(tenant).unwrap());
+}
+
+#[test]
+fn test_named_tempids() {
+    let mut db = db();
+    db.transact(&[(Assert, TempId::named("name"), "db/ident", "person/name"),
+                  (Assert, TempId::named("friend"), "db/ident", "person/friend")]).unwrap();
+
+    let karl = TempId::named("karl");
+    assert_eq!(karl, TempId::named("karl"));
+    assert_ne!(karl, TempId::named("anna"));
+
+    let txd = db.transact(vec![Operation::from(&(Assert, TempId::named("karl"), "person/name", "Karl")),
+                               Operation::from(&(Assert, TempId::named("anna"), "person/name", "Anna")),
+                               Operation::from(&(Assert, TempId::named("anna"), "person/friend", karl.clone()))]).unwrap();
+    let (karl, anna) = (txd.tempid_mappings[&karl], txd.tempid_mappings[&TempId::named("anna")]);
+    assert_ne!(karl, anna);
+    assert_eq!(db.entity(anna).unwrap()["person/friend"], Value::Ref(karl));
+
+    // Names are scoped to their transaction
+    let txd = db.transact(&[(Assert, TempId::named("karl"), "person/name", "Karl")]).unwrap();
+    assert_ne!(karl, txd.tempid_mappings[&TempId::named("karl")]);
+}
+
+#[test]
+fn test_chosen_and_generated_tempids() {
+    let mut db = db();
+    db.transact(&[(Assert, tempid(), "db/ident", "person/name")]).unwrap();
+
+    // Chosen tempids never collide with generated ones
+    let generated = (0..3).map(|_| db.tempid()).collect::<Vec<_>>();
+    let mut tx = generated.iter()
+        .map(|tempid| (Assert, tempid.clone(), "person/name", "Generated"))
+        .collect::<Vec<_>>();
+    tx.extend((0..3).map(|i| (Assert, TempId::Id(i), "person/name", "Chosen")));
+
+    let txd = db.transact(&tx).unwrap();
+    assert_eq!(6, txd.tempid_mappings.values().filter(|e| Partition::User.contains(**e)).count());
+}
+
+#[test]
+fn test_tempids_scoped_to_db() {
+    let (mut db, mut other) = (db(), db());
+    db.transact(&[(Assert, tempid(), "db/ident", "person/name")]).unwrap();
+
+    assert_eq!(db.tempid(), other.tempid());
+    assert_ne!(db.tempid(), db.tempid());
+
+    // A tempid which was never transacted leaves nothing behind
+    let karl = db.tempid_in(Partition::Custom(0));
+    assert!(db.transact(&[(Assert, karl.clone(), "person/name", "Karl")]).is_err());
+    let acme = db.create_partition("tenant/acme").unwrap();
+    let txd = db.transact(&[(Assert, karl.clone(), "person/name", "Karl")]).unwrap();
+    assert_eq!(Some(acme), Partition::of(txd.tempid_mappings[&karl]));
+}
+
+#[test]
+fn test_tempid_in_partition() {
+    let mut db = db();
+    db.transact(&[(Assert, tempid(), "db/ident", "person/name"),
+                  (Assert, tempid(), "db/ident", "person/friend")]).unwrap();
+
+    let acme = db.create_partition("tenant/acme").unwrap();
+    let initech = db.create_partition("tenant/initech").unwrap();
+    assert_eq!(Partition::Custom(0), acme);
+    assert_eq!(Partition::Custom(1), initech);
+    assert_eq!(acme, db.create_partition("tenant/acme").unwrap());
+    assert_eq!(Some(initech), db.partition("tenant/initech").unwrap());
+    assert_eq!(vec![("tenant/acme".to_string(), acme), ("tenant/initech".to_string(), initech)],
+               db.partitions().unwrap());
+
+    let (karl, anna, otto) = (db.tempid_in(acme), db.tempid_in(acme), db.tempid_in(initech));
+    let txd = db.transact(&[(Assert, karl.clone(), "person/name", "Karl"),
+                            (Assert, anna.clone(), "person/name", "Anna"),
+                            (Assert, otto.clone(), "person/name", "Otto")]).unwrap();
+    let entity = |tempid| txd.tempid_mappings[&tempid];
+    let friend = tempid();
+    let user = db.transact(&[(Assert, friend.clone(), "person/friend", entity(karl.clone()))]).unwrap().tempid_mappings[&friend];
+
+    assert_eq!(Some(acme), Partition::of(entity(karl.clone())));
+    assert_eq!(entity(karl).0 + 1, entity(anna.clone()).0);
+    assert_eq!(Some(initech), Partition::of(entity(otto)));
+    assert_eq!(Some(Partition::User), Partition::of(user));
+    assert_eq!(entity(anna), db.highest_eid(acme).unwrap());
+
+    // Refs to tempids are allocated in their partition as well
+    let (paul, user) = (db.tempid_in(initech), tempid());
+    let txd = db.transact(&[(Assert, user, "person/friend", paul.clone())]).unwrap();
+    assert_eq!(Some(initech), Partition::of(txd.tempid_mappings[&paul]));
+}
+
+#[test]
+fn test_error_undeclared_partition() {
+    use ::error::Error;
+
+    let mut db = db();
+    db.transact(&[(Assert, tempid(), "db/ident", "person/name")]).unwrap();
+
+    let karl = db.tempid_in(Partition::Custom(3));
+    match db.transact(&[(Assert, karl, "person/name", "Karl")]).unwrap_err() {
+        Error::TransactionError(TransactionError::UnknownPartition(3)) => (),
+        _ => panic!("")
+    }
+
+    let acme = tempid();
+    match db.transact(&[(Assert, acme.clone(), "db/ident", Value::from("tenant/acme")), (Assert, acme, "db/partition", Value::Int(-1))]).unwrap_err() {
+        Error::TransactionError(TransactionError::InvalidPartition(_)) => (),
+        _ => panic!("")
+    }
+}
+
+#[test]
+fn test_error_partition_full() {
+    use ::error::Error;
+
+    let mut db = db();
+    db.transact(&[(Assert, tempid(), "db/ident", "person/name")]).unwrap();
+    let acme = db.create_partition("tenant/acme").unwrap();
+    db.transact(&[(Assert, EntityId(acme.end().0 - 1), "person/name", "Karl")]).unwrap();
+
+    match db.allocate_eid(acme).unwrap_err() {
+        Error::TransactionError(TransactionError::PartitionFull(partition)) => assert_eq!(acme, partition),
+        _ => panic!("")
+    }
+
+    let anna = db.tempid_in(acme);
+    match db.transact(&[(Assert, anna, "person/name", "Anna")]).unwrap_err() {
+        Error::TransactionError(TransactionError::PartitionFull(partition)) => assert_eq!(acme, partition),
+        _ => panic!("")
+    }
+}
+
+#[test]
+fn test_attr_preds() {
+    use ::error::Error;
+
+    let mut db = db();
+    let age = tempid();
+    db.transact(&[(Assert, age.clone(), "db/ident", Value::from("person/age")),
+                  (Assert, age, "db/attr_preds", Value::from("db.pred/non_negative"))]).unwrap();
+    db.transact(&[(Assert, tempid(), "person/age", 31)]).unwrap();
+
+    match db.transact(&[(Assert, tempid(), "person/age", -1)]).unwrap_err() {
+        Error::TransactionError(TransactionError::AttributePredicate(attribute, predicate, _)) => {
+            assert_eq!("person/age", attribute);
+            assert_eq!("db.pred/non_negative", predicate);
+        },
+        _ => panic!("")
+    }
+
+    // Predicates registered by the application
+    let age = db.attribute("person/age").unwrap();
+    db.transact(&[(Assert, age.0, "db/attr_preds", "person/plausible_age")]).unwrap();
+    match db.transact(&[(Assert, tempid(), "person/age", 150)]).unwrap_err() {
+        Error::TransactionError(TransactionError::UnknownPredicate(_)) => (),
+        _ => panic!("")
+    }
+
+    db.register_attr_pred("person/plausible_age", |v| match v.as_int() { Some(age) => age < 130, None => false });
+    db.transact(&[(Assert, tempid(), "person/age", 42)]).unwrap();
+    assert!(db.transact(&[(Assert, tempid(), "person/age", 150)]).is_err());
+    assert_eq!(vec!["db.pred/non_negative", "person/plausible_age"], db.attribute_info("person/age").unwrap().preds);
+}
+
+#[test]
+fn test_entity_specs() {
+    use ::error::Error;
+
+    let mut db = db();
+    db.transact(&[(Assert, tempid(), "db/ident", "diary.entry/text"),
+                  (Assert, tempid(), "db/ident", "diary.entry/date"),
+                  (Assert, tempid(), "db/ident", "diary.entry/draft")]).unwrap();
+    let spec = tempid();
+    db.transact(&[(Assert, spec.clone(), "db/ident", "diary.entry/spec"),
+                  (Assert, spec.clone(), "db.entity/attrs", "diary.entry/text"),
+                  (Assert, spec.clone(), "db.entity/attrs", "diary.entry/date"),
+                  (Assert, spec, "db.entity/preds", "diary.entry/not_a_draft")]).unwrap();
+    db.register_entity_pred("diary.entry/not_a_draft", |entity| entity.get("diary.entry/draft") != Some(&Value::Bool(true)));
+
+    let entry = tempid();
+    match db.transact(&[(Assert, entry.clone(), "diary.entry/text", Value::from("Hello")),
+                        (Assert, entry.clone(), "db/ensure", Value::from("diary.entry/spec"))]).unwrap_err() {
+        Error::TransactionError(TransactionError::MissingAttribute(spec, _, attribute)) => {
+            assert_eq!("diary.entry/spec", spec);
+            assert_eq!("diary.entry/date", attribute);
+        },
+        _ => panic!("")
+    }
+
+    let txd = db.transact(&[(Assert, entry.clone(), "diary.entry/text", Value::from("Hello")),
+                            (Assert, entry.clone(), "diary.entry/date", Value::DateTime(chrono::Utc::now())),
+                            (Assert, entry.clone(), "db/ensure", Value::from("diary.entry/spec"))]).unwrap();
+    let entry = txd.tempid_mappings[&entry];
+    // `db/ensure` isn't stored
+    assert_eq!(None, db.entity(entry).unwrap().get("db/ensure"));
+
+    // Specs are checked against the entity after the transaction
+    match db.transact(&[(Assert, entry, "diary.entry/draft", Value::Bool(true)),
+                        (Assert, entry, "db/ensure", Value::from("diary.entry/spec"))]).unwrap_err() {
+        Error::TransactionError(TransactionError::EntityPredicate(_, e, predicate)) => {
+            assert_eq!(entry.0, e);
+            assert_eq!("diary.entry/not_a_draft", predicate);
+        },
+        _ => panic!("")
+    }
+
+    let text = db.entity(entry).unwrap()["diary.entry/text"].clone();
+    assert!(db.transact(&[(Retract, entry, "diary.entry/text", text.clone())]).is_ok());
+    db.transact(&[(Assert, entry, "diary.entry/text", text.clone())]).unwrap();
+    assert!(db.transact(vec![Operation::from(&(Retract, entry, "diary.entry/text", text)),
+                             Operation::from(&(Assert, entry, "db/ensure", "diary.entry/spec"))]).is_err());
+
+    match db.transact(&[(Assert, entry, "db/ensure", "diary.entry/unknown")]).unwrap_err() {
+        Error::TransactionError(TransactionError::UnknownEntitySpec(_)) => (),
+        _ => panic!("")
+    }
+}
+
+#[test]
+fn test_enum_values() {
+    use ::error::Error;
+
+    let mut db = db();
+    let status = tempid();
+    db.transact(&[(Assert, status.clone(), "db/ident", Value::from("order/status")),
+                  (Assert, status, "db/enum", Value::Bool(true)),
+                  (Assert, tempid(), "db/ident", Value::from("order/note"))]).unwrap();
+    db.transact(&[(Assert, tempid(), "db/ident", "order.status/pending"),
+                  (Assert, tempid(), "db/ident", "order.status/shipped")]).unwrap();
+    assert!(db.attribute_info("order/status").unwrap().enumerated);
+
+    let order = tempid();
+    let txd = db.transact(&[(Assert, order.clone(), "order/status", "order.status/pending"),
+                            (Assert, order.clone(), "order/note", "order.status/shipped")]).unwrap();
+    let order = txd.tempid_mappings[&order];
+    let pending = db.lookup("db/ident", "order.status/pending").unwrap().unwrap();
+
+    // Stored as a ref, read as the ident
+    let status = db.attribute("order/status").unwrap();
+    assert_eq!(Value::Ref(pending), db.datoms(Index::Eavt.e(order).a(status)).unwrap()[0].value);
+    assert_eq!(1, db.datoms(Index::Vaet.v(Value::Ref(pending))).unwrap().len());
+    assert_eq!(db.entity(order).unwrap()["order/status"], Value::from("order.status/pending"));
+    // Only enum attributes resolve idents
+    assert_eq!(db.entity(order).unwrap()["order/note"], Value::from("order.status/shipped"));
+
+    db.transact(&[(Assert, order, "order/status", "order.status/shipped")]).unwrap();
+    assert_eq!(db.entity(order).unwrap()["order/status"], Value::from("order.status/shipped"));
+    db.transact(&[(Retract, order, "order/status", "order.status/shipped")]).unwrap();
+    assert_eq!(None, db.entity(order).unwrap().get("order/status"));
+
+    match db.transact(&[(Assert, order, "order/status", "order.status/lost")]).unwrap_err() {
+        Error::TransactionError(TransactionError::UnknownEnumValue(attribute, value)) => {
+            assert_eq!("order/status", attribute);
+            assert_eq!("order.status/lost", value);
+        },
+        _ => panic!("")
+    }
+
+    // Enum values can be defined alongside their use
+    let mut tx = TxBuilder::new();
+    tx.add(tempid(), "db/ident", "order.status/lost")
+        .add(order, "order/status", "order.status/lost");
+    db.transact(tx).unwrap();
+    assert_eq!(db.entity(order).unwrap()["order/status"], Value::from("order.status/lost"));
 }
 
 #[test]
@@ -246,8 +532,8 @@ fn test_avet_index() {
     //
     // Current implementation: Only `db/ident` is marked as unique
 
-    db.transact(&[(Assert, TempId(0), "db/ident", Value::Str("foo/bar".into()))]).unwrap();
-    db.transact(&[(Assert, TempId(0), "foo/bar", Value::Int(42))]).unwrap();
+    db.transact(&[(Assert, TempId::Id(0), "db/ident", Value::Str("foo/bar".into()))]).unwrap();
+    db.transact(&[(Assert, TempId::Id(0), "foo/bar", Value::Int(42))]).unwrap();
 
     let datoms = db.datoms(Index::Avet).unwrap();
     assert!(datoms.len() > 0);
@@ -257,18 +543,44 @@ fn test_avet_index() {
     }
 }
 
+#[test]
+fn test_vaet_index() {
+    let mut db = db();
+    db.transact(&[(Assert, tempid(), "db/ident", "person/name"),
+                  (Assert, tempid(), "db/ident", "person/friend")]).unwrap();
+
+    let (karl, anna, otto) = (tempid(), tempid(), tempid());
+    let txd = db.transact(&[(Assert, karl.clone(), "person/name", Value::from("Karl")),
+                            (Assert, anna.clone(), "person/name", Value::from("Anna")),
+                            (Assert, otto.clone(), "person/name", Value::from("Otto"))]).unwrap();
+    let (karl, anna, otto) = (txd.tempid_mappings[&karl], txd.tempid_mappings[&anna], txd.tempid_mappings[&otto]);
+    db.transact(&[(Assert, anna, "person/friend", Value::Ref(karl)),
+                  (Assert, otto, "person/friend", Value::Ref(karl))]).unwrap();
+
+    // Only ref values are in VAET
+    let datoms = db.datoms(Index::Vaet).unwrap();
+    assert!(datoms.iter().all(|d| d.value.as_ref_id().is_some()));
+
+    let referencing = |db: &Db| db.datoms(Index::Vaet.v(Value::Ref(karl))).unwrap()
+        .into_iter().map(|d| d.entity).collect::<Vec<_>>();
+    assert_eq!(vec![anna, otto], referencing(&db));
+
+    db.transact(&[(Retract, anna, "person/friend", Value::Ref(karl))]).unwrap();
+    assert_eq!(vec![otto], referencing(&db));
+}
+
 #[test]
 fn test_repeated_assertions() {
     let mut db = db();
     let attr_tid = db.tempid();
-    db.transact(&[(Assert, attr_tid, "db/ident", Value::Str("foo/bar".into())),
+    db.transact(&[(Assert, attr_tid.clone(), "db/ident", Value::Str("foo/bar".into())),
                   (Assert, attr_tid, "db.cardinality/many", Value::Bool(true))]).unwrap();
 
     let tid = db.tempid();
-    let txd = db.transact(&[(Assert, tid, "foo/bar", Value::Int(42)),
-                            (Assert, tid, "foo/bar", Value::Int(42)),
-                            (Assert, tid, "foo/bar", Value::Int(23)),
-                            (Assert, tid, "foo/bar", Value::Int(42))]).unwrap();
+    let txd = db.transact(&[(Assert, tid.clone(), "foo/bar", Value::Int(42)),
+                            (Assert, tid.clone(), "foo/bar", Value::Int(42)),
+                            (Assert, tid.clone(), "foo/bar", Value::Int(23)),
+                            (Assert, tid.clone(), "foo/bar", Value::Int(42))]).unwrap();
 
     let entity = db.entity(txd.tempid_mappings[&tid]).unwrap();
     assert_eq!(entity.get_many("foo/bar"),
@@ -279,7 +591,7 @@ fn test_repeated_assertions() {
 #[test]
 fn test_non_cardinality_many() {
     let mut db = db();
-    db.transact(&[(Assert, TempId(100), "db/ident", Value::Str("foo/bar".into()))]).unwrap();
+    db.transact(&[(Assert, TempId::Id(100), "db/ident", Value::Str("foo/bar".into()))]).unwrap();
 
     let eid = EntityId(1000);
     db.transact(&[(Assert, eid, "foo/bar", Value::Int(23))]).unwrap();
@@ -294,7 +606,7 @@ fn test_non_cardinality_many() {
 fn test_cardinality_many() {
     let mut db = db();
     let attr_tid = db.tempid();
-    db.transact(&[(Assert, attr_tid, "db/ident", Value::Str("foo/bar".into())),
+    db.transact(&[(Assert, attr_tid.clone(), "db/ident", Value::Str("foo/bar".into())),
                   (Assert, attr_tid, "db.cardinality/many", Value::Bool(true))]).unwrap();
 
     let eid = EntityId(1000);
@@ -309,17 +621,17 @@ fn test_cardinality_many() {
 
 #[test]
 fn test_error_changing_ident_attribute() {
-    use ::sqlite::Error;
+    use ::error::Error;
     
     let mut db = db();
-    let attr = EntityId(101010);
-    db.transact(&[(Assert, attr, "db/ident", "foo/bar")]).unwrap();
+    let attribute = EntityId(101010);
+    db.transact(&[(Assert, attribute, "db/ident", "foo/bar")]).unwrap();
 
     // Transacting the same ident is fine
-    assert!(db.transact(&[(Assert, attr, "db/ident", "foo/bar")]).is_ok());
+    assert!(db.transact(&[(Assert, attribute, "db/ident", "foo/bar")]).is_ok());
 
-    // Changing the ident is an error
-    let error = db.transact(&[(Assert, attr, "db/ident", "some.new/ident")]).unwrap_err();
+    // Renaming built-in attributes is an error
+    let error = db.transact(&[(Assert, attr::doc.0, "db/ident", "some.new/ident")]).unwrap_err();
 
     match error {
         Error::TransactionError(TransactionError::ChangingIdentAttribute(_, _)) => (),
@@ -327,9 +639,117 @@ fn test_error_changing_ident_attribute() {
     }
 }
 
+#[test]
+fn test_rename_attribute() {
+    let mut db = db();
+    let attribute = tempid();
+    let attribute = db.transact(&[(Assert, attribute.clone(), "db/ident", "person/name")]).unwrap()
+        .tempid_mappings[&attribute];
+    let karl = tempid();
+    let karl = db.transact(&[(Assert, karl.clone(), "person/name", "Karl")]).unwrap()
+        .tempid_mappings[&karl];
+
+    db.transact(&[(Assert, attribute, "db/ident", "person/full-name")]).unwrap();
+    assert_eq!(Some("person/full-name".to_string()), db.attribute_name(Attribute(attribute)));
+    assert_eq!(db.entity(karl).unwrap()["person/full-name"], Value::from("Karl"));
+
+    // The old ident is an alias
+    assert_eq!(Some(Attribute(attribute)), db.attribute("person/name"));
+    db.transact(&[(Assert, karl, "person/name", "Karl Marx")]).unwrap();
+    assert_eq!(db.entity(karl).unwrap()["person/full-name"], Value::from("Karl Marx"));
+
+    // Renaming back removes the alias
+    db.transact(&[(Assert, attribute, "db/ident", "person/name")]).unwrap();
+    let aliases = db.datoms(Index::Eavt.e(attribute).a(attr::alias)).unwrap();
+    assert_eq!(vec![Value::from("person/full-name")], aliases.into_iter().map(|d| d.value).collect::<Vec<_>>());
+
+    // A new attribute can take over an alias
+    let nickname = tempid();
+    let nickname = db.transact(&[(Assert, nickname.clone(), "db/ident", "person/full-name")]).unwrap()
+        .tempid_mappings[&nickname];
+    assert_eq!(Some(Attribute(nickname)), db.attribute("person/full-name"));
+}
+
+#[test]
+fn test_alter_cardinality() {
+    use ::error::Error;
+
+    let mut db = db();
+    let attribute = tempid();
+    let attribute = db.transact(&[(Assert, attribute.clone(), "db/ident", Value::from("person/email")),
+                                  (Assert, attribute.clone(), "db.cardinality/many", Value::Bool(true))]).unwrap()
+        .tempid_mappings[&attribute];
+
+    let karl = tempid();
+    let karl = db.transact(&[(Assert, karl.clone(), "person/email", "karl@example.com")]).unwrap()
+        .tempid_mappings[&karl];
+    db.transact(&[(Assert, karl, "person/email", "marx@example.com")]).unwrap();
+
+    // Karl has two emails
+    let error = db.transact(&[(Assert, attribute, "db.cardinality/many", Value::Bool(false))]).unwrap_err();
+    match error {
+        Error::TransactionError(TransactionError::CardinalityConflict(_, e)) => assert_eq!(karl.0, e),
+        _ => panic!("{:?}", error)
+    }
+
+    db.transact(&[(Retract, karl, "person/email", "karl@example.com")]).unwrap();
+    db.transact(&[(Assert, attribute, "db.cardinality/many", Value::Bool(false))]).unwrap();
+    assert!(!db.attribute_info("person/email").unwrap().cardinality_many);
+
+    db.transact(&[(Assert, karl, "person/email", "karl@example.com")]).unwrap();
+    assert_eq!(db.entity(karl).unwrap().get_many("person/email"), &[Value::from("karl@example.com")]);
+
+    // Back to many, which is always valid
+    db.transact(&[(Assert, attribute, "db.cardinality/many", Value::Bool(true))]).unwrap();
+    db.transact(&[(Assert, karl, "person/email", "marx@example.com")]).unwrap();
+    assert_eq!(db.entity(karl).unwrap().get_many("person/email").len(), 2);
+}
+
+#[test]
+fn test_alter_unique() {
+    use ::error::Error;
+
+    let mut db = db();
+    let attribute = tempid();
+    let attribute = db.transact(&[(Assert, attribute.clone(), "db/ident", "person/email")]).unwrap()
+        .tempid_mappings[&attribute];
+    let (karl, anna) = (tempid(), tempid());
+    let txd = db.transact(&[(Assert, karl, "person/email", "karl@example.com"),
+                            (Assert, anna.clone(), "person/email", "karl@example.com")]).unwrap();
+    let anna = txd.tempid_mappings[&anna];
+
+    let error = db.transact(&[(Assert, attribute, "db/unique", Value::Bool(true))]).unwrap_err();
+    match error {
+        Error::TransactionError(TransactionError::UniqueConflict(_, _)) => (),
+        _ => panic!("{:?}", error)
+    }
+
+    db.transact(&[(Assert, anna, "person/email", "anna@example.com")]).unwrap();
+    db.transact(&[(Assert, attribute, "db/unique", Value::Bool(true))]).unwrap();
+    assert_eq!(Some(anna), db.lookup("person/email", "anna@example.com").unwrap());
+    assert!(db.transact(&[(Assert, tempid(), "person/email", "anna@example.com")]).is_err());
+
+    // Removing uniqueness is always valid
+    db.transact(&[(Assert, attribute, "db/unique", Value::Bool(false))]).unwrap();
+    assert!(!db.attribute_info("person/email").unwrap().unique);
+    assert!(db.transact(&[(Assert, tempid(), "person/email", "anna@example.com")]).is_ok());
+}
+
+#[test]
+fn test_alter_doc() {
+    let mut db = db();
+    let attribute = tempid();
+    let attribute = db.transact(&[(Assert, attribute.clone(), "db/ident", "person/name"),
+                                  (Assert, attribute.clone(), "db/doc", "The name")]).unwrap()
+        .tempid_mappings[&attribute];
+
+    db.transact(&[(Assert, attribute, "db/doc", "The full name of a person")]).unwrap();
+    assert_eq!(Some("The full name of a person".to_string()), db.attribute_info("person/name").unwrap().doc);
+}
+
 #[test]
 fn test_error_non_ident_attribute_transacted() {
-    use ::sqlite::Error;
+    use ::error::Error;
     
     let mut db = db();
     let tx = &[(Assert, db.tempid(), "foo/bar", Value::Int(42))];
@@ -361,7 +781,7 @@ fn test_attribute_info_cardinality_many() {
     let mut db = db();
     let attr_tid = db.tempid();
     db.transact(&[(Assert, tempid(), "db/ident", Value::Str("cardinality/one".into())),
-                  (Assert, attr_tid, "db/ident", Value::Str("cardinality/many".into())),
+                  (Assert, attr_tid.clone(), "db/ident", Value::Str("cardinality/many".into())),
                   (Assert, attr_tid, "db.cardinality/many", Value::Bool(true))]).unwrap();
     assert_eq!(false, db.attribute_info("cardinality/one").unwrap().cardinality_many);
     assert_eq!(true, db.attribute_info("cardinality/many").unwrap().cardinality_many);
@@ -371,7 +791,7 @@ fn test_attribute_info_cardinality_many() {
 fn test_attribute_info_doc() {
     let mut db = db();
     let attr_tid = db.tempid();
-    db.transact(&[(Assert, attr_tid, "db/ident", Value::Str("foo/bar".into())),
+    db.transact(&[(Assert, attr_tid.clone(), "db/ident", Value::Str("foo/bar".into())),
                   (Assert, attr_tid, "db/doc", Value::Str("Foobar".into()))]).unwrap();
     assert_eq!(Some("Foobar".into()), db.attribute_info("foo/bar").unwrap().doc);
 }
@@ -381,7 +801,375 @@ fn test_attribute_info_doc() {
 fn test_attribute_info_doc_invalid_value() {
     let mut db = db();
     let attr_tid = db.tempid();
-    db.transact(&[(Assert, attr_tid, "db/ident", Value::Str("foo/bar".into())),
+    db.transact(&[(Assert, attr_tid.clone(), "db/ident", Value::Str("foo/bar".into())),
                   (Assert, attr_tid, "db/doc", Value::Int(42))]).unwrap();
     db.attribute_info("foo/bar").unwrap();
 }    
+
+fn order_schema(db: &mut Db) {
+    db.transact(&[(Assert, tempid(), "db/ident", "order/customer"),
+                  (Assert, tempid(), "db/ident", "order/number")]).unwrap();
+
+    let composite = tempid();
+    db.transact(&[(Assert, composite.clone(), "db/ident", Value::Str("order/customer+number".into())),
+                  (Assert, composite.clone(), "db/tuple_attrs", Value::Tuple(vec!["order/customer".into(),
+                                                                          "order/number".into()])),
+                  (Assert, composite, "db/unique", Value::Bool(true))]).unwrap();
+}
+
+#[test]
+fn test_composite_tuple_attribute() {
+    let mut db = db();
+    order_schema(&mut db);
+
+    let info = db.attribute_info("order/customer+number").unwrap();
+    assert!(info.unique);
+    assert_eq!(info.tuple_attrs, Some(vec![db.attribute("order/customer").unwrap(),
+                                           db.attribute("order/number").unwrap()]));
+
+    // Tuple is only asserted once all components have a value
+    let order = tempid();
+    let order = db.transact(&[(Assert, order.clone(), "order/customer", "Karl")]).unwrap()
+        .tempid_mappings[&order];
+    assert!(db.entity(order).unwrap().get("order/customer+number").is_none());
+
+    db.transact(&[(Assert, order, "order/number", 1)]).unwrap();
+    assert_eq!(db.entity(order).unwrap()["order/customer+number"],
+               Value::Tuple(vec!["Karl".into(), Value::Int(1)]));
+
+    // Changing a component updates the tuple
+    db.transact(&[(Assert, order, "order/number", 2)]).unwrap();
+    assert_eq!(db.entity(order).unwrap().get_many("order/customer+number"),
+               &[Value::Tuple(vec!["Karl".into(), Value::Int(2)])]);
+
+    // Retracting a component retracts the tuple
+    db.transact(&[(Retract, order, "order/number", 2)]).unwrap();
+    assert!(db.entity(order).unwrap().get("order/customer+number").is_none());
+}
+
+#[test]
+fn test_composite_tuple_lookup() {
+    let mut db = db();
+    order_schema(&mut db);
+
+    let order = tempid();
+    let order = db.transact(&[(Assert, order.clone(), "order/customer", Value::from("Karl")),
+                              (Assert, order.clone(), "order/number", Value::Int(1))]).unwrap()
+        .tempid_mappings[&order];
+
+    let tuple = Value::Tuple(vec!["Karl".into(), Value::Int(1)]);
+    assert_eq!(Some(order), db.lookup("order/customer+number", tuple).unwrap());
+
+    let tuple = Value::Tuple(vec!["Karl".into(), Value::Int(2)]);
+    assert_eq!(None, db.lookup("order/customer+number", tuple).unwrap());
+}
+
+#[test]
+fn test_composite_tuple_lookup_ref() {
+    use ::error::Error;
+
+    let mut db = db();
+    order_schema(&mut db);
+    db.transact(&[(Assert, tempid(), "db/ident", "order/status")]).unwrap();
+
+    let order = tempid();
+    let order = db.transact(&[(Assert, order.clone(), "order/customer", Value::from("Karl")),
+                              (Assert, order.clone(), "order/number", Value::Int(1))]).unwrap()
+        .tempid_mappings[&order];
+
+    let karl_1 = LookupRef::new("order/customer+number", Value::Tuple(vec!["Karl".into(), Value::Int(1)]));
+    db.transact(&[(Assert, karl_1.clone(), "order/status", "shipped")]).unwrap();
+    assert_eq!(db.entity(order).unwrap()["order/status"], Value::from("shipped"));
+
+    let mut tx = TxBuilder::new();
+    tx.entity(karl_1.clone()).set("order/status", "delivered");
+    db.transact(tx).unwrap();
+    assert_eq!(db.entity(order).unwrap()["order/status"], Value::from("delivered"));
+
+    db.transact(&[(Retract, karl_1, "order/status", "delivered")]).unwrap();
+    assert_eq!(None, db.entity(order).unwrap().get("order/status"));
+
+    let karl_2 = LookupRef::new("order/customer+number", Value::Tuple(vec!["Karl".into(), Value::Int(2)]));
+    match db.transact(&[(Assert, karl_2, "order/status", "shipped")]).unwrap_err() {
+        Error::TransactionError(TransactionError::LookupRefNotFound(..)) => (),
+        _ => panic!("")
+    }
+}
+
+#[test]
+fn test_composite_tuple_unique_conflict() {
+    use ::error::Error;
+
+    let mut db = db();
+    order_schema(&mut db);
+
+    let order = tempid();
+    db.transact(&[(Assert, order.clone(), "order/customer", Value::from("Karl")),
+                  (Assert, order, "order/number", Value::Int(1))]).unwrap();
+
+    let other = tempid();
+    let error = db.transact(&[(Assert, other.clone(), "order/customer", Value::from("Karl")),
+                              (Assert, other, "order/number", Value::Int(1))]).unwrap_err();
+    match error {
+        Error::TransactionError(TransactionError::UniqueConflict(_, _)) => (),
+        _ => panic!("")
+    }
+
+    // Composite tuples can't be transacted directly
+    let tuple = Value::Tuple(vec!["Heinz".into(), Value::Int(1)]);
+    let error = db.transact(&[(Assert, tempid(), "order/customer+number", tuple)]).unwrap_err();
+    match error {
+        Error::TransactionError(TransactionError::CompositeTupleAttribute(_)) => (),
+        _ => panic!("")
+    }
+}
+
+#[test]
+fn test_unique_attribute() {
+    use ::error::Error;
+
+    let mut db = db();
+    let attr_tid = db.tempid();
+    db.transact(&[(Assert, attr_tid.clone(), "db/ident", Value::Str("person/email".into())),
+                  (Assert, attr_tid, "db/unique", Value::Bool(true))]).unwrap();
+
+    let karl = tempid();
+    let karl = db.transact(&[(Assert, karl.clone(), "person/email", "karl@example.com")]).unwrap()
+        .tempid_mappings[&karl];
+    assert_eq!(Some(karl), db.lookup("person/email", "karl@example.com").unwrap());
+
+    let error = db.transact(&[(Assert, tempid(), "person/email", "karl@example.com")]).unwrap_err();
+    match error {
+        Error::TransactionError(TransactionError::UniqueConflict(_, _)) => (),
+        _ => panic!("")
+    }
+
+    // Once retracted the value can be used by another entity
+    db.transact(&[(Retract, karl, "person/email", "karl@example.com")]).unwrap();
+    assert!(db.transact(&[(Assert, tempid(), "person/email", "karl@example.com")]).is_ok());
+}
+
+#[test]
+fn test_fulltext() {
+    let mut db = db();
+    let attr_tid = db.tempid();
+    db.transact(&[(Assert, attr_tid.clone(), "db/ident", Value::Str("diary.entry/text".into())),
+                  (Assert, attr_tid, "db/fulltext", Value::Bool(true))]).unwrap();
+    assert!(db.attribute_info("diary.entry/text").unwrap().fulltext);
+
+    let (summer, winter) = (tempid(), tempid());
+    let txd = db.transact(&[(Assert, summer.clone(), "diary.entry/text", "Summer holiday at the sea"),
+                            (Assert, winter.clone(), "diary.entry/text", "Back to work after the winter holiday holiday")]).unwrap();
+    let summer = txd.tempid_mappings[&summer];
+    let winter = txd.tempid_mappings[&winter];
+
+    let results = db.fulltext("diary.entry/text", "holiday").unwrap();
+    assert_eq!(results.iter().map(|r| r.0).collect::<Vec<_>>(), vec![winter, summer]);
+    assert_eq!(results[1].1, Value::Str("Summer holiday at the sea".into()));
+    assert!(results[0].2 > results[1].2);
+
+    // Changing a value updates the index
+    db.transact(&[(Assert, summer, "diary.entry/text", "Summer at the sea")]).unwrap();
+    let results = db.fulltext("diary.entry/text", "holiday").unwrap();
+    assert_eq!(results.iter().map(|r| r.0).collect::<Vec<_>>(), vec![winter]);
+
+    db.transact(&[(Retract, winter, "diary.entry/text", "Back to work after the winter holiday holiday")]).unwrap();
+    assert!(db.fulltext("diary.entry/text", "holiday").unwrap().is_empty());
+}
+
+#[test]
+fn test_fulltext_existing_values() {
+    let mut db = db();
+    let attr_tid = db.tempid();
+    let attribute = db.transact(&[(Assert, attr_tid.clone(), "db/ident", "diary.entry/text")]).unwrap()
+        .tempid_mappings[&attr_tid];
+    db.transact(&[(Assert, tempid(), "diary.entry/text", "Holiday")]).unwrap();
+    assert!(db.fulltext("diary.entry/text", "holiday").unwrap().is_empty());
+
+    // Values asserted before the attribute was marked as `db/fulltext`
+    // are indexed too
+    db.transact(&[(Assert, attribute, "db/fulltext", Value::Bool(true))]).unwrap();
+    assert_eq!(db.fulltext("diary.entry/text", "holiday").unwrap().len(), 1);
+
+    db.transact(&[(Assert, attribute, "db/fulltext", Value::Bool(false))]).unwrap();
+    assert!(db.fulltext("diary.entry/text", "holiday").unwrap().is_empty());
+}
+
+#[test]
+fn test_export_import() {
+    let mut db = db();
+    let attribute = db.tempid();
+    let entry = db.tempid();
+    let txd = db.transact(&[(Assert, attribute.clone(), "db/ident", Value::from("diary.entry/text")),
+                            (Assert, attribute.clone(), "db/fulltext", Value::Bool(true))]).unwrap();
+    let attribute = txd.tempid_mappings[&attribute];
+    let entry = db.transact(&[(Assert, entry.clone(), "diary.entry/text", "Holiday")]).unwrap()
+        .tempid_mappings[&entry];
+    db.transact(&[(Assert, entry, "diary.entry/text", "Holiday at the sea")]).unwrap();
+
+    let mut dump = vec![];
+    db.export(&mut dump).unwrap();
+    assert_eq!(dump.iter().filter(|&&b| b == b'\n').count(), 3);
+
+    let mut restored = super::new_db();
+    restored.import(&dump[..]).unwrap();
+
+    assert_eq!(restored.all_datoms(), db.all_datoms());
+    assert_eq!(restored.attribute("diary.entry/text").unwrap(), Attribute(attribute));
+    assert_eq!(restored.entity(entry).unwrap()["diary.entry/text"], Value::from("Holiday at the sea"));
+    assert_eq!(restored.fulltext("diary.entry/text", "sea").unwrap().len(), 1);
+    assert_eq!(restored.fulltext("diary.entry/text", "holiday").unwrap().len(), 1);
+
+    // Ids are preserved, so importing into a non-empty database fails
+    assert!(restored.import(&dump[..]).is_err());
+}
+
+#[test]
+fn test_import_invalid_log() {
+    let mut db = db();
+    let attribute = db.tempid();
+    let entry = db.tempid();
+    db.transact(&[(Assert, attribute, "db/ident", "diary.entry/text")]).unwrap();
+    db.transact(&[(Assert, entry, "diary.entry/text", "Holiday")]).unwrap();
+
+    let mut dump = vec![];
+    db.export(&mut dump).unwrap();
+    let truncated = &dump[..dump.len() - 10];
+
+    let mut restored = super::new_db();
+    let empty = restored.all_datoms();
+    assert!(restored.import(truncated).is_err());
+    assert_eq!(restored.all_datoms(), empty);
+    assert_eq!(restored.attribute("diary.entry/text"), None);
+
+    // Nothing of the failed import is left over, so the full log imports
+    restored.import(&dump[..]).unwrap();
+    assert_eq!(restored.all_datoms(), db.all_datoms());
+}
+
+#[test]
+fn test_as_of() {
+    let mut db = db();
+    let attribute = db.tempid();
+    db.transact(&[(Assert, attribute, "db/ident", "diary.entry/text")]).unwrap();
+    let entry = db.tempid();
+    let txd = db.transact(&[(Assert, entry.clone(), "diary.entry/text", "First")]).unwrap();
+    let (entry, first_tx) = (txd.tempid_mappings[&entry], txd.tx_id);
+    db.transact(&[(Assert, entry, "diary.entry/text", "Second")]).unwrap();
+
+    db.set_as_of(Some(first_tx));
+    assert_eq!(db.as_of(), Some(first_tx));
+    assert_eq!(db.entity(entry).unwrap()["diary.entry/text"], Value::from("First"));
+    assert!(db.transact(&[(Assert, entry, "diary.entry/text", "Third")]).is_err());
+
+    db.set_as_of(None);
+    assert_eq!(db.entity(entry).unwrap()["diary.entry/text"], Value::from("Second"));
+}
+
+#[test]
+fn test_migrate() {
+    let mut db = db();
+    let migrations = vec![
+        Migration::new("person/schema", &[(Assert, tempid(), "db/ident", "person/name")]),
+        Migration::new("person/email", &[(Assert, tempid(), "db/ident", "person/email")]),
+    ];
+
+    let applied = db.migrate(&migrations).unwrap();
+    assert_eq!(2, applied.len());
+    assert!(db.has_attribute("person/name") && db.has_attribute("person/email"));
+
+    // Migrations are recorded on their transaction
+    let names = db.migrations().unwrap();
+    assert_eq!(vec![("person/schema".to_string(), applied[0].tx_id),
+                    ("person/email".to_string(), applied[1].tx_id)], names);
+    assert!(db.entity(applied[0].tx_id).unwrap().get("db/tx_instant").is_some());
+
+    // Only new migrations run
+    let mut migrations = migrations;
+    migrations.push(Migration::new("person/age", &[(Assert, tempid(), "db/ident", "person/age")]));
+    let applied = db.migrate(&migrations).unwrap();
+    assert_eq!(1, applied.len());
+    assert!(db.migrate(&migrations).unwrap().is_empty());
+
+    // A failing migration isn't recorded
+    let failing = Migration::new("person/nickname", &[(Assert, tempid(), "person/nickname", "Kalle")]);
+    assert!(db.migrate(&[failing]).is_err());
+    assert_eq!(3, db.migrations().unwrap().len());
+}
+
+#[test]
+fn test_excise_composite_tuple_component() {
+    let mut db = db();
+    order_schema(&mut db);
+
+    let order = tempid();
+    let order = db.transact(&[(Assert, order.clone(), "order/customer", Value::from("Karl")),
+                              (Assert, order.clone(), "order/number", Value::Int(1))]).unwrap()
+        .tempid_mappings[&order];
+    let tuple = Value::Tuple(vec!["Karl".into(), Value::Int(1)]);
+    assert_eq!(db.lookup("order/customer+number", tuple.clone()).unwrap(), Some(order));
+
+    db.excise(&[Excision::Attribute(order, "order/customer".into())]).unwrap();
+
+    let entity = db.entity(order).unwrap();
+    assert_eq!(None, entity.get("order/customer"));
+    assert_eq!(None, entity.get("order/customer+number"));
+    assert_eq!(entity["order/number"], Value::Int(1));
+    assert_eq!(db.lookup("order/customer+number", tuple).unwrap(), None);
+    assert!(db.all_datoms().iter().all(|d| d.entity != order || d.value != Value::from("Karl")));
+    assert_eq!(vec![Excision::Attribute(order, "order/customer".into()),
+                    Excision::Attribute(order, "order/customer+number".into())],
+               db.excisions().unwrap());
+}
+
+#[test]
+fn test_excise() {
+    use ::error::Error;
+
+    let mut db = db();
+    db.transact(&[(Assert, tempid(), "db/ident", "person/name"),
+                  (Assert, tempid(), "db/ident", "person/email")]).unwrap();
+    let bio = tempid();
+    db.transact(&[(Assert, bio.clone(), "db/ident", Value::from("person/bio")),
+                  (Assert, bio, "db/fulltext", Value::Bool(true))]).unwrap();
+
+    let (karl, anna) = (tempid(), tempid());
+    let txd = db.transact(&[(Assert, karl.clone(), "person/name", "Karl"),
+                            (Assert, karl.clone(), "person/email", "karl@example.com"),
+                            (Assert, karl.clone(), "person/bio", "Likes the sea"),
+                            (Assert, anna.clone(), "person/name", "Anna"),
+                            (Assert, anna.clone(), "person/email", "anna@example.com")]).unwrap();
+    let (karl, anna) = (txd.tempid_mappings[&karl], txd.tempid_mappings[&anna]);
+    db.transact(&[(Assert, karl, "person/name", "Karl Marx")]).unwrap();
+
+    let txd = db.excise(&[Excision::Entity(karl), Excision::Attribute(anna, "person/email".into())]).unwrap();
+
+    // Neither current nor historical datoms are left
+    assert!(db.all_datoms().iter().all(|d| d.entity != karl));
+    assert!(db.all_datoms().iter().all(|d| d.entity != anna || d.attribute != db.attribute("person/email").unwrap()));
+    assert!(db.entity(karl).unwrap().values.is_empty());
+    assert!(db.fulltext("person/bio", "sea").unwrap().is_empty());
+    assert_eq!(db.entity(anna).unwrap()["person/name"], Value::from("Anna"));
+    assert_eq!(None, db.entity(anna).unwrap().get("person/email"));
+
+    // The excisions are recorded in the transaction log
+    assert!(db.tx_log().unwrap().iter().any(|(tx, _)| *tx == txd.tx_id));
+    assert_eq!(vec![Excision::Entity(karl), Excision::Attribute(anna, "person/email".into())],
+               db.excisions().unwrap());
+
+    // New entities don't reuse the ids of excised ones
+    let otto = tempid();
+    let otto = db.transact(&[(Assert, otto.clone(), "person/name", "Otto")]).unwrap().tempid_mappings[&otto];
+    assert!(otto > karl && otto > anna);
+
+    // Attributes and transactions can't be excised
+    let name = db.attribute("person/name").unwrap();
+    match db.excise(&[Excision::Entity(name.0)]).unwrap_err() {
+        Error::TransactionError(TransactionError::InvalidExcision(_)) => (),
+        _ => panic!("")
+    }
+    match db.excise(&[Excision::Attribute(otto, "person/unknown".into())]).unwrap_err() {
+        Error::TransactionError(TransactionError::UnknownAttribute(_)) => (),
+        _ => panic!("")
+    }
+}
@@ -4,13 +4,13 @@ use ::*;
 pub fn test_usage_001() {
     let mut db = Db::new().unwrap();
     let tid = db.tempid();
-    let schema = &[(Assert, tid, "db/ident", "person/name".into()),
+    let schema = &[(Assert, tid.clone(), "db/ident", "person/name".into()),
                    (Assert, tid, "db/doc",   "The name of a person")];
     db.transact(schema).unwrap();
 
-    let persons = &[(Assert, TempId(0), "person/name", "Karl".to_string()),
-                    (Assert, TempId(1), "person/name", "Heinz".to_string())];
-    let heinz = db.transact(persons).unwrap().tempid_mappings[&TempId(1)];
+    let persons = &[(Assert, TempId::Id(0), "person/name", "Karl".to_string()),
+                    (Assert, TempId::Id(1), "person/name", "Heinz".to_string())];
+    let heinz = db.transact(persons).unwrap().tempid_mappings[&TempId::Id(1)];
 
     let retract_heinz_name = &[(Retract, heinz, "person/name", Value::Str("Heinz".into()))];
     db.transact(retract_heinz_name).unwrap();
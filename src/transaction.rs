@@ -4,27 +4,111 @@ use std::fmt;
 
 /// Struct containing the `tx_id` of a successful transaction and
 /// allows mapping from `TempId`s to `EntityId`s.
-#[derive(Debug)]
+#[derive(Debug, Serialize, Deserialize)]
 pub struct TransactionData {
     pub tx_id: TxId,
+    #[serde(with = "tempid_mappings")]
     pub tempid_mappings: BTreeMap<TempId, EntityId>
 }
 
+/// `TempId`s can't be keys of JSON objects, so the mappings are
+/// serialized as a list of pairs
+mod tempid_mappings {
+    use super::{EntityId, TempId};
+    use serde::{Deserialize, Deserializer, Serializer};
+    use std::collections::BTreeMap;
+
+    pub fn serialize<S: Serializer>(mappings: &BTreeMap<TempId, EntityId>, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq(mappings)
+    }
+
+    pub fn deserialize<'de, D: Deserializer<'de>>(deserializer: D) -> Result<BTreeMap<TempId, EntityId>, D::Error> {
+        Ok(Vec::<(TempId, EntityId)>::deserialize(deserializer)?.into_iter().collect())
+    }
+}
+
 // TODO: Use `String` to describe the attributes
-#[derive(Debug, Fail, PartialEq, Eq)]
+#[derive(Debug, Clone, Fail, PartialEq, Eq)]
 pub enum TransactionError {
-    #[fail(display = "Tried to transact new value ({}) for existing db/ident attribute {}", _0, _1)]
+    #[fail(display = "Can't rename built-in attribute {} to {}", _0, _1)]
     ChangingIdentAttribute(String, String),
     #[fail(display = "Tried to transact unknown attribute {}", _0)]
     UnknownAttribute(String),
+    #[fail(display = "Tried to transact value for composite tuple attribute {}", _0)]
+    CompositeTupleAttribute(String),
+    #[fail(display = "Invalid value {} for db/tuple_attrs. Expected a tuple of attributes", _0)]
+    InvalidTupleAttrs(String),
+    #[fail(display = "Value {} of unique attribute {} is already asserted for another entity", _1, _0)]
+    UniqueConflict(String, String),
+    #[fail(display = "Can't transact while reading as of transaction {}", _0)]
+    AsOfBasis(i64),
+    #[fail(display = "Can't make {} cardinality one, entity {} has more than one value", _0, _1)]
+    CardinalityConflict(String, i64),
+    #[fail(display = "Invalid value {} for db/partition. Expected an integer between 0 and 2^32 - 1", _0)]
+    InvalidPartition(String),
+    #[fail(display = "Tried to allocate an entity in undeclared partition {}", _0)]
+    UnknownPartition(u32),
+    #[fail(display = "Partition {:?} has no entity ids left", _0)]
+    PartitionFull(Partition),
+    #[fail(display = "Compare-and-swap of {} on entity {} failed, the current value is {}", _0, _1, _2)]
+    CasFailed(String, i64, String),
+    #[fail(display = "Conflicting operations in transaction: {}", _0)]
+    ConflictingOperations(String),
+    #[fail(display = "Predicate {} isn't registered", _0)]
+    UnknownPredicate(String),
+    #[fail(display = "Value {} of {} doesn't satisfy {}", _2, _0, _1)]
+    AttributePredicate(String, String, String),
+    #[fail(display = "Unknown entity spec {}", _0)]
+    UnknownEntitySpec(String),
+    #[fail(display = "Entity {} lacks attribute {} required by {}", _1, _2, _0)]
+    MissingAttribute(String, i64, String),
+    #[fail(display = "Entity {} doesn't satisfy {} of {}", _1, _2, _0)]
+    EntityPredicate(String, i64, String),
+    #[fail(display = "No entity has {} for {}", _1, _0)]
+    LookupRefNotFound(String, String),
+    #[fail(display = "Can't assert tempid {} for lookup ref {}", _0, _1)]
+    TempidForLookupRef(String, String),
+    #[fail(display = "Value {} of enum attribute {} isn't an ident", _1, _0)]
+    UnknownEnumValue(String, String),
+    #[fail(display = "Can't excise entity {}, only entities in the user and custom partitions can be excised", _0)]
+    InvalidExcision(i64),
     // TODO: Error for setting db.cardinality/many on db/ident
 }
 
-#[derive(Debug)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub enum Operation {
     Assertion(EntityId, AttributeName, Value),
     Retraction(EntityId, AttributeName, Value),
-    TempidAssertion(TempId, AttributeName, Value)
+    TempidAssertion(TempId, AttributeName, Value),
+    /// Asserts a `Value::Ref` to the entity allocated for a `TempId`
+    RefAssertion(EntityId, AttributeName, TempId),
+    TempidRefAssertion(TempId, AttributeName, TempId),
+    /// Asserts the last value if the current value of the cardinality one
+    /// attribute is the expected one, `None` meaning no current value
+    Cas(EntityId, AttributeName, Option<Value>, Value),
+    /// Asserts a value for the entity a lookup ref resolves to
+    LookupAssertion(LookupRef, AttributeName, Value),
+    LookupRetraction(LookupRef, AttributeName, Value),
+    /// The operation on the attribute with this id, e.g. of a typed `Attr`.
+    /// Its attribute name is replaced with the current ident of the
+    /// attribute when it's transacted, so renaming the attribute, or
+    /// reusing its old ident for another one, doesn't affect it.
+    ByAttribute(Attribute, Box<Operation>),
+}
+
+/// Identifies an existing entity by the value of one of its unique
+/// attributes, e.g. a composite tuple attribute and a `Value::Tuple` of
+/// its component values. See `Db::lookup`.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+pub struct LookupRef {
+    pub attribute: AttributeName,
+    pub value: Value,
+}
+
+impl LookupRef {
+    pub fn new<A: Into<AttributeName>, V: Into<Value>>(attribute: A, value: V) -> Self {
+        LookupRef { attribute: attribute.into(), value: value.into() }
+    }
 }
 
 impl Operation {
@@ -32,9 +116,41 @@ impl Operation {
         match self {
             Operation::Assertion(_, a, _) => a,
             Operation::Retraction(_, a, _) => a,
-            Operation::TempidAssertion(_, a, _) => a
+            Operation::TempidAssertion(_, a, _) => a,
+            Operation::RefAssertion(_, a, _) => a,
+            Operation::TempidRefAssertion(_, a, _) => a,
+            Operation::Cas(_, a, _, _) => a,
+            Operation::LookupAssertion(_, a, _) => a,
+            Operation::LookupRetraction(_, a, _) => a,
+            Operation::ByAttribute(_, operation) => operation.attribute_name(),
         }
     }
+
+    /// The operation on the attribute `attribute_name` instead
+    pub(crate) fn with_attribute_name(self, attribute_name: AttributeName) -> Operation {
+        match self {
+            Operation::Assertion(e, _, v)          => Operation::Assertion(e, attribute_name, v),
+            Operation::Retraction(e, _, v)         => Operation::Retraction(e, attribute_name, v),
+            Operation::TempidAssertion(e, _, v)    => Operation::TempidAssertion(e, attribute_name, v),
+            Operation::RefAssertion(e, _, v)       => Operation::RefAssertion(e, attribute_name, v),
+            Operation::TempidRefAssertion(e, _, v) => Operation::TempidRefAssertion(e, attribute_name, v),
+            Operation::Cas(e, _, expected, v)      => Operation::Cas(e, attribute_name, expected, v),
+            Operation::LookupAssertion(e, _, v)    => Operation::LookupAssertion(e, attribute_name, v),
+            Operation::LookupRetraction(e, _, v)   => Operation::LookupRetraction(e, attribute_name, v),
+            Operation::ByAttribute(_, operation)   => operation.with_attribute_name(attribute_name),
+        }
+    }
+}
+
+/// Everything `transact` accepts: iterators of operations and `TxBuilder`s
+pub trait IntoOperations {
+    fn into_operations(self) -> Result<Vec<Operation>, TransactionError>;
+}
+
+impl<O: Into<Operation>, I: IntoIterator<Item=O>> IntoOperations for I {
+    fn into_operations(self) -> Result<Vec<Operation>, TransactionError> {
+        Ok(self.into_iter().map(Into::into).collect())
+    }
 }
 
 pub struct Assert;
@@ -52,7 +168,7 @@ impl fmt::Display for UnknownAttributeError {
 impl<'a, A, V> From<&'a (Assert, TempId, A, V)> for Operation
     where A: Into<AttributeName> + Clone, V: Into<Value> + Clone {
     fn from(o: &'a (Assert, TempId, A, V)) -> Operation {
-        Operation::TempidAssertion(o.1, o.2.clone().into(), o.3.clone().into())
+        Operation::TempidAssertion(o.1.clone(), o.2.clone().into(), o.3.clone().into())
     }
 }
 
@@ -69,3 +185,31 @@ impl<'a, A, V> From<&'a (Retract, EntityId, A, V)> for Operation
         Operation::Retraction(o.1, o.2.clone().into(), o.3.clone().into())
     }
 }
+
+impl<'a, A> From<&'a (Assert, EntityId, A, TempId)> for Operation
+    where A: Into<AttributeName> + Clone {
+    fn from(o: &'a (Assert, EntityId, A, TempId)) -> Operation {
+        Operation::RefAssertion(o.1, o.2.clone().into(), o.3.clone())
+    }
+}
+
+impl<'a, A> From<&'a (Assert, TempId, A, TempId)> for Operation
+    where A: Into<AttributeName> + Clone {
+    fn from(o: &'a (Assert, TempId, A, TempId)) -> Operation {
+        Operation::TempidRefAssertion(o.1.clone(), o.2.clone().into(), o.3.clone())
+    }
+}
+
+impl<'a, A, V> From<&'a (Assert, LookupRef, A, V)> for Operation
+    where A: Into<AttributeName> + Clone, V: Into<Value> + Clone {
+    fn from(o: &'a (Assert, LookupRef, A, V)) -> Operation {
+        Operation::LookupAssertion(o.1.clone(), o.2.clone().into(), o.3.clone().into())
+    }
+}
+
+impl<'a, A, V> From<&'a (Retract, LookupRef, A, V)> for Operation
+    where A: Into<AttributeName> + Clone, V: Into<Value> + Clone {
+    fn from(o: &'a (Retract, LookupRef, A, V)) -> Operation {
+        Operation::LookupRetraction(o.1.clone(), o.2.clone().into(), o.3.clone().into())
+    }
+}
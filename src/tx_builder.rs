@@ -0,0 +1,300 @@
+use super::{Attr, AttributeName, EntityId, IntoOperations, LookupRef, Operation, TempId, TransactionError, Value};
+
+use std::collections::{BTreeMap, BTreeSet};
+
+/// Collects operations of different kinds for a single `transact` call.
+///
+/// ```ignore
+/// let karl = db.tempid();
+/// let mut tx = TxBuilder::new();
+/// tx.entity(karl)
+///     .set("person/name", "Karl")
+///     .set("person/friend", anna);
+/// tx.retract(otto, "person/friend", Value::Ref(anna));
+/// tx.cas(anna, "person/age", Some(Value::Int(30)), 31);
+/// db.transact(tx)?;
+/// ```
+///
+/// The operations are validated when they're transacted: asserting and
+/// retracting the same datom, or combining a compare-and-swap with another
+/// operation on the same attribute of the entity, fails with
+/// `TransactionError::ConflictingOperations`.
+#[derive(Debug, Clone, Default)]
+pub struct TxBuilder {
+    operations: Vec<Operation>,
+    /// An invalid operation which was added, returned by `build`
+    error: Option<TransactionError>,
+}
+
+/// The entity of an operation: an existing one, a tempid or an existing
+/// one identified by a lookup ref
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TxEntity {
+    Existing(EntityId),
+    New(TempId),
+    Lookup(LookupRef),
+}
+
+impl From<EntityId> for TxEntity {
+    fn from(eid: EntityId) -> Self {
+        TxEntity::Existing(eid)
+    }
+}
+
+impl From<TempId> for TxEntity {
+    fn from(tempid: TempId) -> Self {
+        TxEntity::New(tempid)
+    }
+}
+
+impl From<LookupRef> for TxEntity {
+    fn from(lookup_ref: LookupRef) -> Self {
+        TxEntity::Lookup(lookup_ref)
+    }
+}
+
+/// The value of an assertion. Tempids are asserted as a `Value::Ref` to
+/// their entity.
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum TxValue {
+    Value(Value),
+    Ref(TempId),
+}
+
+impl<V: Into<Value>> From<V> for TxValue {
+    fn from(value: V) -> Self {
+        TxValue::Value(value.into())
+    }
+}
+
+impl From<TempId> for TxValue {
+    fn from(tempid: TempId) -> Self {
+        TxValue::Ref(tempid)
+    }
+}
+
+impl TxBuilder {
+    pub fn new() -> Self {
+        TxBuilder::default()
+    }
+
+    /// Asserts `value` for `attribute` of `entity`. A tempid can't be
+    /// asserted for an entity identified by a lookup ref, this fails with
+    /// `TransactionError::TempidForLookupRef`.
+    pub fn add<E, A, V>(&mut self, entity: E, attribute: A, value: V) -> &mut Self
+        where E: Into<TxEntity>, A: Into<AttributeName>, V: Into<TxValue>
+    {
+        let attribute = attribute.into();
+        self.operations.push(match (entity.into(), value.into()) {
+            (TxEntity::Existing(e), TxValue::Value(v)) => Operation::Assertion(e, attribute, v),
+            (TxEntity::Existing(e), TxValue::Ref(v))   => Operation::RefAssertion(e, attribute, v),
+            (TxEntity::New(e), TxValue::Value(v))      => Operation::TempidAssertion(e, attribute, v),
+            (TxEntity::New(e), TxValue::Ref(v))        => Operation::TempidRefAssertion(e, attribute, v),
+            (TxEntity::Lookup(e), TxValue::Value(v))   => Operation::LookupAssertion(e, attribute, v),
+            (TxEntity::Lookup(e), TxValue::Ref(v))     => {
+                self.error.get_or_insert(TransactionError::TempidForLookupRef(format!("{:?}", v), format!("{:?}", e)));
+                return self
+            },
+        });
+        self
+    }
+
+    /// Asserts `value` for the typed `attr` of `entity`. The attribute is
+    /// identified by its id, see `Operation::ByAttribute`.
+    pub fn add_typed<E: Into<TxEntity>, T: Into<TxValue>>(&mut self, entity: E, attr: &Attr<T>, value: T) -> &mut Self {
+        let len = self.operations.len();
+        self.add(entity, attr.name(), value);
+        if self.operations.len() > len {
+            let operation = self.operations.pop().unwrap();
+            self.operations.push(Operation::ByAttribute(attr.attribute(), Box::new(operation)));
+        }
+        self
+    }
+
+    /// Retracts `value` of `attribute` of `entity`
+    pub fn retract<A: Into<AttributeName>, V: Into<Value>>(&mut self, entity: EntityId, attribute: A, value: V) -> &mut Self {
+        self.operations.push(Operation::Retraction(entity, attribute.into(), value.into()));
+        self
+    }
+
+    /// Asserts `value` for the cardinality one `attribute` of `entity` if
+    /// its current value is `expected`, `None` if it has no value yet.
+    /// Otherwise the transaction fails with `TransactionError::CasFailed`.
+    pub fn cas<A, V>(&mut self, entity: EntityId, attribute: A, expected: Option<Value>, value: V) -> &mut Self
+        where A: Into<AttributeName>, V: Into<Value>
+    {
+        self.operations.push(Operation::Cas(entity, attribute.into(), expected, value.into()));
+        self
+    }
+
+    /// Asserts several attributes of the same entity
+    pub fn entity<E: Into<TxEntity>>(&mut self, entity: E) -> EntityBuilder<'_> {
+        EntityBuilder { tx: self, entity: entity.into() }
+    }
+
+    /// Adds an operation as is
+    pub fn operation<O: Into<Operation>>(&mut self, operation: O) -> &mut Self {
+        self.operations.push(operation.into());
+        self
+    }
+
+    pub fn len(&self) -> usize {
+        self.operations.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.operations.is_empty()
+    }
+
+    /// Validates the operations and returns them
+    pub fn build(self) -> Result<Vec<Operation>, TransactionError> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+
+        let mut asserted = BTreeSet::new();
+        let mut retracted = BTreeSet::new();
+        let mut attributes = BTreeMap::new();
+
+        for mut operation in &self.operations {
+            while let Operation::ByAttribute(_, inner) = operation {
+                operation = inner;
+            }
+
+            let (entity, attribute, value, cas) = match operation {
+                Operation::Assertion(e, a, v)          => (TxEntity::Existing(*e), a, TxValue::Value(v.clone()), false),
+                Operation::Retraction(e, a, v)         => (TxEntity::Existing(*e), a, TxValue::Value(v.clone()), false),
+                Operation::TempidAssertion(e, a, v)    => (TxEntity::New(e.clone()), a, TxValue::Value(v.clone()), false),
+                Operation::RefAssertion(e, a, v)       => (TxEntity::Existing(*e), a, TxValue::Ref(v.clone()), false),
+                Operation::TempidRefAssertion(e, a, v) => (TxEntity::New(e.clone()), a, TxValue::Ref(v.clone()), false),
+                Operation::Cas(e, a, _, v)             => (TxEntity::Existing(*e), a, TxValue::Value(v.clone()), true),
+                Operation::LookupAssertion(e, a, v)    => (TxEntity::Lookup(e.clone()), a, TxValue::Value(v.clone()), false),
+                Operation::LookupRetraction(e, a, v)   => (TxEntity::Lookup(e.clone()), a, TxValue::Value(v.clone()), false),
+                Operation::ByAttribute(..)             => unreachable!(),
+            };
+
+            // A compare-and-swap must be the only operation on its attribute
+            let conflict = match attributes.insert((entity.clone(), attribute.clone()), cas) {
+                Some(previous_cas) => cas || previous_cas,
+                None => false,
+            };
+
+            // Asserting and retracting the same datom
+            let datom = (entity, attribute.clone(), value);
+            let (same, opposite) = match operation {
+                Operation::Retraction(..) | Operation::LookupRetraction(..) => (&mut retracted, &asserted),
+                _ => (&mut asserted, &retracted),
+            };
+            let conflict = conflict || opposite.contains(&datom);
+            same.insert(datom.clone());
+
+            if conflict {
+                return Err(TransactionError::ConflictingOperations(format!("{:?}", datom)));
+            }
+        }
+
+        Ok(self.operations)
+    }
+}
+
+impl IntoOperations for TxBuilder {
+    fn into_operations(self) -> Result<Vec<Operation>, TransactionError> {
+        self.build()
+    }
+}
+
+/// Asserts attributes of one entity of a `TxBuilder`, see
+/// `TxBuilder::entity`
+pub struct EntityBuilder<'a> {
+    tx: &'a mut TxBuilder,
+    entity: TxEntity,
+}
+
+impl<'a> EntityBuilder<'a> {
+    pub fn set<A: Into<AttributeName>, V: Into<TxValue>>(self, attribute: A, value: V) -> Self {
+        self.tx.add(self.entity.clone(), attribute, value);
+        self
+    }
+
+    pub fn set_typed<T: Into<TxValue>>(self, attr: &Attr<T>, value: T) -> Self {
+        self.tx.add_typed(self.entity.clone(), attr, value);
+        self
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {tempid, Assert, Db, Error};
+
+    fn db() -> Db {
+        let mut db = Db::new().unwrap();
+        db.transact(&[(Assert, tempid(), "db/ident", "person/name"),
+                      (Assert, tempid(), "db/ident", "person/age"),
+                      (Assert, tempid(), "db/ident", "person/friend")]).unwrap();
+        db
+    }
+
+    #[test]
+    fn mixed_operations() {
+        let mut db = db();
+        let otto = tempid();
+        let otto = db.transact(&[(Assert, otto.clone(), "person/name", "Otto")]).unwrap().tempid_mappings[&otto];
+
+        let (karl, anna) = (tempid(), tempid());
+        let mut tx = TxBuilder::new();
+        tx.entity(karl.clone())
+            .set("person/name", "Karl")
+            .set("person/friend", anna.clone());
+        tx.add(anna.clone(), "person/name", "Anna")
+            .add(otto, "person/friend", karl.clone())
+            .retract(otto, "person/name", "Otto");
+        let txd = db.transact(tx).unwrap();
+
+        let (karl, anna) = (txd.tempid_mappings[&karl], txd.tempid_mappings[&anna]);
+        assert_eq!(db.entity(karl).unwrap()["person/friend"], Value::Ref(anna));
+        assert_eq!(db.entity(otto).unwrap()["person/friend"], Value::Ref(karl));
+        assert_eq!(None, db.entity(otto).unwrap().get("person/name"));
+    }
+
+    #[test]
+    fn cas() {
+        let mut db = db();
+        let karl = tempid();
+        let karl = db.transact(&[(Assert, karl.clone(), "person/name", "Karl")]).unwrap().tempid_mappings[&karl];
+
+        let mut tx = TxBuilder::new();
+        tx.cas(karl, "person/age", None, 30);
+        db.transact(tx).unwrap();
+
+        let mut tx = TxBuilder::new();
+        tx.cas(karl, "person/age", Some(Value::Int(30)), 31);
+        db.transact(tx).unwrap();
+        assert_eq!(db.entity(karl).unwrap()["person/age"], Value::Int(31));
+
+        let mut tx = TxBuilder::new();
+        tx.cas(karl, "person/age", Some(Value::Int(30)), 32);
+        match db.transact(tx).unwrap_err() {
+            Error::TransactionError(TransactionError::CasFailed(_, e, _)) => assert_eq!(karl.0, e),
+            _ => panic!("")
+        }
+        assert_eq!(db.entity(karl).unwrap()["person/age"], Value::Int(31));
+    }
+
+    #[test]
+    fn conflicting_operations() {
+        let karl = EntityId(1 << 49);
+
+        let mut tx = TxBuilder::new();
+        tx.add(karl, "person/name", "Karl").retract(karl, "person/name", "Karl");
+        assert!(tx.build().is_err());
+
+        let mut tx = TxBuilder::new();
+        tx.cas(karl, "person/age", None, 30).add(karl, "person/age", 31);
+        assert!(tx.build().is_err());
+
+        let mut tx = TxBuilder::new();
+        tx.add(karl, "person/name", "Karl").retract(karl, "person/name", "Carl");
+        assert_eq!(2, tx.build().unwrap().len());
+    }
+}
@@ -0,0 +1,184 @@
+use super::{Attribute, AttributeName, Database, Db, Entity, Error, FromValue, TransactionError};
+
+use std::fmt;
+use std::marker::PhantomData;
+
+/// An attribute resolved once by `Db::attr`, with the Rust type `T` of
+/// its values.
+///
+/// ```ignore
+/// let text: Attr<String> = db.attr("diary.entry/text")?;
+/// let date: Attr<DateTime<Utc>> = db.attr("diary.entry/date")?;
+///
+/// let mut tx = TxBuilder::new();
+/// tx.entity(entry).set_typed(&text, "Hello".to_string()).set_typed(&date, Utc::now());
+/// db.transact(tx)?;
+///
+/// let text: Option<String> = db.entity(eid)?.get_typed(&text);
+/// let entries = db.datoms(Index::Aevt.a(&date))?;
+/// ```
+///
+/// The type isn't part of the schema, so it's up to the caller to pick
+/// the type of the values asserted for the attribute.
+pub struct Attr<T> {
+    attribute: Attribute,
+    name: AttributeName,
+    value_type: PhantomData<fn() -> T>,
+}
+
+impl<T> Attr<T> {
+    pub fn attribute(&self) -> Attribute {
+        self.attribute
+    }
+
+    /// The ident the attribute was resolved by
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub(crate) fn resolve(db: &dyn Database, attribute_name: &str) -> Result<Self, Error> {
+        let attribute = db.attribute(attribute_name)
+            .ok_or_else(|| TransactionError::UnknownAttribute(attribute_name.to_string()))?;
+
+        Ok(Attr { attribute, name: attribute_name.to_string(), value_type: PhantomData })
+    }
+}
+
+// Derived impls would require `T: Clone` and `T: Debug`
+
+impl<T> Clone for Attr<T> {
+    fn clone(&self) -> Self {
+        Attr { attribute: self.attribute, name: self.name.clone(), value_type: PhantomData }
+    }
+}
+
+impl<T> fmt::Debug for Attr<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.debug_struct("Attr")
+            .field("attribute", &self.attribute)
+            .field("name", &self.name)
+            .finish()
+    }
+}
+
+impl<T> PartialEq for Attr<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.attribute == other.attribute
+    }
+}
+
+impl<'a, T> From<&'a Attr<T>> for Attribute {
+    fn from(attr: &'a Attr<T>) -> Attribute {
+        attr.attribute
+    }
+}
+
+impl Db {
+    /// Resolves `attribute_name` to a typed attribute handle, failing
+    /// with `TransactionError::UnknownAttribute` if there's no such
+    /// attribute
+    pub fn attr<T>(&self, attribute_name: &str) -> Result<Attr<T>, Error> {
+        self.refresh_schema()?;
+        Attr::resolve(self, attribute_name)
+    }
+}
+
+impl<'a> Entity<'a> {
+    /// The value of `attr` converted to `T`. `None` if the entity has no
+    /// value or the value has another type.
+    pub fn get_typed<T: FromValue>(&self, attr: &Attr<T>) -> Option<T> {
+        self.values.get(&attr.attribute)
+            .and_then(|values| values.first())
+            .and_then(T::from_value)
+    }
+
+    /// All values of `attr` which have the type `T`
+    pub fn get_many_typed<T: FromValue>(&self, attr: &Attr<T>) -> Vec<T> {
+        self.values.get(&attr.attribute)
+            .map(|values| values.iter().filter_map(T::from_value).collect())
+            .unwrap_or_default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use {tempid, Assert, EntityId, Index, TxBuilder, Value};
+
+    use chrono::{DateTime, Utc};
+
+    #[test]
+    fn typed_values() {
+        let mut db = Db::new().unwrap();
+        db.transact(&[(Assert, tempid(), "db/ident", "diary.entry/text"),
+                      (Assert, tempid(), "db/ident", "diary.entry/date"),
+                      (Assert, tempid(), "db/ident", "diary.entry/tags")]).unwrap();
+        db.transact(&[(Assert, db.attribute("diary.entry/tags").unwrap().0, "db.cardinality/many", true)]).unwrap();
+
+        let text: Attr<String> = db.attr("diary.entry/text").unwrap();
+        let date: Attr<DateTime<Utc>> = db.attr("diary.entry/date").unwrap();
+        let tags: Attr<String> = db.attr("diary.entry/tags").unwrap();
+        assert!(db.attr::<i64>("diary.entry/unknown").is_err());
+
+        let now = Utc::now();
+        let entry = tempid();
+        let mut tx = TxBuilder::new();
+        tx.entity(entry.clone())
+            .set_typed(&text, "Hello".to_string())
+            .set_typed(&date, now)
+            .set_typed(&tags, "greeting".to_string())
+            .set_typed(&tags, "short".to_string());
+        let entry: EntityId = db.transact(tx).unwrap().tempid_mappings[&entry];
+
+        let entity = db.entity(entry).unwrap();
+        assert_eq!(Some("Hello".to_string()), entity.get_typed(&text));
+        assert_eq!(Some(now), entity.get_typed(&date));
+        assert_eq!(vec!["greeting".to_string(), "short".to_string()], entity.get_many_typed(&tags));
+
+        // Values of another type aren't converted
+        let wrong: Attr<i64> = db.attr("diary.entry/text").unwrap();
+        assert_eq!(None, entity.get_typed(&wrong));
+
+        let datoms = db.datoms(Index::Aevt.a(&text).v(Value::from("Hello"))).unwrap();
+        assert_eq!(entry, datoms[0].entity);
+    }
+
+    #[test]
+    fn survives_rename() {
+        let mut db = Db::new().unwrap();
+        let name = tempid();
+        let attribute = db.transact(&[(Assert, name.clone(), "db/ident", "person/name")]).unwrap().tempid_mappings[&name];
+        let name: Attr<String> = db.attr("person/name").unwrap();
+
+        db.transact(&[(Assert, attribute, "db/ident", "person/full_name")]).unwrap();
+        let karl = tempid();
+        let mut tx = TxBuilder::new();
+        tx.add_typed(karl.clone(), &name, "Karl".to_string());
+        let karl = db.transact(tx).unwrap().tempid_mappings[&karl];
+
+        assert_eq!(Some("Karl".to_string()), db.entity(karl).unwrap().get_typed(&name));
+        assert_eq!(db.entity(karl).unwrap()["person/full_name"], Value::from("Karl"));
+    }
+
+    #[test]
+    fn survives_reused_ident() {
+        let mut db = Db::new().unwrap();
+        let name = tempid();
+        let attribute = db.transact(&[(Assert, name.clone(), "db/ident", "person/name")]).unwrap().tempid_mappings[&name];
+        let name: Attr<String> = db.attr("person/name").unwrap();
+
+        db.transact(&[(Assert, attribute, "db/ident", "person/full_name")]).unwrap();
+        db.transact(&[(Assert, tempid(), "db/ident", "person/name")]).unwrap();
+        assert_ne!(db.attribute("person/name"), Some(name.attribute()));
+
+        let karl = tempid();
+        let mut tx = TxBuilder::new();
+        tx.add_typed(karl.clone(), &name, "Karl".to_string());
+        let karl = db.transact(tx).unwrap().tempid_mappings[&karl];
+
+        let entity = db.entity(karl).unwrap();
+        assert_eq!(Some("Karl".to_string()), entity.get_typed(&name));
+        assert_eq!(entity["person/full_name"], Value::from("Karl"));
+        assert!(entity.get("person/name").is_none());
+    }
+}
@@ -1,4 +1,4 @@
-use super::{Db, EntityId, Entity};
+use super::{Database, EntityId, Entity};
 use chrono;
 
 #[derive(Debug, PartialEq, Eq, Clone, PartialOrd, Ord, Hash, Serialize, Deserialize, From)]
@@ -7,7 +7,8 @@ pub enum Value {
     Str(String),
     Int(i64),
     Ref(EntityId),
-    DateTime(chrono::DateTime<chrono::Utc>)
+    DateTime(chrono::DateTime<chrono::Utc>),
+    Tuple(Vec<Value>),
 }
 
 impl Value {
@@ -35,6 +36,14 @@ impl Value {
         }
     }
 
+    pub fn as_ref_id(&self) -> Option<EntityId> {
+        if let Value::Ref(eid) = self {
+            Some(*eid)
+        } else {
+            None
+        }
+    }
+
     pub fn as_datetime(&self) -> Option<chrono::DateTime<chrono::Utc>> {
         if let Value::DateTime(i) = self {
             Some(*i)
@@ -43,7 +52,15 @@ impl Value {
         }
     }
 
-    pub fn follow_ref<'a>(&self, db: &'a Db) -> Option<Entity<'a>> {
+    pub fn as_tuple(&self) -> Option<&[Value]> {
+        if let Value::Tuple(ref values) = self {
+            Some(&values[..])
+        } else {
+            None
+        }
+    }
+
+    pub fn follow_ref<'a>(&self, db: &'a dyn Database) -> Option<Entity<'a>> {
         if let Value::Ref(eid) = self {
             Some(db.entity(*eid).unwrap()) // TODO
         } else {
@@ -0,0 +1,64 @@
+use std::env;
+use std::fs;
+use std::io::Write;
+use std::path::PathBuf;
+use std::process::{Command, Output, Stdio};
+
+fn temp_dir(name: &str) -> PathBuf {
+    let dir = env::temp_dir().join(format!("hellschreiber-cli-{}-{}", std::process::id(), name));
+    let _ = fs::remove_dir_all(&dir);
+    fs::create_dir_all(&dir).unwrap();
+    dir
+}
+
+fn hellschreiber(args: &[&str], stdin: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_hellschreiber"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(stdin.as_bytes()).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+#[test]
+fn schema_and_export() {
+    let dir = temp_dir("schema");
+    let db = dir.join("diary.sqlite");
+    let db = db.to_str().unwrap();
+
+    // Importing an empty log creates the database
+    assert!(hellschreiber(&[db, "import"], "").status.success());
+
+    let schema = dir.join("schema.edn");
+    fs::write(&schema, "[{:db/id #db/id[:db.part/db] :db/ident :diary.entry/text}]").unwrap();
+    assert!(hellschreiber(&[db, "transact", schema.to_str().unwrap()], "").status.success());
+
+    let output = hellschreiber(&[db, "schema"], "");
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert!(stdout.lines().any(|line| line.contains("diary.entry/text")));
+
+    let output = hellschreiber(&[db, "export"], "");
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    assert_eq!(1, stdout.lines().count());
+    assert!(stdout.contains("diary.entry/text"));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn missing_database() {
+    let dir = temp_dir("missing");
+    let db = dir.join("typo.sqlite");
+
+    let output = hellschreiber(&[db.to_str().unwrap(), "schema"], "");
+    assert!(!output.status.success());
+    assert!(String::from_utf8(output.stderr).unwrap().contains("No database at"));
+    assert!(!db.exists());
+
+    fs::remove_dir_all(&dir).unwrap();
+}